@@ -1,14 +1,11 @@
 use zainpay::engine::Engine;
-use zainpay::enviroment::Environment;
 use zainpay::models::model::ZainboxInfo;
 use zainpay::zainbox::ZainboxService;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let merchant_key ="eyJ0eXAiOiJKV1QiLCJhbGciOiJIUzI1NiJ9";
-
-    // Initialize the Zainpay client with your API key and environment
-    let engine = Engine::new(Environment::Sandbox, merchant_key);
+    // Initialize the Zainpay client from ZAINPAY_MERCHANT_KEY and ZAINPAY_ENV
+    let engine = Engine::from_env()?;
 
     // Create an instance of the ZainboxService
     let zainbox_service = ZainboxService::new(engine);
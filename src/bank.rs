@@ -1,5 +1,7 @@
 use crate::engine::Engine;
+use crate::models::model::FundTransferRequest;
 use crate::response::Response;
+use crate::secret::Secret;
 use serde_json::{json, Value};
 use std::error::Error;
 
@@ -21,8 +23,7 @@ impl BankService {
      * @link https://zainpay.ng/developers/api-endpoints?section=get-bank-list
      */
     pub async fn get_bank_list(&self) -> Result<Response, Box<dyn Error>> {
-        let http_response = self.engine.get("bank/list").await?;
-        Ok(Response::new(http_response).await)
+        self.engine.endpoint("bank/list").get("bank/list").await
     }
 
     /**
@@ -39,14 +40,13 @@ impl BankService {
         bank_code: String,
         account_number: String,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("bank/name-enquiry")
             .get(&format!(
                 "bank/name-enquiry?bankCode={}&accountNumber={}",
                 bank_code, account_number
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     /**
@@ -86,64 +86,59 @@ impl BankService {
         narration: String,
         callback_url: Option<String>,
     ) -> Result<Response, Box<dyn Error>> {
-        let mut payload = json!({
-            "destinationAccountNumber": destination_account_number,
-            "destinationBankCode": destination_bank_code,
-            "amount": amount,
-            "sourceAccountNumber": source_account_number,
-            "sourceBankCode": source_bank_code,
-            "zainboxCode": zainbox_code,
-            "txnRef": txn_ref,
-            "narration": narration,
-        });
-
-        if let Some(callback_url) = callback_url {
-            payload["callbackUrl"] = Value::from(callback_url);
-        }
+        let payload = FundTransferRequest {
+            destination_account_number: Secret::new(destination_account_number),
+            destination_bank_code,
+            amount,
+            source_account_number: Secret::new(source_account_number),
+            source_bank_code,
+            zainbox_code,
+            txn_ref,
+            narration,
+            callback_url,
+        };
 
-        let http_response = self.engine.post("bank/transfer", &payload).await?;
-        Ok(Response::new(http_response).await)
+        self.engine
+            .endpoint("bank/transfer")
+            .post("bank/transfer", &payload)
+            .await
     }
 
     pub async fn verify_transfer(&self, txn_ref: String) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("virtual-account/wallet/transaction/verify/{}")
             .get(&format!(
                 "virtual-account/wallet/transaction/verify/{}",
                 txn_ref
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     pub async fn verify_deposit(&self, txn_ref: String) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("virtual-account/wallet/transaction/deposit/verify/{}")
             .get(&format!(
                 "virtual-account/wallet/transaction/deposit/verify/{}",
                 txn_ref
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     pub async fn verify_deposit_v2(&self, txn_ref: String) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("virtual-account/wallet/transaction/deposit/verify/v2/{}")
             .get(&format!(
                 "virtual-account/wallet/transaction/deposit/verify/v2/{}",
                 txn_ref
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     pub async fn repush_deposit_event(&self, txn_ref: String) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/repush/deposit/{}")
             .get(&format!("zainbox/repush/deposit/{}", txn_ref))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     pub async fn reconcile_bank_deposit(
@@ -163,13 +158,12 @@ impl BankService {
             payload["sessionId"] = Value::from(session_id);
         }
 
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("virtual-account/wallet/transaction/reconcile/bank-deposit")
             .patch(
                 "virtual-account/wallet/transaction/reconcile/bank-deposit",
                 &payload,
             )
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 }
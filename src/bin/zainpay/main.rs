@@ -0,0 +1,568 @@
+//! `zainpay` command-line client, built with the `cli` feature.
+//!
+//! Credentials come from the `ZAINPAY_*` environment variables, or from a profile of a TOML
+//! config file when `--profile` is given:
+//!
+//! ```toml
+//! [profiles.ops]
+//! merchant_key = "eyJ0eXAi..."
+//! environment = "production"
+//! ```
+
+mod output;
+
+use clap::{Args, Parser, Subcommand};
+use output::Format;
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use zainpay::bank::BankService;
+use zainpay::card::CardService;
+use zainpay::config::EngineConfig;
+use zainpay::engine::Engine;
+use zainpay::models::model::SettlementAccount;
+use zainpay::response::Response;
+use zainpay::settlement::SettlementService;
+use zainpay::virtual_account;
+use zainpay::zainbox::ZainboxService;
+
+#[derive(Debug, Parser)]
+#[command(name = "zainpay", version, about = "Zainpay API from the command line")]
+struct Cli {
+    /// Profile of the config file to read credentials from, instead of the environment.
+    #[arg(long, global = true, env = "ZAINPAY_PROFILE")]
+    profile: Option<String>,
+
+    /// Config file holding the profiles. Defaults to ~/.config/zainpay/config.toml.
+    #[arg(long, global = true, env = "ZAINPAY_CONFIG")]
+    config: Option<PathBuf>,
+
+    #[arg(long, short, global = true, value_enum, default_value = "table")]
+    output: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Zainboxes
+    #[command(subcommand)]
+    Zainbox(ZainboxCommand),
+    /// Virtual accounts
+    #[command(subcommand)]
+    Va(VaCommand),
+    /// Banks and transfers
+    #[command(subcommand)]
+    Bank(BankCommand),
+    /// Card payments
+    #[command(subcommand)]
+    Card(CardCommand),
+    /// Settlements
+    #[command(subcommand)]
+    Settlement(SettlementCommand),
+}
+
+#[derive(Debug, Subcommand)]
+enum ZainboxCommand {
+    List {
+        /// Only active (true) or inactive (false) zainboxes.
+        #[arg(long)]
+        status: Option<bool>,
+    },
+    Create {
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        email_notification: String,
+        #[arg(long)]
+        callback_url: String,
+        #[arg(long, value_delimiter = ',')]
+        tags: Option<Vec<String>>,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        code_name_prefix: Option<String>,
+        #[arg(long)]
+        allow_auto_internal_transfer: Option<bool>,
+    },
+    Update {
+        zainbox_code: String,
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        email_notification: Option<String>,
+        #[arg(long)]
+        callback_url: Option<String>,
+        #[arg(long, value_delimiter = ',')]
+        tags: Option<Vec<String>>,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        allow_auto_internal_transfer: Option<bool>,
+        #[arg(long)]
+        status: Option<bool>,
+    },
+    Profile {
+        zainbox_code: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum VaCommand {
+    Create {
+        #[arg(long)]
+        bank_type: String,
+        #[arg(long)]
+        bvn: String,
+        #[arg(long)]
+        first_name: String,
+        #[arg(long)]
+        last_name: String,
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        mobile: String,
+        #[arg(long)]
+        dob: String,
+        #[arg(long)]
+        gender: String,
+        #[arg(long)]
+        address: String,
+        #[arg(long)]
+        title: String,
+        #[arg(long)]
+        state: String,
+        #[arg(long)]
+        zainbox_code: String,
+    },
+    Balance {
+        account_number: String,
+    },
+    /// Activate or deactivate a virtual account.
+    Status {
+        account_number: String,
+        #[arg(long)]
+        zainbox_code: String,
+        #[arg(long)]
+        active: bool,
+    },
+    History {
+        account_number: String,
+        #[command(flatten)]
+        period: Period,
+        #[arg(long)]
+        txn_type: Option<String>,
+        #[arg(long)]
+        payment_channel: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum BankCommand {
+    List,
+    /// Look up the name on a bank account.
+    Enquiry {
+        #[arg(long)]
+        bank_code: String,
+        #[arg(long)]
+        account_number: String,
+    },
+    Transfer {
+        #[arg(long)]
+        to_account: String,
+        #[arg(long)]
+        to_bank: String,
+        /// Amount in kobo.
+        #[arg(long)]
+        amount: String,
+        #[arg(long)]
+        from_account: String,
+        #[arg(long)]
+        from_bank: String,
+        #[arg(long)]
+        zainbox_code: String,
+        #[arg(long)]
+        txn_ref: String,
+        #[arg(long)]
+        narration: String,
+        #[arg(long)]
+        callback_url: Option<String>,
+    },
+    /// Verify a transfer, or a deposit with --deposit.
+    Verify {
+        txn_ref: String,
+        #[arg(long)]
+        deposit: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CardCommand {
+    /// Initialize a card payment and print its checkout URL.
+    Init {
+        /// Amount in kobo.
+        #[arg(long)]
+        amount: String,
+        #[arg(long)]
+        txn_ref: String,
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        mobile: String,
+        #[arg(long)]
+        zainbox_code: String,
+        #[arg(long)]
+        callback_url: String,
+    },
+    Verify {
+        txn_ref: String,
+        /// Use the v2 verification endpoint.
+        #[arg(long)]
+        v2: bool,
+    },
+    Reconcile {
+        txn_ref: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum SettlementCommand {
+    Get {
+        zainbox_code: String,
+    },
+    /// Create or replace the settlement of a zainbox.
+    Set {
+        zainbox_code: String,
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        schedule_type: String,
+        #[arg(long)]
+        schedule_period: String,
+        /// Settlement account as ACCOUNT_NUMBER:BANK_CODE:PERCENTAGE, repeatable.
+        #[arg(long = "account", required = true, value_parser = parse_settlement_account)]
+        accounts: Vec<(String, String, f64)>,
+        #[arg(long)]
+        inactive: bool,
+    },
+    History {
+        zainbox_code: String,
+        #[command(flatten)]
+        period: Period,
+        #[arg(long)]
+        status: Option<String>,
+    },
+}
+
+#[derive(Debug, Args)]
+struct Period {
+    #[arg(long)]
+    count: Option<u32>,
+    /// Start date, YYYY-MM-DD.
+    #[arg(long)]
+    from: Option<String>,
+    /// End date, YYYY-MM-DD.
+    #[arg(long)]
+    to: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let outcome = async {
+        let engine = engine(&cli)?;
+        let response = run(cli.command, engine).await?;
+        output::print(&response, cli.output)
+    };
+
+    match outcome.await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn engine(cli: &Cli) -> Result<Engine, Box<dyn Error>> {
+    let config = match &cli.profile {
+        Some(profile) => {
+            let path = match &cli.config {
+                Some(path) => path.clone(),
+                None => PathBuf::from(std::env::var("HOME")?)
+                    .join(".config")
+                    .join("zainpay")
+                    .join("config.toml"),
+            };
+            EngineConfig::from_toml_file(path, Some(&format!("profiles.{}", profile)))?
+        }
+        None => EngineConfig::from_env()?,
+    };
+    Ok(config.build_engine()?)
+}
+
+async fn run(command: Command, engine: Engine) -> Result<Response, Box<dyn Error>> {
+    match command {
+        Command::Zainbox(command) => {
+            let service = ZainboxService::new(engine);
+            match command {
+                ZainboxCommand::List { status } => service.list(status).await,
+                ZainboxCommand::Create {
+                    name,
+                    email_notification,
+                    callback_url,
+                    tags,
+                    description,
+                    code_name_prefix,
+                    allow_auto_internal_transfer,
+                } => {
+                    service
+                        .create(
+                            name,
+                            email_notification,
+                            tags,
+                            callback_url,
+                            description,
+                            code_name_prefix,
+                            allow_auto_internal_transfer,
+                        )
+                        .await
+                }
+                ZainboxCommand::Update {
+                    zainbox_code,
+                    name,
+                    email_notification,
+                    callback_url,
+                    tags,
+                    description,
+                    allow_auto_internal_transfer,
+                    status,
+                } => {
+                    service
+                        .update(
+                            name,
+                            email_notification,
+                            tags,
+                            callback_url,
+                            description,
+                            allow_auto_internal_transfer,
+                            zainbox_code,
+                            status,
+                        )
+                        .await
+                }
+                ZainboxCommand::Profile { zainbox_code } => {
+                    service.get_zainbox_profile(zainbox_code).await
+                }
+            }
+        }
+        Command::Va(command) => {
+            let service = virtual_account::CardService::new(engine);
+            match command {
+                VaCommand::Create {
+                    bank_type,
+                    bvn,
+                    first_name,
+                    last_name,
+                    email,
+                    mobile,
+                    dob,
+                    gender,
+                    address,
+                    title,
+                    state,
+                    zainbox_code,
+                } => {
+                    service
+                        .create(
+                            bank_type,
+                            bvn,
+                            first_name,
+                            last_name,
+                            email,
+                            mobile,
+                            dob,
+                            gender,
+                            address,
+                            title,
+                            state,
+                            zainbox_code,
+                        )
+                        .await
+                }
+                VaCommand::Balance { account_number } => {
+                    service.get_virtual_account_balance(account_number).await
+                }
+                VaCommand::Status {
+                    account_number,
+                    zainbox_code,
+                    active,
+                } => {
+                    service
+                        .change_virtual_account_status(zainbox_code, account_number, active)
+                        .await
+                }
+                VaCommand::History {
+                    account_number,
+                    period,
+                    txn_type,
+                    payment_channel,
+                } => {
+                    service
+                        .get_virtual_account_txn_history(
+                            account_number,
+                            period.count,
+                            period.from,
+                            period.to,
+                            txn_type,
+                            payment_channel,
+                        )
+                        .await
+                }
+            }
+        }
+        Command::Bank(command) => {
+            let service = BankService::new(engine);
+            match command {
+                BankCommand::List => service.get_bank_list().await,
+                BankCommand::Enquiry {
+                    bank_code,
+                    account_number,
+                } => {
+                    service
+                        .make_account_name_enquiry(bank_code, account_number)
+                        .await
+                }
+                BankCommand::Transfer {
+                    to_account,
+                    to_bank,
+                    amount,
+                    from_account,
+                    from_bank,
+                    zainbox_code,
+                    txn_ref,
+                    narration,
+                    callback_url,
+                } => {
+                    service
+                        .make_fund_transfer(
+                            to_account,
+                            to_bank,
+                            amount,
+                            from_account,
+                            from_bank,
+                            zainbox_code,
+                            txn_ref,
+                            narration,
+                            callback_url,
+                        )
+                        .await
+                }
+                BankCommand::Verify { txn_ref, deposit } => {
+                    if deposit {
+                        service.verify_deposit(txn_ref).await
+                    } else {
+                        service.verify_transfer(txn_ref).await
+                    }
+                }
+            }
+        }
+        Command::Card(command) => {
+            let service = CardService::new(engine);
+            match command {
+                CardCommand::Init {
+                    amount,
+                    txn_ref,
+                    email,
+                    mobile,
+                    zainbox_code,
+                    callback_url,
+                } => {
+                    service
+                        .initialize_new_payment(
+                            amount,
+                            txn_ref,
+                            email,
+                            mobile,
+                            zainbox_code,
+                            callback_url,
+                        )
+                        .await
+                }
+                CardCommand::Verify { txn_ref, v2 } => {
+                    if v2 {
+                        service.verify_card_payment_v2(txn_ref).await
+                    } else {
+                        service.verify_card_payment(txn_ref).await
+                    }
+                }
+                CardCommand::Reconcile { txn_ref } => service.reconcile_card_payment(txn_ref).await,
+            }
+        }
+        Command::Settlement(command) => {
+            let service = SettlementService::new(engine);
+            match command {
+                SettlementCommand::Get { zainbox_code } => {
+                    service.get_settlement_info_for_zainbox(zainbox_code).await
+                }
+                SettlementCommand::Set {
+                    zainbox_code,
+                    name,
+                    schedule_type,
+                    schedule_period,
+                    accounts,
+                    inactive,
+                } => {
+                    let accounts = accounts
+                        .into_iter()
+                        .map(|(account_number, bank_code, percentage)| {
+                            SettlementAccount::new(account_number, bank_code, percentage)
+                        })
+                        .collect();
+                    service
+                        .create_or_update_zainbox_settlemet(
+                            name,
+                            zainbox_code,
+                            schedule_type,
+                            schedule_period,
+                            accounts,
+                            !inactive,
+                        )
+                        .await
+                }
+                SettlementCommand::History {
+                    zainbox_code,
+                    period,
+                    status,
+                } => {
+                    service
+                        .get_settlement_payment_history_for_zainbox(
+                            zainbox_code,
+                            period.count,
+                            period.from,
+                            period.to,
+                            status,
+                        )
+                        .await
+                }
+            }
+        }
+    }
+}
+
+fn parse_settlement_account(value: &str) -> Result<(String, String, f64), String> {
+    let mut parts = value.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(account_number), Some(bank_code), Some(percentage)) => Ok((
+            account_number.to_string(),
+            bank_code.to_string(),
+            percentage
+                .parse()
+                .map_err(|_| format!("invalid percentage `{}`", percentage))?,
+        )),
+        _ => Err("expected ACCOUNT_NUMBER:BANK_CODE:PERCENTAGE".to_string()),
+    }
+}
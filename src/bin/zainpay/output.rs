@@ -0,0 +1,167 @@
+use clap::ValueEnum;
+use serde_json::Value;
+use std::error::Error;
+use std::io::{self, Write};
+use zainpay::response::Response;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+/// Print a response to stdout, or its code and description to stderr when it failed.
+/// Returns whether the request succeeded.
+pub fn print(response: &Response, format: Format) -> Result<bool, Box<dyn Error>> {
+    if response.has_failed() {
+        if format == Format::Json {
+            println!("{}", serde_json::to_string_pretty(&response.full_json())?);
+        }
+        eprintln!(
+            "error: HTTP {} code {}: {}",
+            response.get_status_code(),
+            response.get_code().unwrap_or("-"),
+            response.get_description().unwrap_or("no description")
+        );
+        return Ok(false);
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match format {
+        Format::Json => writeln!(
+            out,
+            "{}",
+            serde_json::to_string_pretty(&response.full_json())?
+        )?,
+        Format::Table | Format::Csv => match response.get_raw_data() {
+            Some(data) => {
+                let (columns, rows) = tabulate(data);
+                if format == Format::Csv {
+                    let mut writer = csv::Writer::from_writer(out);
+                    writer.write_record(&columns)?;
+                    for row in rows {
+                        writer.write_record(row)?;
+                    }
+                    writer.flush()?;
+                } else {
+                    write_table(&mut out, &columns, &rows)?;
+                }
+            }
+            None => writeln!(
+                out,
+                "{}",
+                response
+                    .get_description()
+                    .or(response.get_status())
+                    .unwrap_or("ok")
+            )?,
+        },
+    }
+
+    Ok(true)
+}
+
+/// Columns and rows of `data`: one row per element of an array, or a single row for an object.
+/// Nested values are kept as JSON.
+fn tabulate(data: &Value) -> (Vec<String>, Vec<Vec<String>>) {
+    let records: Vec<&Value> = match data {
+        Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+
+    let mut columns: Vec<String> = Vec::new();
+    for record in &records {
+        match record {
+            Value::Object(fields) => {
+                for key in fields.keys() {
+                    if !columns.contains(key) {
+                        columns.push(key.clone());
+                    }
+                }
+            }
+            _ if !columns.iter().any(|column| column == "value") => {
+                columns.push("value".to_string())
+            }
+            _ => {}
+        }
+    }
+
+    let rows = records
+        .iter()
+        .map(|record| {
+            columns
+                .iter()
+                .map(|column| match record {
+                    Value::Object(fields) => fields.get(column).map(cell).unwrap_or_default(),
+                    scalar if column == "value" => cell(scalar),
+                    _ => String::new(),
+                })
+                .collect()
+        })
+        .collect();
+
+    (columns, rows)
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Write rows as aligned columns. A single record is written vertically, one field per line.
+fn write_table(out: &mut impl Write, columns: &[String], rows: &[Vec<String>]) -> io::Result<()> {
+    if rows.len() == 1 && columns.len() > 1 {
+        let width = columns
+            .iter()
+            .map(|column| column.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (column, value) in columns.iter().zip(&rows[0]) {
+            writeln!(out, "{:width$}  {}", column, value, width = width)?;
+        }
+        return Ok(());
+    }
+
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            rows.iter()
+                .map(|row| row[index].chars().count())
+                .chain(std::iter::once(column.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let line = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    writeln!(out, "{}", line(columns))?;
+    writeln!(
+        out,
+        "{}",
+        widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>()
+            .join("  ")
+    )?;
+    for row in rows {
+        writeln!(out, "{}", line(row))?;
+    }
+    Ok(())
+}
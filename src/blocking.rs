@@ -0,0 +1,493 @@
+//! Synchronous versions of the services, enabled by the `blocking` feature.
+//!
+//! Each service wraps its async counterpart and drives it on a current-thread tokio runtime
+//! owned by the blocking [`Engine`], so requests, responses and models are the same as in the
+//! async API. Do not call these from inside an async runtime: blocking on a runtime from within
+//! another one panics.
+//!
+//! `WebhookReceiver`, `WebhookSimulator` and the `Store` implementations do not go through the
+//! engine and have no blocking versions; drive them on a runtime of your own.
+
+use crate::enviroment::Environment;
+use crate::export::{ExportFormat, ExportOptions, ExportSummary, HistorySource};
+use crate::ledger::{ChartOfAccounts, LedgerError, LedgerStore, PostingReport};
+use crate::models::model::{FundTransferRequest, SettlementAccount, Transaction};
+use crate::payments::{CheckoutOutcome, TransferOutcome};
+use crate::provision::{Applied, Manifest, Plan};
+use crate::reconcile::{LocalRecord, ReconciliationReport, Resolution, ResolutionAction};
+use crate::recovery::{RecoveryOptions, RecoveryReport};
+use crate::response::Response;
+use crate::statement::{Statement, StatementBuilder};
+use crate::store::{CardSession, Store, TransferIntent};
+use crate::zainbox::{EnsureOutcome, ZainboxSpec};
+use chrono::NaiveDate;
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::{self, Write};
+use std::sync::Arc;
+use tokio::runtime::{Builder, Runtime};
+
+/// An async [`crate::engine::Engine`] paired with the runtime its requests run on.
+#[derive(Debug, Clone)]
+pub struct Engine {
+    inner: crate::engine::Engine,
+    runtime: Arc<Runtime>,
+}
+
+impl Engine {
+    pub fn new(enviroment: Environment, merchant_key: &str) -> io::Result<Self> {
+        Self::from_async(crate::engine::Engine::new(enviroment, merchant_key))
+    }
+
+    /// Wrap an engine configured with `crate::engine::Engine::builder`.
+    pub fn from_async(engine: crate::engine::Engine) -> io::Result<Self> {
+        Ok(Self {
+            inner: engine,
+            runtime: Arc::new(Builder::new_current_thread().enable_all().build()?),
+        })
+    }
+
+    pub fn get(&self, path: &str) -> Result<Response, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.get(path))
+    }
+
+    pub fn post<T: serde::Serialize>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<Response, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.post(path, body))
+    }
+
+    pub fn patch<T: serde::Serialize>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<Response, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.patch(path, body))
+    }
+}
+
+/// Declares a blocking service whose methods block on the same-named async methods of `$inner`.
+macro_rules! blocking_service {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident => $inner:path {
+            $(fn $method:ident(&self $(, $arg:ident: $ty:ty)*) -> $ret:ty;)*
+        }
+    ) => {
+        $(#[$meta])*
+        pub struct $name {
+            inner: $inner,
+            runtime: Arc<Runtime>,
+        }
+
+        impl $name {
+            pub fn new(engine: Engine) -> Self {
+                Self {
+                    inner: <$inner>::new(engine.inner),
+                    runtime: engine.runtime,
+                }
+            }
+
+            $(
+                pub fn $method(&self $(, $arg: $ty)*) -> Result<$ret, Box<dyn Error>> {
+                    self.runtime.block_on(self.inner.$method($($arg),*))
+                }
+            )*
+        }
+    };
+}
+
+blocking_service! {
+    pub struct BankService => crate::bank::BankService {
+        fn get_bank_list(&self) -> Response;
+        fn make_account_name_enquiry(&self, bank_code: String, account_number: String) -> Response;
+        fn make_fund_transfer(
+            &self,
+            destination_account_number: String,
+            destination_bank_code: String,
+            amount: String,
+            source_account_number: String,
+            source_bank_code: String,
+            zainbox_code: String,
+            txn_ref: String,
+            narration: String,
+            callback_url: Option<String>
+        ) -> Response;
+        fn verify_transfer(&self, txn_ref: String) -> Response;
+        fn verify_deposit(&self, txn_ref: String) -> Response;
+        fn verify_deposit_v2(&self, txn_ref: String) -> Response;
+        fn repush_deposit_event(&self, txn_ref: String) -> Response;
+        fn reconcile_bank_deposit(
+            &self,
+            verification_type: String,
+            bank_type: String,
+            account_number: String,
+            session_id: Option<String>
+        ) -> Response;
+    }
+}
+
+blocking_service! {
+    pub struct CardService => crate::card::CardService {
+        fn initialize_new_payment(
+            &self,
+            amount: String,
+            txn_ref: String,
+            email_address: String,
+            mobile_number: String,
+            zainbox_code: String,
+            callback_url: String
+        ) -> Response;
+        fn verify_card_payment(&self, txn_ref: String) -> Response;
+        fn verify_card_payment_v2(&self, txn_ref: String) -> Response;
+        fn reconcile_card_payment(&self, txn_ref: String) -> Response;
+        fn get_zainbox_card_payment_txn_history(
+            &self,
+            zainbox_code: String,
+            count: Option<u32>,
+            date_from: Option<String>,
+            date_to: Option<String>,
+            email: Option<String>,
+            status: Option<String>,
+            txn_ref: Option<String>
+        ) -> Response;
+    }
+}
+
+blocking_service! {
+    /// Blocking version of `virtual_account::CardService`.
+    pub struct VirtualAccountService => crate::virtual_account::CardService {
+        fn create(
+            &self,
+            bank_type: String,
+            bvn: String,
+            first_name: String,
+            last_name: String,
+            email: String,
+            mobile: String,
+            dob: String,
+            gender: String,
+            address: String,
+            title: String,
+            state: String,
+            zainbox_code: String
+        ) -> Response;
+        fn get_virtual_account_balance(&self, account_number: String) -> Response;
+        fn get_all_virtual_accounts_balance_for_zainbox(&self, zainbox_code: String) -> Response;
+        fn change_virtual_account_status(
+            &self,
+            zainbox_code: String,
+            account_number: String,
+            status: bool
+        ) -> Response;
+        fn get_all_virtual_accounts_for_zainbox(&self, zainbox_code: String) -> Response;
+        fn get_virtual_account_txn_history(
+            &self,
+            account_number: String,
+            count: Option<u32>,
+            date_from: Option<String>,
+            date_to: Option<String>,
+            txn_type: Option<String>,
+            payment_channel: Option<String>
+        ) -> Response;
+    }
+}
+
+blocking_service! {
+    pub struct SettlementService => crate::settlement::SettlementService {
+        fn create_or_update_zainbox_settlemet(
+            &self,
+            name: String,
+            zainbox_code: String,
+            schedule_type: String,
+            schedule_period: String,
+            settlement_account_list: Vec<SettlementAccount>,
+            status: bool
+        ) -> Response;
+        fn get_settlement_info_for_zainbox(&self, zainbox_code: String) -> Response;
+        fn get_settlement_payment_history_for_zainbox(
+            &self,
+            zainbox_code: String,
+            count: Option<u32>,
+            date_from: Option<String>,
+            date_to: Option<String>,
+            status: Option<String>
+        ) -> Response;
+    }
+}
+
+blocking_service! {
+    pub struct ZainboxService => crate::zainbox::ZainboxService {
+        fn create(
+            &self,
+            name: String,
+            email_notification: String,
+            tags: Option<Vec<String>>,
+            callback_url: String,
+            description: Option<String>,
+            code_name_prefix: Option<String>,
+            allow_auto_internal_transfer: Option<bool>
+        ) -> Response;
+        fn list(&self, status: Option<bool>) -> Response;
+        fn update(
+            &self,
+            name: String,
+            email_notification: Option<String>,
+            tags: Option<Vec<String>>,
+            callback_url: Option<String>,
+            description: Option<String>,
+            allow_auto_internal_transfer: Option<bool>,
+            zainbox_code: String,
+            status: Option<bool>
+        ) -> Response;
+        fn get_zainbox_profile(&self, zainbox_code: String) -> Response;
+        fn get_total_payment_collected_by_zainbox(
+            &self,
+            zainbox_code: String,
+            date_from: Option<String>,
+            date_to: Option<String>
+        ) -> Response;
+        fn get_total_payment_collected_for_all_zainboxes(
+            &self,
+            date_from: Option<String>,
+            date_to: Option<String>
+        ) -> Response;
+        fn get_zainbox_txn_history(
+            &self,
+            zainbox_code: String,
+            count: Option<u32>,
+            date_from: Option<String>,
+            date_to: Option<String>,
+            txn_type: Option<String>,
+            payment_channel: Option<String>,
+            account_number: Option<String>
+        ) -> Response;
+        fn get_all_zainboxes_txn_history(
+            &self,
+            count: Option<u32>,
+            date_from: Option<String>,
+            date_to: Option<String>,
+            txn_type: Option<String>,
+            payment_channel: Option<String>,
+            account_number: Option<String>
+        ) -> Response;
+        fn ensure_zainbox(&self, spec: &ZainboxSpec) -> EnsureOutcome;
+    }
+}
+
+blocking_service! {
+    pub struct ReconcileService => crate::reconcile::ReconcileService {
+        fn fetch_zainbox_transactions(
+            &self,
+            zainbox_code: String,
+            count: Option<u32>,
+            date_from: Option<String>,
+            date_to: Option<String>
+        ) -> Vec<Transaction>;
+        fn reconcile_zainbox(
+            &self,
+            zainbox_code: String,
+            local: &[LocalRecord],
+            count: Option<u32>,
+            date_from: Option<String>,
+            date_to: Option<String>
+        ) -> ReconciliationReport;
+        fn resolve(
+            &self,
+            report: &ReconciliationReport,
+            action: &ResolutionAction
+        ) -> Vec<Resolution>;
+    }
+}
+
+blocking_service! {
+    pub struct RecoveryService => crate::recovery::RecoveryService {
+        fn recover_deposits(
+            &self,
+            zainbox_code: String,
+            processed: &HashSet<String>,
+            options: &RecoveryOptions
+        ) -> RecoveryReport;
+    }
+}
+
+blocking_service! {
+    pub struct ProvisionService => crate::provision::ProvisionService {
+        fn plan(&self, manifest: &Manifest) -> Plan;
+        fn apply(&self, plan: &Plan) -> Vec<Applied>;
+    }
+}
+
+/// Blocking version of `export::ExportService`.
+pub struct ExportService {
+    inner: crate::export::ExportService,
+    runtime: Arc<Runtime>,
+}
+
+impl ExportService {
+    pub fn new(engine: Engine) -> Self {
+        Self {
+            inner: crate::export::ExportService::new(engine.inner),
+            runtime: engine.runtime,
+        }
+    }
+
+    pub fn export<W: Write>(
+        &self,
+        source: &HistorySource,
+        options: &ExportOptions,
+        format: ExportFormat,
+        writer: W,
+    ) -> Result<ExportSummary, Box<dyn Error>> {
+        self.runtime
+            .block_on(self.inner.export(source, options, format, writer))
+    }
+}
+
+/// Builds statements with a `statement::StatementBuilder`, blocking on its `build`.
+pub struct StatementService {
+    engine: Engine,
+}
+
+impl StatementService {
+    pub fn new(engine: Engine) -> Self {
+        Self { engine }
+    }
+
+    pub fn build(&self, builder: StatementBuilder) -> Result<Statement, Box<dyn Error>> {
+        self.engine
+            .runtime
+            .block_on(builder.build(&self.engine.inner))
+    }
+}
+
+/// Blocking version of `ledger::LedgerService`.
+pub struct LedgerService {
+    inner: crate::ledger::LedgerService,
+    runtime: Arc<Runtime>,
+}
+
+impl LedgerService {
+    pub fn new(engine: Engine, chart: ChartOfAccounts) -> Self {
+        Self {
+            inner: crate::ledger::LedgerService::new(engine.inner, chart),
+            runtime: engine.runtime,
+        }
+    }
+
+    pub fn chart(&self) -> &ChartOfAccounts {
+        self.inner.chart()
+    }
+
+    pub fn post(
+        &self,
+        store: &dyn LedgerStore,
+        transactions: &[Transaction],
+    ) -> Result<PostingReport, LedgerError> {
+        self.runtime.block_on(self.inner.post(store, transactions))
+    }
+
+    pub fn post_history(
+        &self,
+        store: &dyn LedgerStore,
+        source: &HistorySource,
+        date_from: NaiveDate,
+        date_to: NaiveDate,
+    ) -> Result<PostingReport, Box<dyn Error>> {
+        self.runtime
+            .block_on(self.inner.post_history(store, source, date_from, date_to))
+    }
+}
+
+/// Blocking version of `payments::PaymentService`.
+pub struct PaymentService {
+    inner: crate::payments::PaymentService,
+    runtime: Arc<Runtime>,
+}
+
+impl PaymentService {
+    pub fn new(engine: Engine, store: Arc<dyn Store>) -> Self {
+        Self {
+            inner: crate::payments::PaymentService::new(engine.inner, store),
+            runtime: engine.runtime,
+        }
+    }
+
+    pub fn store(&self) -> &Arc<dyn Store> {
+        self.inner.store()
+    }
+
+    pub fn transfer(
+        &self,
+        idempotency_key: &str,
+        request: FundTransferRequest,
+    ) -> Result<TransferOutcome, Box<dyn Error>> {
+        self.runtime
+            .block_on(self.inner.transfer(idempotency_key, request))
+    }
+
+    pub fn verify_transfer(&self, txn_ref: &str) -> Result<TransferIntent, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.verify_transfer(txn_ref))
+    }
+
+    pub fn verify_unsettled_transfers(&self) -> Result<Vec<TransferIntent>, Box<dyn Error>> {
+        self.runtime
+            .block_on(self.inner.verify_unsettled_transfers())
+    }
+
+    pub fn start_card_payment(
+        &self,
+        session: CardSession,
+    ) -> Result<CheckoutOutcome, Box<dyn Error>> {
+        self.runtime
+            .block_on(self.inner.start_card_payment(session))
+    }
+
+    pub fn verify_card_payment(&self, txn_ref: &str) -> Result<CardSession, Box<dyn Error>> {
+        self.runtime
+            .block_on(self.inner.verify_card_payment(txn_ref))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::Secret;
+    use crate::store::{MemoryStore, TransferState};
+    use crate::transport::{HttpResponse, Method, MockTransport};
+
+    #[test]
+    fn payment_service_blocks_on_the_async_one() {
+        let transport = Arc::new(MockTransport::new().route(
+            Method::Post,
+            "bank/transfer",
+            HttpResponse::new(200, r#"{"code":"00","description":"queued"}"#),
+        ));
+        let engine = Engine::from_async(
+            crate::engine::Engine::builder(Environment::Sandbox, "key")
+                .transport(transport.clone())
+                .build(),
+        )
+        .unwrap();
+        let payments = PaymentService::new(engine, Arc::new(MemoryStore::new()));
+        let request = FundTransferRequest {
+            destination_account_number: Secret::from("0123456789"),
+            destination_bank_code: "000013".to_string(),
+            amount: "100000".to_string(),
+            source_account_number: Secret::from("7966884043"),
+            source_bank_code: "0017".to_string(),
+            zainbox_code: "ZB".to_string(),
+            txn_ref: "TRF-1".to_string(),
+            narration: "payout".to_string(),
+            callback_url: None,
+        };
+
+        let sent = payments.transfer("key-1", request.clone()).unwrap();
+        assert_eq!(sent.intent().state, TransferState::Submitted);
+        let again = payments.transfer("key-1", request).unwrap();
+        assert!(matches!(again, TransferOutcome::Duplicate(_)));
+        assert_eq!(transport.requests().len(), 1);
+    }
+}
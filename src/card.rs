@@ -1,7 +1,7 @@
 use crate::engine::Engine;
 use crate::response::Response;
 use crate::utils::filter::FilterUtil;
-use serde_json::{json, Value};
+use serde_json::json;
 use std::error::Error;
 
 pub struct CardService {
@@ -22,7 +22,7 @@ impl CardService {
         zainbox_code: String,
         callback_url: String,
     ) -> Result<Response, Box<dyn Error>> {
-        let mut payload = json!({
+        let payload = json!({
             "amount": amount,
             "txnRef": txn_ref,
             "emailAddress": email_address,
@@ -31,50 +31,46 @@ impl CardService {
             "callbackUrl": callback_url
         });
 
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/card/initialize/payment")
             .post("zainbox/card/initialize/payment", &payload)
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     pub async fn verify_card_payment(&self, txn_ref: String) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("virtual-account/wallet/deposit/verify/{}")
             .get(&format!(
                 "virtual-account/wallet/deposit/verify/{}",
                 txn_ref
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     pub async fn verify_card_payment_v2(
         &self,
         txn_ref: String,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("virtual-account/wallet/deposit/verify/v2/{}")
             .get(&format!(
                 "virtual-account/wallet/deposit/verify/v2/{}",
                 txn_ref
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     pub async fn reconcile_card_payment(
         &self,
         txn_ref: String,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("virtual-account/wallet/transaction/reconcile/card-payment")
             .get(&format!(
                 "virtual-account/wallet/transaction/reconcile/card-payment?txnRef={}",
                 txn_ref
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     pub async fn get_zainbox_card_payment_txn_history(
@@ -87,8 +83,8 @@ impl CardService {
         status: Option<String>,
         txn_ref: Option<String>,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/card/transactions/{}")
             .get(&format!(
                 "zainbox/card/transactions/{}?count={}&{}",
                 zainbox_code,
@@ -97,7 +93,6 @@ impl CardService {
                     date_from, date_to, email, status, txn_ref, None, None, None
                 )
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 }
@@ -0,0 +1,626 @@
+//! Record-and-replay transports for tests.
+//!
+//! [`RecordingTransport`] sends requests through a real transport and writes each request and
+//! response to a cassette file. [`ReplayTransport`] serves the responses of a cassette back
+//! without a network. Both scrub what they see with a [`Scrubber`]: the merchant key and the
+//! values of personal fields never reach the file.
+//!
+//! Requests are matched on method, path, query and body. The query is compared with its
+//! parameters sorted and empty ones dropped, and JSON bodies are compared as values, so key
+//! order and whitespace do not matter.
+
+use crate::secret::Secret;
+use crate::transport::{HttpRequest, HttpResponse, Transport, TransportError};
+use crate::utils::redact::REDACTED;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+/// Lowercased fragments of the JSON keys and query parameters holding personal data. Unlike the
+/// keys redacted from logs, balances are kept, so replayed responses still parse.
+const PII_KEYS: &[&str] = &[
+    "bvn",
+    "accountnumber",
+    "accountname",
+    "email",
+    "mobile",
+    "phone",
+    "dob",
+    "address",
+    "firstname",
+    "lastname",
+    "surname",
+    "sendername",
+    "beneficiaryname",
+];
+
+/// Response headers not worth keeping in a cassette.
+const DROPPED_HEADERS: &[&str] = &["set-cookie", "date", "authorization"];
+
+#[derive(Debug, Error)]
+pub enum CassetteError {
+    #[error("could not access cassette {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("could not parse cassette {path}: {source}")]
+    Parse {
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("no cassette interaction matches {method} {path}")]
+    NoMatch { method: String, path: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Body {
+    Json(Value),
+    Text(String),
+}
+
+impl Body {
+    fn parse(body: &str) -> Self {
+        serde_json::from_str(body)
+            .map(Body::Json)
+            .unwrap_or_else(|_| Body::Text(body.to_string()))
+    }
+
+    fn into_string(self) -> String {
+        match self {
+            Body::Json(value) => value.to_string(),
+            Body::Text(text) => text,
+        }
+    }
+}
+
+/// A request as written to a cassette: scrubbed, with its query normalised.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path and normalised query, without scheme and host.
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Body>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CassetteError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|source| CassetteError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        serde_json::from_str(&content).map_err(|source| CassetteError::Parse {
+            path: path.display().to_string(),
+            source,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CassetteError> {
+        let path = path.as_ref();
+        let io_error = |source| CassetteError::Io {
+            path: path.display().to_string(),
+            source,
+        };
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        let content =
+            serde_json::to_string_pretty(self).map_err(|source| CassetteError::Parse {
+                path: path.display().to_string(),
+                source,
+            })?;
+        std::fs::write(path, content + "\n").map_err(io_error)
+    }
+}
+
+/// Removes the merchant key and personal data from recorded traffic.
+///
+/// The merchant key is taken from the `Authorization` header of each request, so it does not
+/// need to be configured. Path segments of ten or more digits (account numbers, BVNs) are
+/// replaced too, which means requests for different accounts match the same interaction.
+#[derive(Debug, Clone)]
+pub struct Scrubber {
+    keys: Vec<String>,
+    secrets: Vec<Secret<String>>,
+}
+
+impl Default for Scrubber {
+    fn default() -> Self {
+        Self {
+            keys: PII_KEYS.iter().map(|key| key.to_string()).collect(),
+            secrets: Vec::new(),
+        }
+    }
+}
+
+impl Scrubber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also redact the values of keys containing `fragment`, compared case-insensitively.
+    pub fn key(mut self, fragment: &str) -> Self {
+        self.keys.push(fragment.to_ascii_lowercase());
+        self
+    }
+
+    /// Also replace every occurrence of `secret`.
+    pub fn secret(mut self, secret: &str) -> Self {
+        self.secrets.push(Secret::from(secret));
+        self
+    }
+
+    fn is_pii_key(&self, key: &str) -> bool {
+        let key = key.to_ascii_lowercase();
+        self.keys
+            .iter()
+            .any(|fragment| key.contains(fragment.as_str()))
+    }
+
+    fn scrub_text(&self, text: &str, secrets: &[&str]) -> String {
+        secrets
+            .iter()
+            .copied()
+            .chain(self.secrets.iter().map(|secret| secret.expose().as_str()))
+            .filter(|secret| !secret.is_empty())
+            .fold(text.to_string(), |text, secret| {
+                text.replace(secret, REDACTED)
+            })
+    }
+
+    fn scrub_value(&self, value: &Value, secrets: &[&str]) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(key, value)| {
+                        let value = if self.is_pii_key(key) {
+                            redact_scalars(value)
+                        } else {
+                            self.scrub_value(value, secrets)
+                        };
+                        (key.clone(), value)
+                    })
+                    .collect::<Map<String, Value>>(),
+            ),
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.scrub_value(item, secrets))
+                    .collect(),
+            ),
+            Value::String(text) => Value::String(self.scrub_text(text, secrets)),
+            other => other.clone(),
+        }
+    }
+
+    fn scrub_body(&self, body: &str, secrets: &[&str]) -> Body {
+        match Body::parse(body) {
+            Body::Json(value) => Body::Json(self.scrub_value(&value, secrets)),
+            Body::Text(text) => Body::Text(self.scrub_text(&text, secrets)),
+        }
+    }
+
+    /// The scrubbed, normalised form of `request`, used both to record and to match.
+    pub fn request(&self, request: &HttpRequest) -> RecordedRequest {
+        let secrets = [merchant_key(request)];
+
+        let (path, query) = request
+            .path()
+            .split_once('?')
+            .unwrap_or((request.path(), ""));
+        let path = path
+            .split('/')
+            .map(|segment| {
+                if segment.len() >= 10 && segment.bytes().all(|byte| byte.is_ascii_digit()) {
+                    REDACTED.to_string()
+                } else {
+                    self.scrub_text(segment, &secrets)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut parameters: Vec<(String, String)> =
+            serde_urlencoded::from_str(query).unwrap_or_default();
+        parameters.retain(|(_, value)| !value.is_empty());
+        for (name, value) in parameters.iter_mut() {
+            *value = if self.is_pii_key(name) {
+                REDACTED.to_string()
+            } else {
+                self.scrub_text(value, &secrets)
+            };
+        }
+        parameters.sort();
+        let query = serde_urlencoded::to_string(&parameters).unwrap_or_default();
+
+        RecordedRequest {
+            method: request.method.as_str().to_string(),
+            path: if query.is_empty() {
+                path
+            } else {
+                format!("{}?{}", path, query)
+            },
+            body: request
+                .body
+                .as_deref()
+                .filter(|body| !body.trim().is_empty())
+                .map(|body| self.scrub_body(body, &secrets)),
+        }
+    }
+
+    fn response(&self, request: &HttpRequest, response: &HttpResponse) -> RecordedResponse {
+        let key = merchant_key(request);
+        RecordedResponse {
+            status: response.status,
+            headers: response
+                .headers
+                .iter()
+                .filter(|(name, _)| !DROPPED_HEADERS.contains(&name.to_ascii_lowercase().as_str()))
+                .map(|(name, value)| (name.clone(), self.scrub_text(value, &[key])))
+                .collect(),
+            body: self.scrub_body(&response.body, &[key]),
+        }
+    }
+}
+
+/// Copy of `value` with every string, number and boolean in it redacted. Nulls are kept, so an
+/// absent value still reads as absent.
+fn redact_scalars(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| (key.clone(), redact_scalars(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact_scalars).collect()),
+        Value::Null => Value::Null,
+        _ => Value::from(REDACTED),
+    }
+}
+
+/// The merchant key a request was sent with, read from its `Authorization` header.
+fn merchant_key(request: &HttpRequest) -> &str {
+    request
+        .header("authorization")
+        .map(|value| value.trim_start_matches("Bearer ").trim())
+        .unwrap_or_default()
+}
+
+/// Sends requests through another transport and records every exchange to a cassette file.
+///
+/// The file is rewritten after each exchange, so nothing is lost when a test panics.
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    path: PathBuf,
+    scrubber: Scrubber,
+    cassette: Mutex<Cassette>,
+}
+
+impl std::fmt::Debug for RecordingTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordingTransport")
+            .field("inner", &self.inner)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl RecordingTransport {
+    /// Record to `path`, replacing whatever cassette is there.
+    pub fn new(inner: Arc<dyn Transport>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            path: path.into(),
+            scrubber: Scrubber::default(),
+            cassette: Mutex::new(Cassette::default()),
+        }
+    }
+
+    pub fn scrubber(mut self, scrubber: Scrubber) -> Self {
+        self.scrubber = scrubber;
+        self
+    }
+
+    pub fn cassette(&self) -> Cassette {
+        self.cassette
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+#[async_trait]
+impl Transport for RecordingTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        let recorded = self.scrubber.request(&request);
+        let response = self.inner.send(request.clone()).await?;
+
+        let mut cassette = self
+            .cassette
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        cassette.interactions.push(Interaction {
+            request: recorded,
+            response: self.scrubber.response(&request, &response),
+        });
+        cassette.save(&self.path)?;
+
+        Ok(response)
+    }
+}
+
+/// Answers requests from a cassette. Each interaction is served once, in order, among those
+/// matching a request; once they are all used, the last one keeps being served.
+pub struct ReplayTransport {
+    cassette: Cassette,
+    scrubber: Scrubber,
+    used: Mutex<Vec<bool>>,
+}
+
+impl std::fmt::Debug for ReplayTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplayTransport")
+            .field("interactions", &self.cassette.interactions.len())
+            .finish()
+    }
+}
+
+impl ReplayTransport {
+    pub fn new(cassette: Cassette) -> Self {
+        let used = vec![false; cassette.interactions.len()];
+        Self {
+            cassette,
+            scrubber: Scrubber::default(),
+            used: Mutex::new(used),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CassetteError> {
+        Ok(Self::new(Cassette::load(path)?))
+    }
+
+    /// Match with `scrubber`, which must redact the same things as the one that recorded.
+    pub fn scrubber(mut self, scrubber: Scrubber) -> Self {
+        self.scrubber = scrubber;
+        self
+    }
+}
+
+#[async_trait]
+impl Transport for ReplayTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        let recorded = self.scrubber.request(&request);
+        let mut used = self
+            .used
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let matching: Vec<usize> = self
+            .cassette
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, interaction)| interaction.request == recorded)
+            .map(|(index, _)| index)
+            .collect();
+        let index = matching
+            .iter()
+            .copied()
+            .find(|index| !used[*index])
+            .or_else(|| matching.last().copied())
+            .ok_or_else(|| CassetteError::NoMatch {
+                method: recorded.method.clone(),
+                path: recorded.path.clone(),
+            })?;
+        used[index] = true;
+
+        let response = self.cassette.interactions[index].response.clone();
+        Ok(HttpResponse {
+            status: response.status,
+            headers: response.headers,
+            body: response.body.into_string(),
+        })
+    }
+}
+
+/// Replay `path` when it exists, otherwise record to it through `inner`. Delete the file to
+/// record it again.
+pub fn cassette(
+    path: impl AsRef<Path>,
+    inner: Arc<dyn Transport>,
+) -> Result<Arc<dyn Transport>, CassetteError> {
+    let path = path.as_ref();
+    if path.exists() {
+        Ok(Arc::new(ReplayTransport::load(path)?))
+    } else {
+        Ok(Arc::new(RecordingTransport::new(inner, path)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use crate::enviroment::Environment;
+    use crate::transport::{Method, MockTransport};
+    use serde_json::json;
+
+    const NAME_ENQUIRY: &str = "bank/name-enquiry?bankCode=000013&accountNumber=0123456789";
+
+    fn cassette_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "zainpay-{}-{}-{}.json",
+            name,
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ))
+    }
+
+    fn zainpay() -> Arc<MockTransport> {
+        let body = json!({
+            "code": "00",
+            "status": "200 OK",
+            "data": { "accountName": "Ada Obi", "accountNumber": 123456789, "balance": 500000 },
+        });
+        Arc::new(
+            MockTransport::new().route(
+                Method::Get,
+                "bank/name-enquiry",
+                HttpResponse::new(200, &body.to_string())
+                    .with_header("Set-Cookie", "session=abc")
+                    .with_header("X-Request-Id", "req-1"),
+            ),
+        )
+    }
+
+    fn engine(key: &str, transport: Arc<dyn Transport>) -> Engine {
+        Engine::builder(Environment::Sandbox, key)
+            .transport(transport)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn records_scrubbed_traffic_and_replays_it() {
+        let path = cassette_path("record");
+        let recorder = Arc::new(RecordingTransport::new(zainpay(), &path));
+        let live = engine("secret-key", recorder.clone())
+            .get(NAME_ENQUIRY)
+            .await
+            .unwrap();
+        assert_eq!(live.get_raw_data().unwrap()["accountName"], "Ada Obi");
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        for leaked in [
+            "secret-key",
+            "0123456789",
+            "123456789",
+            "Ada Obi",
+            "session=abc",
+        ] {
+            assert!(
+                !content.contains(leaked),
+                "{} leaked into {}",
+                leaked,
+                content
+            );
+        }
+        assert_eq!(Cassette::load(&path).unwrap(), recorder.cassette());
+
+        // Another key and account number match the same scrubbed interaction.
+        let replay = Arc::new(ReplayTransport::load(&path).unwrap());
+        let replayed = engine("other-key", replay.clone())
+            .get("bank/name-enquiry?accountNumber=9876543210&bankCode=000013")
+            .await
+            .unwrap();
+        let data = replayed.get_raw_data().unwrap();
+        assert_eq!(data["accountName"], REDACTED);
+        assert_eq!(data["balance"], 500000);
+
+        let unmatched = replay
+            .send(HttpRequest {
+                method: Method::Get,
+                url: "https://sandbox.zainpay.ng/bank/list".to_string(),
+                headers: Vec::new(),
+                body: None,
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            unmatched.to_string().contains("GET bank/list"),
+            "{}",
+            unmatched
+        );
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn replays_an_existing_cassette_instead_of_recording() {
+        let path = cassette_path("helper");
+        let inner = zainpay();
+
+        engine("key", cassette(&path, inner.clone()).unwrap())
+            .get(NAME_ENQUIRY)
+            .await
+            .unwrap();
+        engine("key", cassette(&path, inner.clone()).unwrap())
+            .get(NAME_ENQUIRY)
+            .await
+            .unwrap();
+
+        assert_eq!(inner.requests().len(), 1);
+        assert_eq!(Cassette::load(&path).unwrap().interactions.len(), 1);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn scrubs_every_scalar_under_a_personal_key() {
+        let scrubber = Scrubber::new().key("reference").secret("internal-token");
+        let request = HttpRequest {
+            method: Method::Post,
+            url: "https://sandbox.zainpay.ng/virtual-account/wallet/balance/7966884043".to_string(),
+            headers: vec![("Authorization".to_string(), "Bearer secret-key".to_string())],
+            body: Some(
+                json!({
+                    "accountNumber": 7966884043u64,
+                    "bvn": 22222222222u64,
+                    "isEmailVerified": true,
+                    "phones": ["08012345678", 8012345678u64],
+                    "mobile": null,
+                    "customerReference": "CUS-1",
+                    "note": "sent with secret-key and internal-token",
+                    "amount": 500000,
+                })
+                .to_string(),
+            ),
+        };
+
+        let recorded = scrubber.request(&request);
+
+        assert_eq!(recorded.path, "virtual-account/wallet/balance/[REDACTED]");
+        assert_eq!(
+            recorded.body,
+            Some(Body::Json(json!({
+                "accountNumber": REDACTED,
+                "bvn": REDACTED,
+                "isEmailVerified": REDACTED,
+                "phones": [REDACTED, REDACTED],
+                "mobile": null,
+                "customerReference": REDACTED,
+                "note": "sent with [REDACTED] and [REDACTED]",
+                "amount": 500000,
+            })))
+        );
+    }
+}
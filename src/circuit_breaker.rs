@@ -0,0 +1,247 @@
+use crate::error::ZainpayError;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CircuitBreakerConfig {
+    /// Share of failed calls in the window at or above which the circuit opens.
+    pub failure_ratio: f64,
+    /// Calls the window must hold before the failure ratio is considered.
+    pub minimum_calls: usize,
+    /// Number of most recent calls the failure ratio is computed over.
+    pub window: usize,
+    /// How long the circuit stays open before letting probe calls through.
+    pub open_duration: Duration,
+    /// Calls let through while half-open. The circuit closes once they have all succeeded.
+    pub half_open_probes: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_ratio: 0.5,
+            minimum_calls: 10,
+            window: 20,
+            open_duration: Duration::from_secs(30),
+            half_open_probes: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    /// Calls fail fast with `ZainpayError::CircuitOpen`.
+    Open,
+    /// A limited number of probe calls is let through to find out whether Zainpay recovered.
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: CircuitState,
+    outcomes: VecDeque<bool>,
+    opened_at: Instant,
+    probes_started: u32,
+    probes_succeeded: u32,
+}
+
+/// Counts transport errors and 5xx responses, and stops calling Zainpay while it is degraded.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                outcomes: VecDeque::with_capacity(config.window),
+                opened_at: Instant::now(),
+                probes_started: 0,
+                probes_succeeded: 0,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let mut inner = self.lock();
+        self.half_open_if_due(&mut inner);
+        inner.state
+    }
+
+    /// Lets a call through, or fails fast while the circuit is open.
+    pub fn try_acquire(&self) -> Result<(), ZainpayError> {
+        let mut inner = self.lock();
+        self.half_open_if_due(&mut inner);
+
+        match inner.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::HalfOpen if inner.probes_started < self.config.half_open_probes => {
+                inner.probes_started += 1;
+                Ok(())
+            }
+            CircuitState::HalfOpen | CircuitState::Open => Err(ZainpayError::CircuitOpen {
+                retry_in: self
+                    .config
+                    .open_duration
+                    .saturating_sub(inner.opened_at.elapsed()),
+            }),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.lock();
+        match inner.state {
+            CircuitState::HalfOpen => {
+                inner.probes_succeeded += 1;
+                if inner.probes_succeeded >= self.config.half_open_probes {
+                    tracing::info!("zainpay circuit closed");
+                    inner.state = CircuitState::Closed;
+                    inner.outcomes.clear();
+                }
+            }
+            _ => self.push_outcome(&mut inner, true),
+        }
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.lock();
+        match inner.state {
+            CircuitState::HalfOpen => self.open(&mut inner),
+            _ => {
+                self.push_outcome(&mut inner, false);
+                let failures = inner.outcomes.iter().filter(|ok| !**ok).count();
+                let calls = inner.outcomes.len();
+                if inner.state == CircuitState::Closed
+                    && calls >= self.config.minimum_calls
+                    && failures as f64 / calls as f64 >= self.config.failure_ratio
+                {
+                    self.open(&mut inner);
+                }
+            }
+        }
+    }
+
+    fn push_outcome(&self, inner: &mut Inner, ok: bool) {
+        if inner.outcomes.len() >= self.config.window.max(1) {
+            inner.outcomes.pop_front();
+        }
+        inner.outcomes.push_back(ok);
+    }
+
+    fn open(&self, inner: &mut Inner) {
+        tracing::warn!(
+            open_for_ms = self.config.open_duration.as_millis() as u64,
+            "zainpay circuit opened"
+        );
+        inner.state = CircuitState::Open;
+        inner.opened_at = Instant::now();
+        inner.outcomes.clear();
+    }
+
+    /// Moves an open circuit to half-open once `open_duration` has passed. A half-open circuit
+    /// whose probes have not all completed within `open_duration` (e.g. a cancelled call) starts
+    /// probing again.
+    fn half_open_if_due(&self, inner: &mut Inner) {
+        if inner.state != CircuitState::Closed
+            && inner.opened_at.elapsed() >= self.config.open_duration
+        {
+            inner.state = CircuitState::HalfOpen;
+            inner.opened_at = Instant::now();
+            inner.probes_started = 0;
+            inner.probes_succeeded = 0;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(open_duration: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            failure_ratio: 0.5,
+            minimum_calls: 4,
+            window: 4,
+            open_duration,
+            half_open_probes: 1,
+        })
+    }
+
+    #[test]
+    fn stays_closed_below_the_minimum_number_of_calls() {
+        let breaker = breaker(Duration::from_secs(30));
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire().is_ok());
+    }
+
+    #[test]
+    fn opens_at_the_failure_ratio_and_fails_fast() {
+        let breaker = breaker(Duration::from_secs(30));
+        breaker.record_success();
+        breaker.record_success();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(matches!(
+            breaker.try_acquire(),
+            Err(ZainpayError::CircuitOpen { .. })
+        ));
+    }
+
+    #[test]
+    fn old_outcomes_leave_the_window() {
+        let breaker = breaker(Duration::from_secs(30));
+        breaker.record_failure();
+        for _ in 0..4 {
+            breaker.record_success();
+        }
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn a_successful_probe_closes_the_circuit() {
+        let breaker = breaker(Duration::from_millis(10));
+        for _ in 0..4 {
+            breaker.record_failure();
+        }
+        std::thread::sleep(Duration::from_millis(15));
+
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.try_acquire().is_ok());
+        assert!(breaker.try_acquire().is_err(), "one probe at a time");
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire().is_ok());
+    }
+
+    #[test]
+    fn a_failed_probe_opens_the_circuit_again() {
+        let breaker = breaker(Duration::from_millis(10));
+        for _ in 0..4 {
+            breaker.record_failure();
+        }
+        std::thread::sleep(Duration::from_millis(15));
+
+        assert!(breaker.try_acquire().is_ok());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.try_acquire().is_err());
+    }
+}
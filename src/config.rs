@@ -0,0 +1,465 @@
+use crate::engine::{Engine, EngineBuilder};
+use crate::enviroment::Environment;
+use crate::secret::Secret;
+use crate::transport::ReqwestTransport;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+const MERCHANT_KEY: &str = "merchant_key";
+const ENVIRONMENT: &str = "environment";
+const BASE_URL: &str = "base_url";
+const TIMEOUT_SECS: &str = "timeout_secs";
+const CONNECT_TIMEOUT_SECS: &str = "connect_timeout_secs";
+const MAX_RATE_LIMIT_RETRIES: &str = "max_rate_limit_retries";
+const MAX_RETRY_AFTER_SECS: &str = "max_retry_after_secs";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("missing required setting `{0}`")]
+    Missing(String),
+
+    #[error("invalid value {value:?} for `{key}`: {reason}")]
+    Invalid {
+        key: String,
+        value: String,
+        reason: String,
+    },
+
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("could not parse {path}: {message}")]
+    Parse { path: String, message: String },
+
+    #[error("section `{section}` not found in {path}")]
+    MissingSection { path: String, section: String },
+
+    #[error("could not build the HTTP client: {0}")]
+    Client(#[from] reqwest::Error),
+}
+
+/// Settings needed to build an [`Engine`], read from the environment or a config file.
+///
+/// | Setting                  | Environment variable             | Default   |
+/// |--------------------------|----------------------------------|-----------|
+/// | `merchant_key`           | `ZAINPAY_MERCHANT_KEY`           | required  |
+/// | `environment`            | `ZAINPAY_ENV`                    | `sandbox` |
+/// | `base_url`               | `ZAINPAY_BASE_URL`               | per environment |
+/// | `timeout_secs`           | `ZAINPAY_TIMEOUT_SECS`           | none      |
+/// | `connect_timeout_secs`   | `ZAINPAY_CONNECT_TIMEOUT_SECS`   | none      |
+/// | `max_rate_limit_retries` | `ZAINPAY_MAX_RATE_LIMIT_RETRIES` | 2         |
+/// | `max_retry_after_secs`   | `ZAINPAY_MAX_RETRY_AFTER_SECS`   | 60        |
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub merchant_key: Secret<String>,
+    pub environment: Environment,
+    pub base_url: Option<String>,
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub max_rate_limit_retries: Option<u32>,
+    pub max_retry_after: Option<Duration>,
+}
+
+impl EngineConfig {
+    pub fn new(environment: Environment, merchant_key: &str) -> Self {
+        Self {
+            merchant_key: Secret::from(merchant_key),
+            environment,
+            base_url: None,
+            timeout: None,
+            connect_timeout: None,
+            max_rate_limit_retries: None,
+            max_retry_after: None,
+        }
+    }
+
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_env_with(|name| std::env::var(name).ok())
+    }
+
+    /// Like [`EngineConfig::from_env`], reading variables through `var` instead of the process
+    /// environment.
+    pub fn from_env_with<F>(var: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        Self::from_settings(env_var_name, |name| {
+            var(name).filter(|value| !value.trim().is_empty())
+        })
+    }
+
+    /// Read the settings from the table at `section` (dotted, e.g. `profiles.ops`) of a TOML
+    /// file, or from its top level when `section` is `None`.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_file(
+        path: impl AsRef<std::path::Path>,
+        section: Option<&str>,
+    ) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        Self::from_toml_str(&read_file(path)?, section, &path.display().to_string())
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(
+        content: &str,
+        section: Option<&str>,
+        source: &str,
+    ) -> Result<Self, ConfigError> {
+        let document: toml::Value =
+            toml::from_str(content).map_err(|error| ConfigError::Parse {
+                path: source.to_string(),
+                message: error.to_string(),
+            })?;
+        Self::from_document(to_json(&document, source)?, section, source)
+    }
+
+    /// Read the settings from the mapping at `section` (dotted, e.g. `profiles.ops`) of a YAML
+    /// file, or from its top level when `section` is `None`.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_file(
+        path: impl AsRef<std::path::Path>,
+        section: Option<&str>,
+    ) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        Self::from_yaml_str(&read_file(path)?, section, &path.display().to_string())
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_str(
+        content: &str,
+        section: Option<&str>,
+        source: &str,
+    ) -> Result<Self, ConfigError> {
+        let document: serde_yaml::Value =
+            serde_yaml::from_str(content).map_err(|error| ConfigError::Parse {
+                path: source.to_string(),
+                message: error.to_string(),
+            })?;
+        Self::from_document(to_json(&document, source)?, section, source)
+    }
+
+    /// Read the settings from a document already parsed into JSON values.
+    pub fn from_document(
+        document: Value,
+        section: Option<&str>,
+        source: &str,
+    ) -> Result<Self, ConfigError> {
+        let mut table = &document;
+        if let Some(section) = section {
+            for key in section.split('.') {
+                table = table.get(key).ok_or_else(|| ConfigError::MissingSection {
+                    path: source.to_string(),
+                    section: section.to_string(),
+                })?;
+            }
+        }
+        if !table.is_object() {
+            return Err(ConfigError::Parse {
+                path: source.to_string(),
+                message: format!("`{}` is not a table", section.unwrap_or("document")),
+            });
+        }
+
+        Self::from_settings(
+            |setting| setting.to_string(),
+            |name| match table.get(name)? {
+                Value::Null => None,
+                Value::String(value) if value.trim().is_empty() => None,
+                Value::String(value) => Some(value.clone()),
+                other => Some(other.to_string()),
+            },
+        )
+    }
+
+    fn from_settings<N, G>(name: N, get: G) -> Result<Self, ConfigError>
+    where
+        N: Fn(&'static str) -> String,
+        G: Fn(&str) -> Option<String>,
+    {
+        let merchant_key =
+            get(&name(MERCHANT_KEY)).ok_or_else(|| ConfigError::Missing(name(MERCHANT_KEY)))?;
+
+        let environment = match get(&name(ENVIRONMENT)) {
+            Some(value) => value
+                .parse::<Environment>()
+                .map_err(|reason| ConfigError::Invalid {
+                    key: name(ENVIRONMENT),
+                    value,
+                    reason,
+                })?,
+            None => Environment::Sandbox,
+        };
+
+        let base_url = match get(&name(BASE_URL)) {
+            Some(value) if !(value.starts_with("http://") || value.starts_with("https://")) => {
+                return Err(ConfigError::Invalid {
+                    key: name(BASE_URL),
+                    value,
+                    reason: "expected an http:// or https:// url".to_string(),
+                })
+            }
+            other => other,
+        };
+
+        Ok(Self {
+            merchant_key: Secret::new(merchant_key),
+            environment,
+            base_url,
+            timeout: parse_number::<u64>(&name(TIMEOUT_SECS), get(&name(TIMEOUT_SECS)))?
+                .map(Duration::from_secs),
+            connect_timeout: parse_number::<u64>(
+                &name(CONNECT_TIMEOUT_SECS),
+                get(&name(CONNECT_TIMEOUT_SECS)),
+            )?
+            .map(Duration::from_secs),
+            max_rate_limit_retries: parse_number::<u32>(
+                &name(MAX_RATE_LIMIT_RETRIES),
+                get(&name(MAX_RATE_LIMIT_RETRIES)),
+            )?,
+            max_retry_after: parse_number::<u64>(
+                &name(MAX_RETRY_AFTER_SECS),
+                get(&name(MAX_RETRY_AFTER_SECS)),
+            )?
+            .map(Duration::from_secs),
+        })
+    }
+
+    /// An engine builder carrying these settings, for further configuration.
+    pub fn builder(&self) -> Result<EngineBuilder, ConfigError> {
+        let mut builder = Engine::builder(self.environment, self.merchant_key.expose());
+
+        if let Some(base_url) = &self.base_url {
+            builder = builder.base_url(base_url);
+        }
+        if let Some(retries) = self.max_rate_limit_retries {
+            builder = builder.max_rate_limit_retries(retries);
+        }
+        if let Some(max_retry_after) = self.max_retry_after {
+            builder = builder.max_retry_after(max_retry_after);
+        }
+        if self.timeout.is_some() || self.connect_timeout.is_some() {
+            let mut client = reqwest::Client::builder();
+            if let Some(timeout) = self.timeout {
+                client = client.timeout(timeout);
+            }
+            if let Some(connect_timeout) = self.connect_timeout {
+                client = client.connect_timeout(connect_timeout);
+            }
+            builder = builder.transport(Arc::new(ReqwestTransport::with_client(client.build()?)));
+        }
+
+        Ok(builder)
+    }
+
+    pub fn build_engine(&self) -> Result<Engine, ConfigError> {
+        Ok(self.builder()?.build())
+    }
+}
+
+fn env_var_name(setting: &'static str) -> String {
+    match setting {
+        ENVIRONMENT => "ZAINPAY_ENV".to_string(),
+        setting => format!("ZAINPAY_{}", setting.to_ascii_uppercase()),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(
+    key: &str,
+    value: Option<String>,
+) -> Result<Option<T>, ConfigError> {
+    value
+        .map(|value| {
+            value.trim().parse::<T>().map_err(|_| ConfigError::Invalid {
+                key: key.to_string(),
+                value,
+                reason: "expected a non-negative whole number".to_string(),
+            })
+        })
+        .transpose()
+}
+
+#[cfg(any(feature = "toml", feature = "yaml"))]
+pub(crate) fn read_file(path: &std::path::Path) -> Result<String, ConfigError> {
+    std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+#[cfg(any(feature = "toml", feature = "yaml"))]
+fn to_json<T: serde::Serialize>(document: &T, source: &str) -> Result<Value, ConfigError> {
+    serde_json::to_value(document).map_err(|error| ConfigError::Parse {
+        path: source.to_string(),
+        message: error.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn from_env(vars: &[(&str, &str)]) -> Result<EngineConfig, ConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        EngineConfig::from_env_with(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn reads_the_environment() {
+        let config = from_env(&[
+            ("ZAINPAY_MERCHANT_KEY", "key"),
+            ("ZAINPAY_ENV", "live"),
+            ("ZAINPAY_BASE_URL", "https://proxy.example.com"),
+            ("ZAINPAY_TIMEOUT_SECS", " 30 "),
+            ("ZAINPAY_MAX_RATE_LIMIT_RETRIES", "5"),
+        ])
+        .unwrap();
+
+        assert_eq!(config.merchant_key.expose(), "key");
+        assert_eq!(config.environment, Environment::Production);
+        assert_eq!(
+            config.base_url.as_deref(),
+            Some("https://proxy.example.com")
+        );
+        assert_eq!(config.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(config.connect_timeout, None);
+        assert_eq!(config.max_rate_limit_retries, Some(5));
+        assert_eq!(config.max_retry_after, None);
+    }
+
+    #[test]
+    fn defaults_to_the_sandbox() {
+        let config = from_env(&[("ZAINPAY_MERCHANT_KEY", "key"), ("ZAINPAY_ENV", " ")]).unwrap();
+        assert_eq!(config.environment, Environment::Sandbox);
+        assert_eq!(config.base_url, None);
+    }
+
+    #[test]
+    fn a_missing_or_blank_key_is_reported() {
+        for vars in [&[][..], &[("ZAINPAY_MERCHANT_KEY", "  ")][..]] {
+            match from_env(vars) {
+                Err(ConfigError::Missing(name)) => assert_eq!(name, "ZAINPAY_MERCHANT_KEY"),
+                other => panic!("expected a missing key, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_values_name_the_setting() {
+        let cases = [
+            ("ZAINPAY_ENV", "staging"),
+            ("ZAINPAY_BASE_URL", "proxy.example.com"),
+            ("ZAINPAY_TIMEOUT_SECS", "-1"),
+            ("ZAINPAY_MAX_RETRY_AFTER_SECS", "a minute"),
+        ];
+        for (name, value) in cases {
+            match from_env(&[("ZAINPAY_MERCHANT_KEY", "key"), (name, value)]) {
+                Err(ConfigError::Invalid {
+                    key, value: got, ..
+                }) => {
+                    assert_eq!(key, name);
+                    assert_eq!(got, value);
+                }
+                other => panic!("expected {} to be invalid, got {:?}", name, other),
+            }
+        }
+    }
+
+    #[test]
+    fn reads_a_section_of_a_document() {
+        let document = json!({
+            "merchant_key": "top",
+            "profiles": {
+                "ops": {
+                    "merchant_key": "ops-key",
+                    "environment": "production",
+                    "max_retry_after_secs": 10,
+                    "timeout_secs": null,
+                }
+            }
+        });
+
+        let top = EngineConfig::from_document(document.clone(), None, "test").unwrap();
+        assert_eq!(top.merchant_key.expose(), "top");
+
+        let ops = EngineConfig::from_document(document, Some("profiles.ops"), "test").unwrap();
+        assert_eq!(ops.merchant_key.expose(), "ops-key");
+        assert_eq!(ops.environment, Environment::Production);
+        assert_eq!(ops.max_retry_after, Some(Duration::from_secs(10)));
+        assert_eq!(ops.timeout, None);
+    }
+
+    #[test]
+    fn a_missing_section_or_setting_is_reported() {
+        let document = json!({ "profiles": { "ops": { "environment": "sandbox" }, "name": "x" } });
+
+        match EngineConfig::from_document(document.clone(), Some("profiles.dev"), "test") {
+            Err(ConfigError::MissingSection { section, .. }) => assert_eq!(section, "profiles.dev"),
+            other => panic!("expected a missing section, got {:?}", other),
+        }
+        assert!(matches!(
+            EngineConfig::from_document(document.clone(), Some("profiles.name"), "test"),
+            Err(ConfigError::Parse { .. })
+        ));
+        match EngineConfig::from_document(document, Some("profiles.ops"), "test") {
+            Err(ConfigError::Missing(name)) => assert_eq!(name, "merchant_key"),
+            other => panic!("expected a missing key, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn numbers_must_be_whole() {
+        let document = json!({ "merchant_key": "key", "timeout_secs": 1.5 });
+        assert!(matches!(
+            EngineConfig::from_document(document, None, "test"),
+            Err(ConfigError::Invalid { key, .. }) if key == "timeout_secs"
+        ));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn reads_toml() {
+        let content = r#"
+            [profiles.ops]
+            merchant_key = "ops-key"
+            environment = "live"
+            max_rate_limit_retries = 3
+        "#;
+        let config =
+            EngineConfig::from_toml_str(content, Some("profiles.ops"), "zainpay.toml").unwrap();
+        assert_eq!(config.merchant_key.expose(), "ops-key");
+        assert_eq!(config.environment, Environment::Production);
+        assert_eq!(config.max_rate_limit_retries, Some(3));
+
+        assert!(matches!(
+            EngineConfig::from_toml_str("merchant_key = ", None, "zainpay.toml"),
+            Err(ConfigError::Parse { path, .. }) if path == "zainpay.toml"
+        ));
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn reads_yaml() {
+        let content = "profiles:\n  ops:\n    merchant_key: ops-key\n    connect_timeout_secs: 5\n";
+        let config =
+            EngineConfig::from_yaml_str(content, Some("profiles.ops"), "zainpay.yaml").unwrap();
+        assert_eq!(config.merchant_key.expose(), "ops-key");
+        assert_eq!(config.environment, Environment::Sandbox);
+        assert_eq!(config.connect_timeout, Some(Duration::from_secs(5)));
+
+        assert!(matches!(
+            EngineConfig::from_yaml_str("merchant_key: [", None, "zainpay.yaml"),
+            Err(ConfigError::Parse { path, .. }) if path == "zainpay.yaml"
+        ));
+    }
+}
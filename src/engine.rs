@@ -1,62 +1,790 @@
+use crate::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitState};
+use crate::config::{ConfigError, EngineConfig};
 use crate::enviroment::Environment;
-use reqwest::{Client, Response as ReqwestResponse};
+use crate::error::ZainpayError;
+use crate::jwt::KeyClaims;
+use crate::middleware::{Flow, Middleware, RequestContext};
+use crate::rate_limit::{parse_retry_after, EndpointGroup, RateLimit, RateLimiter};
+use crate::response::Response;
+use crate::secret::Secret;
+use crate::transport::{
+    HttpRequest, HttpResponse, Method, ReqwestTransport, Transport, TransportError,
+};
+use crate::utils::redact::redact;
+use serde_json::Value;
+use std::collections::HashMap;
 use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{field, Instrument};
 
-#[derive(Clone)]
+/// How request and response bodies are written to `tracing` at debug level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BodyLogging {
+    #[default]
+    Off,
+    /// Log bodies with BVNs, account numbers, balances and contact details replaced by `[REDACTED]`.
+    Redacted,
+    /// Log bodies verbatim. Only meant for local debugging.
+    Full,
+}
+
+/// Sends requests to Zainpay for every service.
+///
+/// Requests go through a [`Transport`], `reqwest` by default, so services can be exercised
+/// against an in-memory `MockTransport` or another HTTP stack.
+#[derive(Debug, Clone)]
 pub struct Engine {
-    client: Client,
+    transport: Arc<dyn Transport>,
     pub base_url: String,
-    pub merchant_key: String,
+    pub merchant_key: Secret<String>,
+    enviroment: Environment,
+    key_claims: Option<Arc<KeyClaims>>,
+    key_expiry_warning: Duration,
+    key_expiry_margin: Duration,
+    key_expiry_warned: Arc<AtomicBool>,
+    body_logging: BodyLogging,
+    rate_limiter: Arc<RateLimiter>,
+    max_rate_limit_retries: u32,
+    max_retry_after: Duration,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+#[derive(Debug)]
+pub struct EngineBuilder {
+    transport: Option<Arc<dyn Transport>>,
+    base_url: String,
+    merchant_key: Secret<String>,
+    enviroment: Environment,
+    key_expiry_warning: Duration,
+    key_expiry_margin: Duration,
+    body_logging: BodyLogging,
+    rate_limits: HashMap<EndpointGroup, RateLimit>,
+    max_rate_limit_retries: u32,
+    max_retry_after: Duration,
+    circuit_breaker: Option<CircuitBreakerConfig>,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl EngineBuilder {
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn body_logging(mut self, body_logging: BodyLogging) -> Self {
+        self.body_logging = body_logging;
+        self
+    }
+
+    /// Throttle requests of an endpoint group on the client side. Groups without a limit are not throttled.
+    pub fn rate_limit(mut self, group: EndpointGroup, limit: RateLimit) -> Self {
+        self.rate_limits.insert(group, limit);
+        self
+    }
+
+    /// How many times a request answered with HTTP 429 is retried, after waiting for the
+    /// `Retry-After` delay. Defaults to 2.
+    pub fn max_rate_limit_retries(mut self, retries: u32) -> Self {
+        self.max_rate_limit_retries = retries;
+        self
+    }
+
+    /// The longest wait a `Retry-After` header is obeyed for; longer delays are cut to it.
+    /// Defaults to a minute.
+    pub fn max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    /// Fail fast with `ZainpayError::CircuitOpen` while Zainpay keeps erroring. Off by default.
+    pub fn circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.circuit_breaker = Some(config);
+        self
+    }
+
+    /// Send requests through `transport` instead of the default `ReqwestTransport`.
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Log a warning, once, when the merchant key expires within `warning`. Defaults to 7 days.
+    pub fn key_expiry_warning(mut self, warning: Duration) -> Self {
+        self.key_expiry_warning = warning;
+        self
+    }
+
+    /// Refuse to send requests with `ZainpayError::KeyExpiring` once the merchant key expires
+    /// within `margin`, instead of only when it has expired. Defaults to zero.
+    pub fn key_expiry_margin(mut self, margin: Duration) -> Self {
+        self.key_expiry_margin = margin;
+        self
+    }
+
+    /// Append a middleware to the chain run around every request.
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    pub fn build(self) -> Engine {
+        Engine {
+            transport: self
+                .transport
+                .unwrap_or_else(|| Arc::new(ReqwestTransport::new())),
+            base_url: self.base_url,
+            key_claims: KeyClaims::decode(self.merchant_key.expose()).map(Arc::new),
+            merchant_key: self.merchant_key,
+            enviroment: self.enviroment,
+            key_expiry_warning: self.key_expiry_warning,
+            key_expiry_margin: self.key_expiry_margin,
+            key_expiry_warned: Arc::new(AtomicBool::new(false)),
+            body_logging: self.body_logging,
+            rate_limiter: Arc::new(RateLimiter::new(self.rate_limits)),
+            max_rate_limit_retries: self.max_rate_limit_retries,
+            max_retry_after: self.max_retry_after,
+            circuit_breaker: self
+                .circuit_breaker
+                .map(|config| Arc::new(CircuitBreaker::new(config))),
+            middleware: self.middleware,
+        }
+    }
 }
 
 impl Engine {
     pub fn new(enviroment: Environment, merchant_key: &str) -> Self {
-        Self {
-            client: Client::new(),
+        Self::builder(enviroment, merchant_key).build()
+    }
+
+    pub fn builder(enviroment: Environment, merchant_key: &str) -> EngineBuilder {
+        EngineBuilder {
+            transport: None,
             base_url: enviroment.base_url().to_string(),
-            merchant_key: merchant_key.to_string(),
+            merchant_key: Secret::from(merchant_key),
+            enviroment,
+            key_expiry_warning: Duration::from_secs(7 * 24 * 60 * 60),
+            key_expiry_margin: Duration::ZERO,
+            body_logging: BodyLogging::default(),
+            rate_limits: HashMap::new(),
+            max_rate_limit_retries: 2,
+            max_retry_after: Duration::from_secs(60),
+            circuit_breaker: None,
+            middleware: Vec::new(),
         }
     }
 
+    /// Build an engine from the `ZAINPAY_*` environment variables, see [`EngineConfig`].
+    pub fn from_env() -> Result<Self, ConfigError> {
+        EngineConfig::from_env()?.build_engine()
+    }
+
+    pub fn environment(&self) -> Environment {
+        self.enviroment
+    }
+
+    /// Claims decoded from the merchant key, `None` when the key is not a JWT.
+    pub fn key_claims(&self) -> Option<&KeyClaims> {
+        self.key_claims.as_deref()
+    }
+
+    /// Check that the merchant key belongs to the engine's environment and has not expired, or
+    /// expires within the configured margin. Every request runs this check first; call it at
+    /// startup to fail early. Keys that are not JWTs are not checked.
+    pub fn check_key(&self) -> Result<(), ZainpayError> {
+        let Some(claims) = &self.key_claims else {
+            return Ok(());
+        };
+
+        if let Some(key) = claims.environment {
+            if key != self.enviroment && self.enviroment != Environment::Localbox {
+                return Err(ZainpayError::KeyEnvironmentMismatch {
+                    key,
+                    engine: self.enviroment,
+                });
+            }
+        }
+
+        if let (Some(expires_at), Some(expires_in)) = (claims.expires_at, claims.expires_in()) {
+            if expires_in <= chrono::Duration::zero() {
+                return Err(ZainpayError::KeyExpired { expires_at });
+            }
+            let expires_in = expires_in.to_std().unwrap_or_default();
+            if expires_in <= self.key_expiry_margin {
+                return Err(ZainpayError::KeyExpiring { expires_at });
+            }
+            if expires_in <= self.key_expiry_warning
+                && !self.key_expiry_warned.swap(true, Ordering::Relaxed)
+            {
+                tracing::warn!(%expires_at, "zainpay merchant key expires soon");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// State of the circuit breaker, for health checks. `None` when no breaker is configured.
+    pub fn circuit_state(&self) -> Option<CircuitState> {
+        self.circuit_breaker.as_ref().map(|breaker| breaker.state())
+    }
+
+    /// Requests to the endpoint `template`, e.g. `zainbox/profile/{}`. Spans and metrics are
+    /// labelled with the template instead of the path, so codes and refs stay out of them.
+    pub fn endpoint(&self, template: &'static str) -> Endpoint<'_> {
+        Endpoint {
+            engine: self,
+            template,
+        }
+    }
+
+    /// Labelled with the first segment of `path` only; prefer `endpoint(..).post`.
     pub async fn post<T: serde::Serialize>(
         &self,
         path: &str,
         body: &T,
-    ) -> Result<ReqwestResponse, Box<dyn Error>> {
-        let url = format!("{}/{}", self.base_url, path);
-        Ok(self
-            .client
-            .post(url)
-            .header("Authorization", format!("Bearer {}", self.merchant_key))
-            .header("Content-Type", "application/json")
-            .json(body)
-            .send()
-            .await?)
-    }
-
-    pub async fn get(&self, path: &str) -> Result<ReqwestResponse, Box<dyn Error>> {
-        let url = format!("{}/{}", self.base_url, path);
-        Ok(self
-            .client
-            .get(url)
-            .header("Authorization", format!("Bearer {}", self.merchant_key))
-            .send()
-            .await?)
+    ) -> Result<Response, Box<dyn Error>> {
+        self.send(
+            Method::Post,
+            path,
+            &path_template(path),
+            Some(serde_json::to_value(body)?),
+        )
+        .await
     }
 
+    /// Labelled with the first segment of `path` only; prefer `endpoint(..).get`.
+    pub async fn get(&self, path: &str) -> Result<Response, Box<dyn Error>> {
+        self.send(Method::Get, path, &path_template(path), None)
+            .await
+    }
+
+    /// Labelled with the first segment of `path` only; prefer `endpoint(..).patch`.
     pub async fn patch<T: serde::Serialize>(
         &self,
         path: &str,
         body: &T,
-    ) -> Result<ReqwestResponse, Box<dyn Error>> {
-        let url = format!("{}/{}", self.base_url, path);
-        Ok(self
-            .client
-            .patch(url)
-            .header("Authorization", format!("Bearer {}", self.merchant_key))
-            .header("Content-Type", "application/json")
-            .json(body)
-            .send()
-            .await?)
+    ) -> Result<Response, Box<dyn Error>> {
+        self.send(
+            Method::Patch,
+            path,
+            &path_template(path),
+            Some(serde_json::to_value(body)?),
+        )
+        .await
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        endpoint: &str,
+        body: Option<Value>,
+    ) -> Result<Response, Box<dyn Error>> {
+        let mut context = RequestContext {
+            method,
+            path: path.to_string(),
+            endpoint: endpoint.to_string(),
+            headers: Vec::new(),
+            body,
+        };
+        if context.body.is_some() {
+            context.set_header("Content-Type", "application/json");
+        }
+
+        let span = tracing::info_span!(
+            "zainpay.request",
+            method = method.as_str(),
+            path = %endpoint,
+            status = field::Empty,
+            latency_ms = field::Empty,
+            code = field::Empty,
+            retries = field::Empty,
+        );
+
+        async {
+            let mut short_circuit = None;
+            for middleware in &self.middleware {
+                match middleware.before_request(&mut context).await {
+                    Ok(Flow::Continue) => {}
+                    Ok(Flow::Respond(response)) => {
+                        short_circuit = Some(Ok(response));
+                        break;
+                    }
+                    Err(error) => {
+                        short_circuit = Some(Err(error));
+                        break;
+                    }
+                }
+            }
+
+            let outcome = match short_circuit {
+                Some(outcome) => outcome,
+                None => self.execute(&context).await,
+            };
+
+            match &outcome {
+                Ok(response) => {
+                    for middleware in self.middleware.iter().rev() {
+                        middleware.after_response(&context, response).await;
+                    }
+                }
+                Err(error) => {
+                    let error = error.to_string();
+                    for middleware in self.middleware.iter().rev() {
+                        middleware.on_error(&context, &error).await;
+                    }
+                }
+            }
+
+            outcome
+        }
+        .instrument(span)
+        .await
+        .map_err(|error| error as Box<dyn Error>)
+    }
+
+    async fn execute(
+        &self,
+        context: &RequestContext,
+    ) -> Result<Response, Box<dyn Error + Send + Sync>> {
+        if let Err(error) = self.check_key() {
+            tracing::warn!(error = %error, "zainpay request rejected");
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_error(context.method, &context.endpoint, "merchant_key", 0);
+            return Err(error.into());
+        }
+
+        let mut headers = context.headers.clone();
+        headers.push((
+            "Authorization".to_string(),
+            format!("Bearer {}", self.merchant_key.expose()),
+        ));
+        let request = HttpRequest {
+            method: context.method,
+            url: format!("{}/{}", self.base_url, context.path),
+            headers,
+            body: context.body.as_ref().map(Value::to_string),
+        };
+
+        if let Some(body) = &context.body {
+            self.log_body("request", body);
+        }
+
+        if let Some(breaker) = &self.circuit_breaker {
+            if let Err(error) = breaker.try_acquire() {
+                tracing::warn!(error = %error, "zainpay request rejected");
+                #[cfg(feature = "metrics")]
+                crate::metrics::record_error(context.method, &context.endpoint, "circuit_open", 0);
+                return Err(error.into());
+            }
+        }
+
+        let started = Instant::now();
+        let mut retries = 0;
+        let outcome = self.dispatch(context, &request, &mut retries).await;
+
+        if let Some(breaker) = &self.circuit_breaker {
+            match &outcome {
+                Ok(http_response) if http_response.status < 500 => breaker.record_success(),
+                _ => breaker.record_failure(),
+            }
+        }
+        #[cfg(feature = "metrics")]
+        if outcome.is_err() {
+            crate::metrics::record_error(context.method, &context.endpoint, "transport", retries);
+        }
+        let http_response = outcome?;
+        let latency = started.elapsed();
+
+        let span = tracing::Span::current();
+        span.record("status", http_response.status);
+        span.record("latency_ms", latency.as_millis() as u64);
+        span.record("retries", retries);
+
+        let response = Response::new(http_response.status, &http_response.body);
+        if let Some(code) = response.get_code() {
+            span.record("code", code);
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_response(
+            context.method,
+            &context.endpoint,
+            http_response.status,
+            response.get_code(),
+            latency,
+            retries,
+        );
+        if let Some(decoded) = response.full_json() {
+            self.log_body("response", &serde_json::to_value(decoded)?);
+        }
+        tracing::debug!("zainpay request completed");
+
+        Ok(response)
+    }
+
+    /// Sends the request, retrying it while Zainpay answers 429 and retries remain.
+    async fn dispatch(
+        &self,
+        context: &RequestContext,
+        request: &HttpRequest,
+        retries: &mut u32,
+    ) -> Result<HttpResponse, Box<dyn Error + Send + Sync>> {
+        let group = EndpointGroup::for_path(&context.path);
+
+        loop {
+            self.rate_limiter.acquire(group).await;
+
+            let http_response = match self.transport.send(request.clone()).await {
+                Ok(http_response) => http_response,
+                Err(error) => {
+                    let error = scrub_transport_error(error, context, request);
+                    tracing::warn!(error = %error, "zainpay request failed");
+                    return Err(error);
+                }
+            };
+
+            if http_response.status == 429 && *retries < self.max_rate_limit_retries {
+                let wait = http_response
+                    .header("Retry-After")
+                    .and_then(parse_retry_after)
+                    .unwrap_or_else(|| Duration::from_secs(1 << *retries))
+                    .min(self.max_retry_after);
+                *retries += 1;
+                tracing::warn!(
+                    retry = *retries,
+                    wait_ms = wait.as_millis() as u64,
+                    "zainpay rate limited the request, retrying"
+                );
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+
+            return Ok(http_response);
+        }
+    }
+
+    fn log_body(&self, direction: &'static str, body: &Value) {
+        match self.body_logging {
+            BodyLogging::Off => {}
+            BodyLogging::Redacted => {
+                tracing::debug!(direction, body = %redact(body), "zainpay body")
+            }
+            BodyLogging::Full => tracing::debug!(direction, body = %body, "zainpay body"),
+        }
+    }
+}
+
+/// A transport error whose message quotes the request url has it replaced by the endpoint
+/// template, since the url may carry account numbers and other personal data. Other errors are
+/// kept as is.
+fn scrub_transport_error(
+    error: TransportError,
+    context: &RequestContext,
+    request: &HttpRequest,
+) -> TransportError {
+    let message = error.to_string();
+    let path = context.path.trim_start_matches('/');
+    let query = path.split_once('?').map(|(_, query)| query);
+    let leaks = message.contains(path) || query.is_some_and(|query| message.contains(query));
+    if !leaks {
+        return error;
+    }
+
+    let template = &context.endpoint;
+    let mut message = message
+        .replace(&request.url, template)
+        .replace(path, template);
+    if let Some(query) = query {
+        message = message.replace(query, "");
+    }
+    message.into()
+}
+
+/// The label of a request sent without an endpoint template: the first segment of the path,
+/// with every later segment replaced by `{}`. Codes and refs can be made of letters only, so no
+/// later segment can be trusted to be part of the endpoint.
+pub(crate) fn path_template(path: &str) -> String {
+    let path = path.split('?').next().unwrap_or_default();
+    path.trim_start_matches('/')
+        .split('/')
+        .enumerate()
+        .map(|(index, segment)| if index == 0 { segment } else { "{}" })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// An endpoint of the Zainpay API, see [`Engine::endpoint`].
+#[derive(Debug, Clone, Copy)]
+pub struct Endpoint<'a> {
+    engine: &'a Engine,
+    template: &'static str,
+}
+
+impl Endpoint<'_> {
+    pub async fn get(&self, path: &str) -> Result<Response, Box<dyn Error>> {
+        self.engine
+            .send(Method::Get, path, self.template, None)
+            .await
+    }
+
+    pub async fn post<T: serde::Serialize>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<Response, Box<dyn Error>> {
+        self.engine
+            .send(
+                Method::Post,
+                path,
+                self.template,
+                Some(serde_json::to_value(body)?),
+            )
+            .await
+    }
+
+    pub async fn patch<T: serde::Serialize>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<Response, Box<dyn Error>> {
+        self.engine
+            .send(
+                Method::Patch,
+                path,
+                self.template,
+                Some(serde_json::to_value(body)?),
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::RequestContext;
+    use crate::transport::MockTransport;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// Fails every request the way reqwest does, quoting the url.
+    #[derive(Debug)]
+    struct UnreachableTransport;
+
+    #[async_trait]
+    impl Transport for UnreachableTransport {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+            Err(format!("error sending request for url ({}): timed out", request.url).into())
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct Errors(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl Middleware for Errors {
+        async fn on_error(&self, _request: &RequestContext, error: &str) {
+            self.0.lock().unwrap().push(error.to_string());
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_after_is_capped() {
+        let transport =
+            Arc::new(MockTransport::new().handler(|_| {
+                Some(HttpResponse::new(429, "{}").with_header("Retry-After", "86400"))
+            }));
+        let engine = Engine::builder(Environment::Sandbox, "key")
+            .transport(transport.clone())
+            .max_rate_limit_retries(1)
+            .max_retry_after(Duration::from_secs(5))
+            .build();
+
+        let started = tokio::time::Instant::now();
+        let response = engine.get("zainbox/list").await.unwrap();
+
+        assert!(response.is_rate_limited());
+        assert_eq!(transport.requests().len(), 2);
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
+    }
+
+    /// Refuses every request in `before_request`.
+    #[derive(Debug)]
+    struct Refuse;
+
+    #[async_trait]
+    impl Middleware for Refuse {
+        async fn before_request(
+            &self,
+            _request: &mut RequestContext,
+        ) -> Result<Flow, Box<dyn Error + Send + Sync>> {
+            Err("refused".into())
+        }
+    }
+
+    #[tokio::test]
+    async fn a_refused_request_reaches_every_on_error() {
+        let first = Arc::new(Errors::default());
+        let last = Arc::new(Errors::default());
+        let transport = Arc::new(MockTransport::new());
+        let engine = Engine::builder(Environment::Sandbox, "key")
+            .transport(transport.clone())
+            .middleware(first.clone())
+            .middleware(Refuse)
+            .middleware(last.clone())
+            .build();
+
+        let error = engine.get("zainbox/list").await.unwrap_err();
+
+        assert_eq!(error.to_string(), "refused");
+        assert!(transport.requests().is_empty());
+        assert_eq!(*first.0.lock().unwrap(), vec!["refused"]);
+        assert_eq!(*last.0.lock().unwrap(), vec!["refused"]);
+    }
+
+    #[tokio::test]
+    async fn transport_errors_do_not_quote_the_url() {
+        let errors = Arc::new(Errors::default());
+        let engine = Engine::builder(Environment::Sandbox, "key")
+            .transport(Arc::new(UnreachableTransport))
+            .middleware(errors.clone())
+            .build();
+
+        let error = engine
+            .get("bank/name-enquiry?bankCode=000013&accountNumber=0123456789")
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("timed out"), "{}", error);
+        assert!(!error.contains("0123456789"), "{}", error);
+        let errors = errors.0.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(!errors[0].contains("0123456789"), "{}", errors[0]);
+    }
+
+    #[derive(Debug, Default)]
+    struct Endpoints(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl Middleware for Endpoints {
+        async fn before_request(
+            &self,
+            request: &mut RequestContext,
+        ) -> Result<Flow, Box<dyn Error + Send + Sync>> {
+            self.0.lock().unwrap().push(request.endpoint.clone());
+            Ok(Flow::Continue)
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_are_labelled_with_their_endpoint_template() {
+        let endpoints = Arc::new(Endpoints::default());
+        let engine = Engine::builder(Environment::Sandbox, "key")
+            .transport(Arc::new(MockTransport::new()))
+            .middleware(endpoints.clone())
+            .build();
+
+        crate::zainbox::ZainboxService::new(engine.clone())
+            .get_zainbox_profile("THbfnDvKxo".to_string())
+            .await
+            .unwrap();
+        engine.get("zainbox/profile/THbfnDvKxo?x=1").await.unwrap();
+
+        assert_eq!(
+            *endpoints.0.lock().unwrap(),
+            ["zainbox/profile/{}", "zainbox/{}/{}"]
+        );
+    }
+
+    fn key_expiring_in(expires_in: chrono::Duration, claims: Value) -> String {
+        let mut claims = claims;
+        claims["exp"] = (chrono::Utc::now() + expires_in).timestamp().into();
+        crate::jwt::test_key(claims)
+    }
+
+    #[tokio::test]
+    async fn a_key_for_another_environment_is_refused() {
+        let key = crate::jwt::test_key(serde_json::json!({ "mode": "live" }));
+        let transport = Arc::new(MockTransport::new());
+        let engine = Engine::builder(Environment::Sandbox, &key)
+            .transport(transport.clone())
+            .build();
+
+        assert!(matches!(
+            engine.check_key(),
+            Err(ZainpayError::KeyEnvironmentMismatch {
+                key: Environment::Production,
+                engine: Environment::Sandbox,
+            })
+        ));
+        assert!(engine.get("zainbox/list").await.is_err());
+        assert!(transport.requests().is_empty());
+
+        let local = Engine::builder(Environment::Localbox, &key).build();
+        assert!(local.check_key().is_ok());
+    }
+
+    #[test]
+    fn an_expired_key_is_refused() {
+        let key = key_expiring_in(chrono::Duration::minutes(-1), serde_json::json!({}));
+        let engine = Engine::new(Environment::Sandbox, &key);
+        assert!(matches!(
+            engine.check_key(),
+            Err(ZainpayError::KeyExpired { .. })
+        ));
+    }
+
+    #[test]
+    fn a_key_expiring_within_the_margin_is_refused() {
+        let key = key_expiring_in(chrono::Duration::hours(1), serde_json::json!({}));
+
+        let engine = Engine::builder(Environment::Sandbox, &key)
+            .key_expiry_margin(Duration::from_secs(2 * 60 * 60))
+            .build();
+        assert!(matches!(
+            engine.check_key(),
+            Err(ZainpayError::KeyExpiring { .. })
+        ));
+
+        let engine = Engine::builder(Environment::Sandbox, &key)
+            .key_expiry_margin(Duration::from_secs(30 * 60))
+            .build();
+        assert!(engine.check_key().is_ok());
+    }
+
+    #[test]
+    fn a_key_expiring_soon_is_warned_about_once() {
+        let key = key_expiring_in(chrono::Duration::days(2), serde_json::json!({}));
+        let engine = Engine::new(Environment::Sandbox, &key);
+        let clone = engine.clone();
+
+        assert!(!engine.key_expiry_warned.load(Ordering::Relaxed));
+        assert!(engine.check_key().is_ok());
+        assert!(engine.key_expiry_warned.load(Ordering::Relaxed));
+        assert!(clone.check_key().is_ok());
+        assert!(clone.key_expiry_warned.load(Ordering::Relaxed));
+
+        let far = key_expiring_in(chrono::Duration::days(30), serde_json::json!({}));
+        let engine = Engine::new(Environment::Sandbox, &far);
+        assert!(engine.check_key().is_ok());
+        assert!(!engine.key_expiry_warned.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn keys_that_are_not_jwts_are_not_checked() {
+        let engine = Engine::new(Environment::Production, "sk_live_0123456789");
+        assert!(engine.key_claims().is_none());
+        assert!(engine.check_key().is_ok());
+    }
+
+    #[test]
+    fn debug_output_leaves_out_the_key_and_its_claims() {
+        let key = crate::jwt::test_key(serde_json::json!({ "email": "ops@merchant.ng" }));
+        let debug = format!("{:?}", Engine::new(Environment::Sandbox, &key));
+
+        assert!(!debug.contains(&key), "{}", debug);
+        assert!(!debug.contains("ops@merchant.ng"), "{}", debug);
     }
 }
@@ -1,5 +1,8 @@
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum Environment {
+    /// A Zainpay stand-in running on this machine, such as a mock server. Zainpay publishes no
+    /// local host, so `http://localhost:8080` is only a default; set the real address with
+    /// `EngineBuilder::base_url` or `ZAINPAY_BASE_URL`.
     Localbox,
     Sandbox,
     Production,
@@ -10,6 +13,24 @@ impl Environment {
         match self {
             Environment::Sandbox => "https://sandbox.zainpay.ng",
             Environment::Production => "https://api.zainpay.ng",
+            Environment::Localbox => "http://localhost:8080",
         }
     }
-}
\ No newline at end of file
+}
+
+impl std::str::FromStr for Environment {
+    type Err = String;
+
+    /// Parses `sandbox`, `production` (or `live`) and `localbox`, ignoring case.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "sandbox" => Ok(Environment::Sandbox),
+            "production" | "live" => Ok(Environment::Production),
+            "localbox" => Ok(Environment::Localbox),
+            other => Err(format!(
+                "unknown environment `{}`, expected sandbox, production or localbox",
+                other
+            )),
+        }
+    }
+}
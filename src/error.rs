@@ -0,0 +1,323 @@
+use crate::enviroment::Environment;
+use crate::response::Response;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::fmt;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ZainpayError {
+    #[error("zainpay request failed with status {status_code} (code {}): {description:?}", code.as_ref().map_or("-".to_string(), ToString::to_string))]
+    Api {
+        status_code: u16,
+        code: Option<ErrorCode>,
+        description: Option<String>,
+    },
+
+    #[error("zainpay response did not contain the expected `data`")]
+    UnexpectedData,
+
+    #[error("zainpay circuit breaker is open, retry in {retry_in:?}")]
+    CircuitOpen { retry_in: Duration },
+
+    #[error("no merchant registered for tenant `{0}`")]
+    UnknownTenant(String),
+
+    #[error("merchant key was issued for {key:?} but the engine targets {engine:?}")]
+    KeyEnvironmentMismatch {
+        key: Environment,
+        engine: Environment,
+    },
+
+    #[error("merchant key expired at {expires_at}")]
+    KeyExpired { expires_at: DateTime<Utc> },
+
+    #[error("merchant key expires at {expires_at}, within the configured expiry margin")]
+    KeyExpiring { expires_at: DateTime<Utc> },
+
+    #[error("idempotency key `{0}` was already used for a different transfer")]
+    IdempotencyConflict(String),
+
+    #[error("invalid date {0:?}, expected YYYY-MM-DD")]
+    InvalidDate(String),
+
+    #[error("period starts on {date_from}, after it ends on {date_to}")]
+    InvalidPeriod {
+        date_from: NaiveDate,
+        date_to: NaiveDate,
+    },
+
+    #[error("zainbox `{0}` has no known code; it was not created or could not be found")]
+    ZainboxCodeUnknown(String),
+}
+
+impl ZainpayError {
+    pub fn from_response(response: &Response) -> Self {
+        ZainpayError::Api {
+            status_code: response.get_status_code(),
+            code: response.error_code(),
+            description: response.get_description().map(str::to_string),
+        }
+    }
+
+    /// The Zainpay code of an API error.
+    pub fn error_code(&self) -> Option<&ErrorCode> {
+        match self {
+            ZainpayError::Api { code, .. } => code.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Whether sending the same request again may succeed: Zainpay failed with a retryable code,
+    /// or, without a catalogued code, with a server error or a rate limit, or the circuit breaker
+    /// is open. A code that is not final, such as a pending one, is never retryable.
+    ///
+    /// A server error does not prove a funds transfer was not made. Verify the transfer with
+    /// `BankService::verify_transfer` before sending it again, or send transfers through
+    /// `PaymentService`, which marks them unknown until `verify_transfer` settles them.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ZainpayError::Api {
+                status_code, code, ..
+            } => match code {
+                None | Some(ErrorCode::Other(_)) => *status_code >= 500 || *status_code == 429,
+                Some(code) if code.is_final() => code.is_retryable(),
+                Some(_) => false,
+            },
+            ZainpayError::CircuitOpen { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Zainpay's published table of response codes.
+pub const ERROR_CODES_URL: &str =
+    "https://zainpay.ng/developers/api-endpoints?section=response-codes";
+
+/// Zainpay's response codes. Transfer failures carry the NIBSS code of the destination bank.
+///
+/// A final code means the transaction will not change state. A code that is not final means it
+/// may still complete and must be verified before it is retried, or it may be paid twice. Codes
+/// missing from the table are kept as `Other`; read Zainpay's `description` for their meaning.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// `00`, or `200` on some endpoints, kept as sent.
+    Successful(String),
+    /// `21`: accepted and still being processed.
+    Pending,
+    /// `01`: the transaction status is unknown.
+    StatusUnknown,
+    /// `03`: invalid sender.
+    InvalidSender,
+    /// `04`: the request was refused, see the description.
+    RequestFailed,
+    /// `05`: do not honor.
+    DoNotHonor,
+    /// `06`: dormant account.
+    DormantAccount,
+    /// `07`: invalid account.
+    InvalidAccount,
+    /// `08`: account name mismatch.
+    AccountNameMismatch,
+    /// `09`: request processing in progress.
+    InProgress,
+    /// `12`: invalid transaction.
+    InvalidTransaction,
+    /// `13`: invalid amount.
+    InvalidAmount,
+    /// `25`: unable to locate record.
+    RecordNotFound,
+    /// `26`: duplicate transaction reference.
+    DuplicateReference,
+    /// `51`: insufficient balance.
+    InsufficientBalance,
+    /// `57`: transaction not permitted.
+    TransactionNotPermitted,
+    /// `61`: transfer limit exceeded.
+    TransferLimitExceeded,
+    /// `91`: the destination bank is not available.
+    BankUnavailable,
+    /// `96`: system malfunction.
+    SystemMalfunction,
+    /// A code missing from the table, kept as sent.
+    Other(String),
+}
+
+impl ErrorCode {
+    pub fn parse(code: &str) -> Self {
+        match code.trim() {
+            code @ ("00" | "200") => ErrorCode::Successful(code.to_string()),
+            "21" => ErrorCode::Pending,
+            "01" => ErrorCode::StatusUnknown,
+            "03" => ErrorCode::InvalidSender,
+            "04" => ErrorCode::RequestFailed,
+            "05" => ErrorCode::DoNotHonor,
+            "06" => ErrorCode::DormantAccount,
+            "07" => ErrorCode::InvalidAccount,
+            "08" => ErrorCode::AccountNameMismatch,
+            "09" => ErrorCode::InProgress,
+            "12" => ErrorCode::InvalidTransaction,
+            "13" => ErrorCode::InvalidAmount,
+            "25" => ErrorCode::RecordNotFound,
+            "26" => ErrorCode::DuplicateReference,
+            "51" => ErrorCode::InsufficientBalance,
+            "57" => ErrorCode::TransactionNotPermitted,
+            "61" => ErrorCode::TransferLimitExceeded,
+            "91" => ErrorCode::BankUnavailable,
+            "96" => ErrorCode::SystemMalfunction,
+            other => ErrorCode::Other(other.to_string()),
+        }
+    }
+
+    /// The code as Zainpay sent it.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ErrorCode::Successful(code) | ErrorCode::Other(code) => code,
+            ErrorCode::Pending => "21",
+            ErrorCode::StatusUnknown => "01",
+            ErrorCode::InvalidSender => "03",
+            ErrorCode::RequestFailed => "04",
+            ErrorCode::DoNotHonor => "05",
+            ErrorCode::DormantAccount => "06",
+            ErrorCode::InvalidAccount => "07",
+            ErrorCode::AccountNameMismatch => "08",
+            ErrorCode::InProgress => "09",
+            ErrorCode::InvalidTransaction => "12",
+            ErrorCode::InvalidAmount => "13",
+            ErrorCode::RecordNotFound => "25",
+            ErrorCode::DuplicateReference => "26",
+            ErrorCode::InsufficientBalance => "51",
+            ErrorCode::TransactionNotPermitted => "57",
+            ErrorCode::TransferLimitExceeded => "61",
+            ErrorCode::BankUnavailable => "91",
+            ErrorCode::SystemMalfunction => "96",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            ErrorCode::Successful(_) => "successful",
+            ErrorCode::Pending => "accepted, pending completion",
+            ErrorCode::StatusUnknown => "transaction status unknown",
+            ErrorCode::InvalidSender => "invalid sender",
+            ErrorCode::RequestFailed => "request failed",
+            ErrorCode::DoNotHonor => "do not honor",
+            ErrorCode::DormantAccount => "dormant account",
+            ErrorCode::InvalidAccount => "invalid account",
+            ErrorCode::AccountNameMismatch => "account name mismatch",
+            ErrorCode::InProgress => "request processing in progress",
+            ErrorCode::InvalidTransaction => "invalid transaction",
+            ErrorCode::InvalidAmount => "invalid amount",
+            ErrorCode::RecordNotFound => "unable to locate record",
+            ErrorCode::DuplicateReference => "duplicate transaction reference",
+            ErrorCode::InsufficientBalance => "insufficient balance",
+            ErrorCode::TransactionNotPermitted => "transaction not permitted",
+            ErrorCode::TransferLimitExceeded => "transfer limit exceeded",
+            ErrorCode::BankUnavailable => "destination bank not available",
+            ErrorCode::SystemMalfunction => "system malfunction",
+            ErrorCode::Other(_) => "uncatalogued response code",
+        }
+    }
+
+    /// Where Zainpay documents the code, `None` for codes missing from its table.
+    pub fn doc_url(&self) -> Option<&'static str> {
+        match self {
+            ErrorCode::Other(_) => None,
+            _ => Some(ERROR_CODES_URL),
+        }
+    }
+
+    /// Whether Zainpay accepted the request. A pending request may not have completed yet.
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, ErrorCode::Successful(_) | ErrorCode::Pending)
+    }
+
+    /// Whether Zainpay accepted the request and is still processing it.
+    pub fn is_pending(&self) -> bool {
+        matches!(self, ErrorCode::Pending)
+    }
+
+    /// Whether the transaction will not change state. Pending, unknown and in-progress codes may
+    /// still complete, and uncatalogued codes are never final.
+    pub fn is_final(&self) -> bool {
+        !matches!(
+            self,
+            ErrorCode::Pending
+                | ErrorCode::StatusUnknown
+                | ErrorCode::InProgress
+                | ErrorCode::Other(_)
+        )
+    }
+
+    /// Whether the request failed for a transient reason and can be sent again as is.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::BankUnavailable | ErrorCode::SystemMalfunction
+        )
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.as_str(), self.message())
+    }
+}
+
+impl From<&str> for ErrorCode {
+    fn from(code: &str) -> Self {
+        ErrorCode::parse(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_error(status_code: u16, code: &str) -> ZainpayError {
+        ZainpayError::Api {
+            status_code,
+            code: Some(ErrorCode::parse(code)),
+            description: None,
+        }
+    }
+
+    #[test]
+    fn codes_keep_the_value_zainpay_sent() {
+        for code in ["00", "200", "21", "01", "26", "51", "96", "000013"] {
+            assert_eq!(ErrorCode::parse(code).as_str(), code);
+        }
+        assert_eq!(
+            ErrorCode::parse("200"),
+            ErrorCode::Successful("200".to_string())
+        );
+        assert_eq!(ErrorCode::parse("51"), ErrorCode::InsufficientBalance);
+        assert_eq!(ErrorCode::parse("07"), ErrorCode::InvalidAccount);
+        assert_eq!(ErrorCode::parse("26"), ErrorCode::DuplicateReference);
+        assert_eq!(
+            ErrorCode::parse("51").to_string(),
+            "51 (insufficient balance)"
+        );
+        assert_eq!(ErrorCode::parse("51").doc_url(), Some(ERROR_CODES_URL));
+        assert_eq!(ErrorCode::parse("000013").doc_url(), None);
+    }
+
+    #[test]
+    fn only_transient_final_codes_are_retryable() {
+        assert!(ErrorCode::BankUnavailable.is_final() && ErrorCode::BankUnavailable.is_retryable());
+        assert!(ErrorCode::InsufficientBalance.is_final());
+        assert!(!ErrorCode::InsufficientBalance.is_retryable());
+        assert!(!ErrorCode::Pending.is_final() && !ErrorCode::StatusUnknown.is_final());
+        assert!(!ErrorCode::Other("99".to_string()).is_final());
+
+        assert!(api_error(400, "91").is_retryable());
+        assert!(api_error(500, "96").is_retryable());
+        assert!(!api_error(500, "51").is_retryable());
+        assert!(!api_error(500, "21").is_retryable());
+        assert!(!api_error(500, "01").is_retryable());
+        assert!(!api_error(503, "09").is_retryable());
+        assert!(api_error(502, "99").is_retryable());
+        assert!(api_error(429, "99").is_retryable());
+        assert!(!api_error(400, "99").is_retryable());
+    }
+}
@@ -0,0 +1,168 @@
+//! Local inspection of merchant keys.
+//!
+//! Zainpay merchant keys are JWTs. Their claims are decoded without verifying the signature, only
+//! Zainpay can do that, to tell which environment a key belongs to, whose it is and when it
+//! expires.
+
+use crate::enviroment::Environment;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Claims naming the environment a key was issued for, tried in order.
+const ENVIRONMENT_CLAIMS: &[&str] = &["environment", "env", "mode"];
+/// Claims identifying the merchant, tried in order.
+const MERCHANT_CLAIMS: &[&str] = &["merchantId", "merchant_id", "merchant", "sub", "email"];
+
+/// Claims of a merchant key. Its `Debug` output leaves out the merchant and the raw claims, which
+/// may hold the merchant's email or other details that must not end up in logs.
+#[derive(Clone, PartialEq)]
+pub struct KeyClaims {
+    /// Environment the key was issued for, `None` when the key does not say.
+    pub environment: Option<Environment>,
+    pub merchant: Option<String>,
+    pub issued_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Every claim of the key, including the ones above.
+    pub claims: Map<String, Value>,
+}
+
+impl KeyClaims {
+    /// Decode the claims of a merchant key. Returns `None` when the key is not a JWT.
+    pub fn decode(merchant_key: &str) -> Option<Self> {
+        let mut segments = merchant_key.trim().split('.');
+        let (_header, payload) = (segments.next()?, segments.next()?);
+        segments.next()?;
+
+        let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+        let claims = match serde_json::from_slice(&payload).ok()? {
+            Value::Object(claims) => claims,
+            _ => return None,
+        };
+
+        Some(Self {
+            environment: ENVIRONMENT_CLAIMS
+                .iter()
+                .filter_map(|name| claims.get(*name)?.as_str())
+                .find_map(parse_environment),
+            merchant: MERCHANT_CLAIMS
+                .iter()
+                .find_map(|name| match claims.get(*name)? {
+                    Value::String(merchant) => Some(merchant.clone()),
+                    Value::Number(merchant) => Some(merchant.to_string()),
+                    _ => None,
+                }),
+            issued_at: timestamp(&claims, "iat"),
+            expires_at: timestamp(&claims, "exp"),
+            claims,
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+
+    /// Time left before the key expires, negative once it has. `None` when it never expires.
+    pub fn expires_in(&self) -> Option<chrono::Duration> {
+        self.expires_at.map(|expires_at| expires_at - Utc::now())
+    }
+}
+
+impl fmt::Debug for KeyClaims {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyClaims")
+            .field("environment", &self.environment)
+            .field("merchant", &self.merchant.as_ref().map(|_| "[REDACTED]"))
+            .field("issued_at", &self.issued_at)
+            .field("expires_at", &self.expires_at)
+            .field("claims", &self.claims.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+fn parse_environment(value: &str) -> Option<Environment> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "test" | "staging" => Some(Environment::Sandbox),
+        value => value.parse().ok(),
+    }
+}
+
+fn timestamp(claims: &Map<String, Value>, name: &str) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(claims.get(name)?.as_i64()?, 0)
+}
+
+/// An unsigned merchant key carrying `claims`, for tests.
+#[cfg(test)]
+pub(crate) fn test_key(claims: Value) -> String {
+    format!(
+        "{}.{}.signature",
+        URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_each_environment_claim_name() {
+        let cases = [
+            (
+                json!({ "environment": "production" }),
+                Environment::Production,
+            ),
+            (json!({ "env": "live" }), Environment::Production),
+            (json!({ "mode": "test" }), Environment::Sandbox),
+            (
+                json!({ "env": "unknown", "mode": "sandbox" }),
+                Environment::Sandbox,
+            ),
+        ];
+        for (claims, environment) in cases {
+            let decoded = KeyClaims::decode(&test_key(claims.clone())).unwrap();
+            assert_eq!(decoded.environment, Some(environment), "{}", claims);
+        }
+        let decoded = KeyClaims::decode(&test_key(json!({ "sub": "m-1" }))).unwrap();
+        assert_eq!(decoded.environment, None);
+    }
+
+    #[test]
+    fn reads_the_merchant_and_timestamps() {
+        let key = test_key(json!({
+            "merchantId": 42,
+            "email": "ops@merchant.ng",
+            "iat": 1_700_000_000,
+            "exp": 1_700_086_400,
+        }));
+        let claims = KeyClaims::decode(&key).unwrap();
+
+        assert_eq!(claims.merchant.as_deref(), Some("42"));
+        assert_eq!(claims.issued_at, DateTime::from_timestamp(1_700_000_000, 0));
+        assert_eq!(
+            claims.expires_at,
+            DateTime::from_timestamp(1_700_086_400, 0)
+        );
+        assert!(claims.is_expired());
+    }
+
+    #[test]
+    fn keys_that_are_not_jwts_have_no_claims() {
+        assert_eq!(KeyClaims::decode("sk_test_0123456789"), None);
+        assert_eq!(KeyClaims::decode("a.bm90IGpzb24.c"), None);
+    }
+
+    #[test]
+    fn debug_output_leaves_out_merchant_details() {
+        let key = test_key(json!({ "email": "ops@merchant.ng", "phone": "08012345678" }));
+        let debug = format!("{:?}", KeyClaims::decode(&key).unwrap());
+
+        assert!(!debug.contains("ops@merchant.ng"), "{}", debug);
+        assert!(!debug.contains("08012345678"), "{}", debug);
+        assert!(debug.contains("phone"), "{}", debug);
+    }
+}
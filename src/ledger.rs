@@ -0,0 +1,493 @@
+//! Double-entry bookkeeping for Zainpay movements.
+//!
+//! Each transaction of a zainbox or virtual account history is classified as a [`MovementKind`]
+//! and turned into a balanced [`JournalEntry`] using the debit and credit accounts the
+//! [`ChartOfAccounts`] maps that kind to. Entries are handed to a [`LedgerStore`], so they can be
+//! written to any accounting system. Amounts are in kobo.
+
+use crate::engine::Engine;
+use crate::export::{ExportService, HistoryPager, HistorySource};
+use crate::models::model::Transaction;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LedgerError {
+    #[error("journal entry {id} does not balance: {debits} debited, {credits} credited")]
+    Unbalanced {
+        id: String,
+        debits: i64,
+        credits: i64,
+    },
+
+    #[error("transaction {reference} has no valid date: {date:?}")]
+    InvalidDate { reference: String, date: String },
+
+    #[error("ledger store failed: {0}")]
+    Store(#[source] Box<dyn Error + Send + Sync>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MovementKind {
+    Deposit,
+    CardPayment,
+    Transfer,
+    Fee,
+    Settlement,
+}
+
+impl fmt::Display for MovementKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MovementKind::Deposit => "deposit",
+            MovementKind::CardPayment => "card_payment",
+            MovementKind::Transfer => "transfer",
+            MovementKind::Fee => "fee",
+            MovementKind::Settlement => "settlement",
+        })
+    }
+}
+
+/// The account debited and the account credited for one kind of movement.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountPair {
+    pub debit: String,
+    pub credit: String,
+}
+
+impl AccountPair {
+    pub fn new(debit: &str, credit: &str) -> Self {
+        Self {
+            debit: debit.to_string(),
+            credit: credit.to_string(),
+        }
+    }
+}
+
+/// Which accounts each kind of movement is posted to.
+///
+/// Every field has a default, so a chart read from a config file only needs the mappings that
+/// differ:
+///
+/// ```toml
+/// [deposit]
+/// debit = "1010 Zainpay wallet"
+/// credit = "4000 Sales"
+///
+/// [transaction_types]
+/// reversal = "deposit"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChartOfAccounts {
+    pub deposit: AccountPair,
+    pub card_payment: AccountPair,
+    pub transfer: AccountPair,
+    pub fee: AccountPair,
+    pub settlement: AccountPair,
+
+    /// Kinds for transaction types the built-in rules do not know, keyed by lowercase type.
+    /// Also overrides the built-in rules.
+    pub transaction_types: HashMap<String, MovementKind>,
+}
+
+impl Default for ChartOfAccounts {
+    fn default() -> Self {
+        Self {
+            deposit: AccountPair::new("zainpay_wallet", "customer_receipts"),
+            card_payment: AccountPair::new("zainpay_wallet", "card_receipts"),
+            transfer: AccountPair::new("transfers_out", "zainpay_wallet"),
+            fee: AccountPair::new("zainpay_fees", "zainpay_wallet"),
+            settlement: AccountPair::new("bank", "zainpay_wallet"),
+            transaction_types: HashMap::new(),
+        }
+    }
+}
+
+impl ChartOfAccounts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Post `kind` to `debit` and `credit`.
+    pub fn map(mut self, kind: MovementKind, debit: &str, credit: &str) -> Self {
+        *self.accounts_mut(kind) = AccountPair::new(debit, credit);
+        self
+    }
+
+    /// Treat transactions of `transaction_type` as `kind`.
+    pub fn classify_as(mut self, transaction_type: &str, kind: MovementKind) -> Self {
+        self.transaction_types
+            .insert(transaction_type.to_lowercase(), kind);
+        self
+    }
+
+    pub fn accounts(&self, kind: MovementKind) -> &AccountPair {
+        match kind {
+            MovementKind::Deposit => &self.deposit,
+            MovementKind::CardPayment => &self.card_payment,
+            MovementKind::Transfer => &self.transfer,
+            MovementKind::Fee => &self.fee,
+            MovementKind::Settlement => &self.settlement,
+        }
+    }
+
+    fn accounts_mut(&mut self, kind: MovementKind) -> &mut AccountPair {
+        match kind {
+            MovementKind::Deposit => &mut self.deposit,
+            MovementKind::CardPayment => &mut self.card_payment,
+            MovementKind::Transfer => &mut self.transfer,
+            MovementKind::Fee => &mut self.fee,
+            MovementKind::Settlement => &mut self.settlement,
+        }
+    }
+
+    /// The kind of `transaction`, or `None` when it should not be posted: its type is unknown,
+    /// or its status says it failed or was reversed.
+    pub fn classify(&self, transaction: &Transaction) -> Option<MovementKind> {
+        if let Some(status) = &transaction.status {
+            let status = status.to_lowercase();
+            if status.contains("fail") || status.contains("revers") {
+                return None;
+            }
+        }
+
+        let transaction_type = transaction.transaction_type.to_lowercase();
+        if let Some(kind) = self.transaction_types.get(&transaction_type) {
+            return Some(*kind);
+        }
+        match transaction_type.as_str() {
+            "deposit" | "credit" => {
+                let by_card = transaction
+                    .payment_channel
+                    .as_deref()
+                    .is_some_and(|channel| channel.eq_ignore_ascii_case("card"));
+                Some(if by_card {
+                    MovementKind::CardPayment
+                } else {
+                    MovementKind::Deposit
+                })
+            }
+            "card" | "card_payment" | "cardpayment" => Some(MovementKind::CardPayment),
+            "transfer" | "withdrawal" | "debit" => Some(MovementKind::Transfer),
+            "fee" | "charge" | "commission" => Some(MovementKind::Fee),
+            "settlement" => Some(MovementKind::Settlement),
+            _ => None,
+        }
+    }
+
+    /// The journal entry for `transaction`, or `Ok(None)` when `classify` skips it.
+    pub fn journal(&self, transaction: &Transaction) -> Result<Option<JournalEntry>, LedgerError> {
+        let Some(kind) = self.classify(transaction) else {
+            return Ok(None);
+        };
+        let date = transaction.date().ok_or_else(|| LedgerError::InvalidDate {
+            reference: transaction.transaction_ref.clone(),
+            date: transaction.transaction_date.clone(),
+        })?;
+
+        let accounts = self.accounts(kind);
+        let amount = transaction.amount.abs();
+        Ok(Some(JournalEntry {
+            id: format!("{}:{}", kind, transaction.transaction_ref),
+            date,
+            kind,
+            reference: transaction.transaction_ref.clone(),
+            memo: transaction
+                .narration
+                .clone()
+                .unwrap_or_else(|| transaction.transaction_type.clone()),
+            lines: vec![
+                JournalLine::debit(&accounts.debit, amount),
+                JournalLine::credit(&accounts.credit, amount),
+            ],
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalLine {
+    pub account: String,
+    pub debit: i64,
+    pub credit: i64,
+}
+
+impl JournalLine {
+    pub fn debit(account: &str, amount: i64) -> Self {
+        Self {
+            account: account.to_string(),
+            debit: amount,
+            credit: 0,
+        }
+    }
+
+    pub fn credit(account: &str, amount: i64) -> Self {
+        Self {
+            account: account.to_string(),
+            debit: 0,
+            credit: amount,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// `<kind>:<transaction ref>`. A fee can share the reference of the transfer it was charged
+    /// on, so the kind is part of the id.
+    pub id: String,
+    pub date: NaiveDate,
+    pub kind: MovementKind,
+    pub reference: String,
+    pub memo: String,
+    pub lines: Vec<JournalLine>,
+}
+
+impl JournalEntry {
+    pub fn total_debits(&self) -> i64 {
+        self.lines.iter().map(|line| line.debit).sum()
+    }
+
+    pub fn total_credits(&self) -> i64 {
+        self.lines.iter().map(|line| line.credit).sum()
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.total_debits() == self.total_credits()
+    }
+}
+
+/// Where journal entries are written. Implement it over your own accounting system.
+#[async_trait]
+pub trait LedgerStore: Send + Sync {
+    /// Record `entry`, unless an entry with the same id was recorded before. Returns whether it
+    /// was recorded, so posting the same history twice is harmless.
+    async fn post(&self, entry: &JournalEntry) -> Result<bool, Box<dyn Error + Send + Sync>>;
+}
+
+/// A `LedgerStore` that keeps entries in memory.
+#[derive(Debug, Default)]
+pub struct MemoryLedger {
+    entries: Mutex<Vec<JournalEntry>>,
+}
+
+impl MemoryLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Debits less credits posted to `account`.
+    pub fn balance(&self, account: &str) -> i64 {
+        self.trial_balance().get(account).copied().unwrap_or(0)
+    }
+
+    /// Debits less credits of every account posted to. Sums to zero.
+    pub fn trial_balance(&self) -> BTreeMap<String, i64> {
+        let mut balances = BTreeMap::new();
+        for entry in self.entries() {
+            for line in entry.lines {
+                *balances.entry(line.account).or_insert(0) += line.debit - line.credit;
+            }
+        }
+        balances
+    }
+}
+
+#[async_trait]
+impl LedgerStore for MemoryLedger {
+    async fn post(&self, entry: &JournalEntry) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let mut entries = self
+            .entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if entries.iter().any(|posted| posted.id == entry.id) {
+            return Ok(false);
+        }
+        entries.push(entry.clone());
+        Ok(true)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PostingReport {
+    pub posted: usize,
+    /// Entries the store already had.
+    pub duplicates: usize,
+    /// Transactions `ChartOfAccounts::classify` did not map to a movement.
+    pub skipped: Vec<Transaction>,
+    /// Days whose history filled a whole page, so some transactions may not have been posted.
+    pub truncated_days: Vec<NaiveDate>,
+}
+
+pub struct LedgerService {
+    engine: Engine,
+    chart: ChartOfAccounts,
+}
+
+impl LedgerService {
+    pub fn new(engine: Engine, chart: ChartOfAccounts) -> Self {
+        Self { engine, chart }
+    }
+
+    pub fn chart(&self) -> &ChartOfAccounts {
+        &self.chart
+    }
+
+    /**
+     * Post transactions to a ledger store
+     *
+     * Every entry is checked to balance before it is handed to the store.
+     *
+     * @param store LedgerStore
+     * @param transactions &[Transaction]
+     *
+     * @return PostingReport
+     * @throws LedgerError
+     */
+    pub async fn post(
+        &self,
+        store: &dyn LedgerStore,
+        transactions: &[Transaction],
+    ) -> Result<PostingReport, LedgerError> {
+        let mut report = PostingReport::default();
+        let mut seen = HashSet::new();
+        for transaction in transactions {
+            let Some(entry) = self.chart.journal(transaction)? else {
+                report.skipped.push(transaction.clone());
+                continue;
+            };
+            if !entry.is_balanced() {
+                return Err(LedgerError::Unbalanced {
+                    debits: entry.total_debits(),
+                    credits: entry.total_credits(),
+                    id: entry.id,
+                });
+            }
+            if !seen.insert(entry.id.clone()) {
+                report.duplicates += 1;
+                continue;
+            }
+
+            if store.post(&entry).await.map_err(LedgerError::Store)? {
+                report.posted += 1;
+            } else {
+                report.duplicates += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    /**
+     * Post the transaction history of a zainbox or virtual account to a ledger store
+     *
+     * Days the history could not be fetched completely are listed in `truncated_days`.
+     *
+     * @param store LedgerStore
+     * @param source HistorySource
+     * @param date_from NaiveDate
+     * @param date_to NaiveDate
+     *
+     * @return PostingReport
+     * @throws Box<dyn Error>
+     */
+    pub async fn post_history(
+        &self,
+        store: &dyn LedgerStore,
+        source: &HistorySource,
+        date_from: NaiveDate,
+        date_to: NaiveDate,
+    ) -> Result<PostingReport, Box<dyn Error>> {
+        let exports = ExportService::new(self.engine.clone());
+        let mut pager = HistoryPager::new(&exports, source, date_from, date_to, 7, 500)?;
+
+        let mut report = PostingReport::default();
+        while let Some(transactions) = pager.next().await? {
+            let page = self.post(store, &transactions).await?;
+            report.posted += page.posted;
+            report.duplicates += page.duplicates;
+            report.skipped.extend(page.skipped);
+        }
+        report.truncated_days = pager.truncated_days;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enviroment::Environment;
+    use crate::transport::{HttpResponse, MockTransport};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn service() -> LedgerService {
+        let body = json!({
+            "code": "00",
+            "data": [
+                { "txnRef": "DEP-1", "txnType": "deposit", "amount": "5000", "txnDate": "2024-03-01T09:00:00" },
+                { "txnRef": "TRF-1", "txnType": "transfer", "amount": "1500", "txnDate": "2024-03-01T10:00:00" },
+                { "txnRef": "TRF-1", "txnType": "fee", "amount": "10", "txnDate": "2024-03-01T10:00:00" },
+            ],
+        })
+        .to_string();
+        let transport = MockTransport::new().handler(move |_| Some(HttpResponse::new(200, &body)));
+        let engine = Engine::builder(Environment::Sandbox, "key")
+            .transport(Arc::new(transport))
+            .build();
+        LedgerService::new(engine, ChartOfAccounts::default())
+    }
+
+    #[tokio::test]
+    async fn posts_a_transfer_and_the_fee_sharing_its_reference() {
+        let ledger = MemoryLedger::new();
+        let source = HistorySource::Zainbox("THbfnDvK5o".to_string());
+        let day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+
+        let report = service()
+            .post_history(&ledger, &source, day, day)
+            .await
+            .unwrap();
+
+        assert_eq!(report.posted, 3);
+        assert!(report.truncated_days.is_empty());
+        let ids: Vec<String> = ledger.entries().into_iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, ["deposit:DEP-1", "transfer:TRF-1", "fee:TRF-1"]);
+        assert_eq!(ledger.balance("zainpay_wallet"), 5000 - 1500 - 10);
+        assert_eq!(ledger.balance("zainpay_fees"), 10);
+    }
+
+    #[tokio::test]
+    async fn entries_balance_and_reposting_is_harmless() {
+        let ledger = MemoryLedger::new();
+        let source = HistorySource::Zainbox("THbfnDvK5o".to_string());
+        let day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let service = service();
+
+        service
+            .post_history(&ledger, &source, day, day)
+            .await
+            .unwrap();
+        let again = service
+            .post_history(&ledger, &source, day, day)
+            .await
+            .unwrap();
+
+        assert_eq!(again.posted, 0);
+        assert_eq!(again.duplicates, 3);
+        assert!(ledger.entries().iter().all(JournalEntry::is_balanced));
+        assert_eq!(ledger.trial_balance().values().sum::<i64>(), 0);
+    }
+}
@@ -1,10 +1,36 @@
+#![allow(clippy::too_many_arguments)]
+
 pub mod bank;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod card;
+pub mod cassette;
+pub mod circuit_breaker;
+pub mod config;
 pub mod engine;
 pub mod enviroment;
+pub mod error;
+pub mod export;
+pub mod jwt;
+pub mod ledger;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod middleware;
 pub mod models;
+pub mod payments;
+pub mod provision;
+pub mod rate_limit;
+pub mod reconcile;
+pub mod recovery;
 pub mod response;
+pub mod secret;
 pub mod settlement;
+pub mod simulator;
+pub mod statement;
+pub mod store;
+pub mod tenant;
+pub mod transport;
 pub mod utils;
 pub mod virtual_account;
+pub mod webhook;
 pub mod zainbox;
@@ -0,0 +1,216 @@
+//! Request metrics, recorded through the `metrics` crate facade when the `metrics` feature is on.
+//!
+//! Install any `metrics` recorder (e.g. a Prometheus exporter) to collect them. Every metric is
+//! labelled with `method` and `endpoint`, the template the service built the path from, e.g.
+//! `zainbox/profile/{}`, so codes, account numbers and refs never become label values.
+
+use crate::transport::Method;
+use std::time::Duration;
+
+/// Counter of completed requests, also labelled with the HTTP `status` and Zainpay `code`.
+pub const REQUESTS_TOTAL: &str = "zainpay_requests_total";
+/// Histogram of request latency in seconds, retries included.
+pub const REQUEST_DURATION_SECONDS: &str = "zainpay_request_duration_seconds";
+/// Counter of requests that got no response, labelled with the error `kind`.
+pub const REQUEST_ERRORS_TOTAL: &str = "zainpay_request_errors_total";
+/// Counter of retries after HTTP 429.
+pub const REQUEST_RETRIES_TOTAL: &str = "zainpay_request_retries_total";
+
+pub(crate) fn record_response(
+    method: Method,
+    endpoint: &str,
+    status: u16,
+    code: Option<&str>,
+    latency: Duration,
+    retries: u32,
+) {
+    let method = method.as_str();
+    let endpoint = endpoint.to_string();
+
+    ::metrics::counter!(
+        REQUESTS_TOTAL,
+        "method" => method,
+        "endpoint" => endpoint.clone(),
+        "status" => status.to_string(),
+        "code" => code.unwrap_or("none").to_string(),
+    )
+    .increment(1);
+    ::metrics::histogram!(
+        REQUEST_DURATION_SECONDS,
+        "method" => method,
+        "endpoint" => endpoint.clone(),
+    )
+    .record(latency.as_secs_f64());
+    record_retries(method, endpoint, retries);
+}
+
+pub(crate) fn record_error(method: Method, endpoint: &str, kind: &'static str, retries: u32) {
+    let method = method.as_str();
+    let endpoint = endpoint.to_string();
+
+    ::metrics::counter!(
+        REQUEST_ERRORS_TOTAL,
+        "method" => method,
+        "endpoint" => endpoint.clone(),
+        "kind" => kind,
+    )
+    .increment(1);
+    record_retries(method, endpoint, retries);
+}
+
+fn record_retries(method: &'static str, endpoint: String, retries: u32) {
+    if retries > 0 {
+        ::metrics::counter!(
+            REQUEST_RETRIES_TOTAL,
+            "method" => method,
+            "endpoint" => endpoint,
+        )
+        .increment(retries as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+        SharedString, Unit,
+    };
+    use std::sync::{Arc, Mutex};
+
+    /// A metric name, its labels and the value recorded.
+    type Sample = (String, Vec<(String, String)>, f64);
+    type Log = Arc<Mutex<Vec<Sample>>>;
+
+    /// Records every increment and observation with the name and labels of its metric.
+    #[derive(Default)]
+    struct TestRecorder {
+        log: Log,
+    }
+
+    struct Handle {
+        key: Key,
+        log: Log,
+    }
+
+    impl Handle {
+        fn push(&self, value: f64) {
+            let labels = self
+                .key
+                .labels()
+                .map(|label| (label.key().to_string(), label.value().to_string()))
+                .collect();
+            self.log
+                .lock()
+                .unwrap()
+                .push((self.key.name().to_string(), labels, value));
+        }
+    }
+
+    impl CounterFn for Handle {
+        fn increment(&self, value: u64) {
+            self.push(value as f64);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.push(value as f64);
+        }
+    }
+
+    impl HistogramFn for Handle {
+        fn record(&self, value: f64) {
+            self.push(value);
+        }
+    }
+
+    impl TestRecorder {
+        fn handle(&self, key: &Key) -> Arc<Handle> {
+            Arc::new(Handle {
+                key: key.clone(),
+                log: self.log.clone(),
+            })
+        }
+
+        fn recorded(&self) -> Vec<Sample> {
+            self.log.lock().unwrap().clone()
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.handle(key))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.handle(key))
+        }
+    }
+
+    fn label<'a>(labels: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        labels
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn responses_are_counted_timed_and_labelled_with_the_endpoint() {
+        let recorder = TestRecorder::default();
+        ::metrics::with_local_recorder(&recorder, || {
+            record_response(
+                Method::Get,
+                "zainbox/profile/{}",
+                200,
+                Some("00"),
+                Duration::from_millis(250),
+                2,
+            );
+        });
+
+        let recorded = recorder.recorded();
+        let names: Vec<&str> = recorded.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                REQUESTS_TOTAL,
+                REQUEST_DURATION_SECONDS,
+                REQUEST_RETRIES_TOTAL
+            ]
+        );
+        let (_, labels, _) = &recorded[0];
+        assert_eq!(label(labels, "method"), Some("GET"));
+        assert_eq!(label(labels, "endpoint"), Some("zainbox/profile/{}"));
+        assert_eq!(label(labels, "status"), Some("200"));
+        assert_eq!(label(labels, "code"), Some("00"));
+        assert_eq!(recorded[1].2, 0.25);
+        assert_eq!(recorded[2].2, 2.0);
+    }
+
+    #[test]
+    fn errors_are_counted_by_kind_and_retries_only_when_there_were_some() {
+        let recorder = TestRecorder::default();
+        ::metrics::with_local_recorder(&recorder, || {
+            record_error(Method::Post, "bank/transfer", "transport", 0);
+            record_response(Method::Post, "bank/transfer", 502, None, Duration::ZERO, 0);
+        });
+
+        let recorded = recorder.recorded();
+        assert_eq!(recorded.len(), 3);
+        let (name, labels, value) = &recorded[0];
+        assert_eq!(name, REQUEST_ERRORS_TOTAL);
+        assert_eq!(label(labels, "kind"), Some("transport"));
+        assert_eq!(*value, 1.0);
+        assert_eq!(label(&recorded[1].1, "code"), Some("none"));
+        assert!(recorded
+            .iter()
+            .all(|(name, _, _)| name != REQUEST_RETRIES_TOTAL));
+    }
+}
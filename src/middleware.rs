@@ -0,0 +1,83 @@
+use crate::response::Response;
+use crate::transport::Method;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// A request on its way to Zainpay, as seen by middleware.
+///
+/// The `Authorization` header is added after the chain has run, so middleware never sees the
+/// merchant key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestContext {
+    pub method: Method,
+    /// Path relative to the base url, including the query string.
+    pub path: String,
+    /// Endpoint template of the request, e.g. `zainbox/profile/{}`, free of codes and refs.
+    pub endpoint: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Value>,
+}
+
+impl RequestContext {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Set a header, replacing any existing value.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+}
+
+pub enum Flow {
+    Continue,
+    /// Skip the rest of the chain and the HTTP call, and answer with this response.
+    Respond(Response),
+}
+
+/// Hooks run around every request an `Engine` sends.
+///
+/// `before_request` hooks run in registration order, `after_response` and `on_error` hooks in
+/// reverse order. Every registered middleware sees the outcome, even when an earlier one
+/// short-circuited the request or refused it with an error, which is passed to `on_error`.
+#[async_trait]
+pub trait Middleware: fmt::Debug + Send + Sync {
+    async fn before_request(
+        &self,
+        _request: &mut RequestContext,
+    ) -> Result<Flow, Box<dyn Error + Send + Sync>> {
+        Ok(Flow::Continue)
+    }
+
+    async fn after_response(&self, _request: &RequestContext, _response: &Response) {}
+
+    /// Called when the request failed without a response, e.g. on a network error.
+    async fn on_error(&self, _request: &RequestContext, _error: &str) {}
+}
+
+/// Lets one middleware instance be shared, e.g. by the engines of a `MerchantRegistry`.
+#[async_trait]
+impl<M: Middleware + ?Sized> Middleware for Arc<M> {
+    async fn before_request(
+        &self,
+        request: &mut RequestContext,
+    ) -> Result<Flow, Box<dyn Error + Send + Sync>> {
+        (**self).before_request(request).await
+    }
+
+    async fn after_response(&self, request: &RequestContext, response: &Response) {
+        (**self).after_response(request, response).await
+    }
+
+    async fn on_error(&self, request: &RequestContext, error: &str) {
+        (**self).on_error(request, error).await
+    }
+}
@@ -1,17 +1,27 @@
-#[derive(Debug, serde::Deserialize)]
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct ZainboxInfo {
     pub name: String,
     pub codeName: String,
     pub callbackUrl: String,
     pub isActive: bool,
+    #[serde(default)]
+    pub emailNotification: Option<Secret<String>>,
+    #[serde(default, deserialize_with = "tags::deserialize")]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub allowAutoInternalTransfer: Option<bool>,
 }
 
+use crate::secret::Secret;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize)]
 pub struct CreateZainboxRequest {
     pub name: String,
-    pub email_notification: String,
+    pub email_notification: Secret<String>,
     pub callback_url: String,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -27,20 +37,275 @@ pub struct CreateZainboxRequest {
     pub allow_auto_internal_transfer: Option<bool>,
 }
 
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SettlementAccount {
-    account_number: String,
+    account_number: Secret<String>,
     bank_code: String,
-    percentage: String,  // Stored as String to match PHP's strval conversion
+    percentage: String, // Stored as String to match PHP's strval conversion
 }
 
 impl SettlementAccount {
     pub fn new(account_number: String, bank_code: String, percentage: f64) -> Self {
         Self {
-            account_number,
+            account_number: Secret::new(account_number),
             bank_code,
             percentage: percentage.to_string(),
         }
     }
 }
+
+/// Payload of a virtual account creation. `Debug` redacts the BVN and the holder's personal details.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateVirtualAccountRequest {
+    pub bank_type: String,
+    pub first_name: Secret<String>,
+    pub bvn: Secret<String>,
+    pub last_name: Secret<String>,
+    pub email: Secret<String>,
+    pub mobile: Secret<String>,
+    pub dob: Secret<String>,
+    pub gender: String,
+    pub address: Secret<String>,
+    pub title: String,
+    pub state: String,
+    pub zainbox_code: String,
+}
+
+/// Payload of a funds transfer. `Debug` redacts both account numbers. The amount is in kobo.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FundTransferRequest {
+    pub destination_account_number: Secret<String>,
+    pub destination_bank_code: String,
+    pub amount: String,
+    pub source_account_number: Secret<String>,
+    pub source_bank_code: String,
+    pub zainbox_code: String,
+    pub txn_ref: String,
+    pub narration: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+}
+
+/// A single entry of a zainbox or virtual account transaction history.
+///
+/// Amounts and balances are in kobo.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transaction {
+    #[serde(alias = "txnRef")]
+    pub transaction_ref: String,
+
+    #[serde(alias = "txnType")]
+    pub transaction_type: String,
+
+    #[serde(with = "kobo")]
+    pub amount: i64,
+
+    #[serde(alias = "txnDate")]
+    pub transaction_date: String,
+
+    #[serde(
+        default,
+        with = "kobo::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub balance: Option<i64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_name: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_number: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub narration: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_channel: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+impl Transaction {
+    /// Calendar date of the transaction, read from the leading `YYYY-MM-DD` of `transactionDate`.
+    pub fn date(&self) -> Option<chrono::NaiveDate> {
+        self.transaction_date
+            .get(..10)
+            .and_then(|date| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+    }
+
+    pub fn is_deposit(&self) -> bool {
+        self.transaction_type.eq_ignore_ascii_case("deposit")
+    }
+}
+
+/// Settlement plan of a zainbox, as returned by `get_settlement_info_for_zainbox`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementInfo {
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(default)]
+    pub schedule_type: Option<String>,
+
+    #[serde(default)]
+    pub schedule_period: Option<String>,
+
+    #[serde(default, alias = "settlementAccounts")]
+    pub settlement_account_list: Vec<SettlementAccountInfo>,
+
+    #[serde(default)]
+    pub status: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementAccountInfo {
+    pub account_number: Secret<String>,
+    pub bank_code: String,
+
+    #[serde(deserialize_with = "percentage::deserialize")]
+    pub percentage: f64,
+}
+
+/// A virtual account of a zainbox, as returned by `get_all_virtual_accounts_for_zainbox`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualAccountInfo {
+    #[serde(alias = "bankAccount")]
+    pub account_number: Secret<String>,
+
+    #[serde(default)]
+    pub account_name: Option<Secret<String>>,
+
+    #[serde(default, alias = "bankName")]
+    pub bank_type: Option<String>,
+}
+
+/// Wallet balance of a virtual account, as returned by `get_virtual_account_balance`. The balance
+/// is in kobo.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualAccountBalance {
+    #[serde(default)]
+    pub account_name: Option<String>,
+
+    #[serde(default)]
+    pub account_number: Option<String>,
+
+    #[serde(alias = "balance", alias = "availableBalance", with = "kobo")]
+    pub balance_amount: i64,
+
+    #[serde(default)]
+    pub bank_type: Option<String>,
+}
+
+/// Parse a kobo amount sent either as a number or as a string.
+pub(crate) fn parse_kobo(value: &serde_json::Value) -> Option<i64> {
+    kobo::from_value(value.clone()).ok()
+}
+
+/// Zainpay returns kobo amounts as numbers on some endpoints and as strings on others.
+mod kobo {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use serde_json::Value;
+
+    pub fn serialize<S: Serializer>(amount: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(*amount)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        from_value(Value::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+
+    pub(super) fn from_value(value: Value) -> Result<i64, String> {
+        match value {
+            Value::Number(number) => number
+                .as_i64()
+                .or_else(|| number.as_f64().map(|amount| amount.round() as i64))
+                .ok_or_else(|| format!("invalid kobo amount {}", number)),
+            Value::String(text) => text
+                .trim()
+                .parse::<f64>()
+                .map(|amount| amount.round() as i64)
+                .map_err(|_| format!("invalid kobo amount {:?}", text)),
+            other => Err(format!("invalid kobo amount {}", other)),
+        }
+    }
+
+    pub mod option {
+        use serde::{de::Error, Deserialize, Deserializer, Serializer};
+        use serde_json::Value;
+
+        pub fn serialize<S: Serializer>(
+            amount: &Option<i64>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match amount {
+                Some(amount) => serializer.serialize_some(amount),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<i64>, D::Error> {
+            match Value::deserialize(deserializer)? {
+                Value::Null => Ok(None),
+                value => super::from_value(value).map(Some).map_err(D::Error::custom),
+            }
+        }
+    }
+}
+
+/// Tags come back either as the comma separated string they are sent as, or as a list.
+mod tags {
+    use serde::{Deserialize, Deserializer};
+    use serde_json::Value;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<String>>, D::Error> {
+        Ok(match Value::deserialize(deserializer)? {
+            Value::String(tags) => Some(
+                tags.split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            Value::Array(tags) => Some(
+                tags.iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            _ => None,
+        })
+    }
+}
+
+/// Settlement percentages are sent as strings and may come back as either strings or numbers.
+mod percentage {
+    use serde::{de::Error, Deserialize, Deserializer};
+    use serde_json::Value;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::Number(number) => number
+                .as_f64()
+                .ok_or_else(|| D::Error::custom(format!("invalid percentage {}", number))),
+            Value::String(text) => text
+                .trim()
+                .trim_end_matches('%')
+                .parse()
+                .map_err(|_| D::Error::custom(format!("invalid percentage {:?}", text))),
+            other => Err(D::Error::custom(format!("invalid percentage {}", other))),
+        }
+    }
+}
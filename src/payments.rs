@@ -0,0 +1,506 @@
+//! Idempotent transfers and card payments, backed by a [`Store`].
+//!
+//! Every transfer is recorded under an idempotency key before it is sent, so retrying a request
+//! after a crash or a timeout never pays twice: the second call returns the recorded transfer
+//! instead. Transfers whose outcome is not known are settled by verifying them with Zainpay.
+
+use crate::bank::BankService;
+use crate::card::CardService;
+use crate::engine::Engine;
+use crate::error::ZainpayError;
+use crate::models::model::FundTransferRequest;
+use crate::response::Response;
+use crate::store::{
+    CardSession, CardSessionState, Store, StoreError, TransferIntent, TransferState,
+};
+use serde_json::Value;
+use std::error::Error;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum TransferOutcome {
+    /// Sent to Zainpay for the first time.
+    Sent {
+        intent: TransferIntent,
+        response: Response,
+    },
+    /// The idempotency key or txn ref was used before. Nothing was sent.
+    Duplicate(TransferIntent),
+}
+
+impl TransferOutcome {
+    pub fn intent(&self) -> &TransferIntent {
+        match self {
+            TransferOutcome::Sent { intent, .. } => intent,
+            TransferOutcome::Duplicate(intent) => intent,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CheckoutOutcome {
+    /// Initialized with Zainpay for the first time.
+    Started {
+        session: CardSession,
+        response: Response,
+    },
+    /// A session with this txn ref was started before. Nothing was sent.
+    Existing(CardSession),
+}
+
+impl CheckoutOutcome {
+    pub fn session(&self) -> &CardSession {
+        match self {
+            CheckoutOutcome::Started { session, .. } => session,
+            CheckoutOutcome::Existing(session) => session,
+        }
+    }
+}
+
+pub struct PaymentService {
+    engine: Engine,
+    store: Arc<dyn Store>,
+}
+
+impl PaymentService {
+    pub fn new(engine: Engine, store: Arc<dyn Store>) -> Self {
+        Self { engine, store }
+    }
+
+    pub fn store(&self) -> &Arc<dyn Store> {
+        &self.store
+    }
+
+    /**
+     * Make a funds transfer at most once per idempotency key
+     *
+     * The transfer is recorded before it is sent. A transfer rejected by Zainpay is marked failed;
+     * one whose response never arrived, arrived as a server error, or carries a pending code, is
+     * marked unknown and should be verified with `verify_transfer` before anything is retried.
+     *
+     * @param idempotency_key &str
+     * @param request FundTransferRequest
+     *
+     * @return TransferOutcome
+     * @throws Box<dyn Error>
+     */
+    pub async fn transfer(
+        &self,
+        idempotency_key: &str,
+        request: FundTransferRequest,
+    ) -> Result<TransferOutcome, Box<dyn Error>> {
+        let intent = TransferIntent::new(idempotency_key, request);
+        if let Some(existing) = self.store.insert_transfer(&intent).await? {
+            if existing.request != intent.request {
+                return Err(ZainpayError::IdempotencyConflict(idempotency_key.to_string()).into());
+            }
+            return Ok(TransferOutcome::Duplicate(existing));
+        }
+
+        let request = intent.request.clone();
+        let sent = BankService::new(self.engine.clone())
+            .make_fund_transfer(
+                request.destination_account_number.into_inner(),
+                request.destination_bank_code,
+                request.amount,
+                request.source_account_number.into_inner(),
+                request.source_bank_code,
+                request.zainbox_code,
+                request.txn_ref,
+                request.narration,
+                request.callback_url,
+            )
+            .await;
+
+        let response = match sent {
+            Ok(response) => response,
+            Err(error) => {
+                self.store
+                    .set_transfer_state(
+                        idempotency_key,
+                        TransferState::Unknown,
+                        Some(&error.to_string()),
+                    )
+                    .await?;
+                return Err(error);
+            }
+        };
+
+        let (state, last_error) = if response.has_succeeded() {
+            (TransferState::Submitted, None)
+        } else if response.get_status_code() >= 500
+            || response.error_code().is_some_and(|code| code.is_pending())
+        {
+            (TransferState::Unknown, Some(describe(&response)))
+        } else {
+            (TransferState::Failed, Some(describe(&response)))
+        };
+        let intent = self
+            .store
+            .set_transfer_state(idempotency_key, state, last_error.as_deref())
+            .await?;
+        Ok(TransferOutcome::Sent { intent, response })
+    }
+
+    /**
+     * Settle the state of a recorded transfer with Zainpay
+     *
+     * Transfers already final are returned as recorded. A transfer Zainpay has no record of is
+     * marked failed only while pending, as it was never sent. An unknown transfer may still be
+     * on its way through Zainpay, so it stays unknown until a verification reports it failed.
+     *
+     * @param txn_ref &str
+     *
+     * @return TransferIntent
+     * @throws Box<dyn Error>
+     */
+    pub async fn verify_transfer(&self, txn_ref: &str) -> Result<TransferIntent, Box<dyn Error>> {
+        let intent =
+            self.store
+                .transfer_by_ref(txn_ref)
+                .await?
+                .ok_or_else(|| StoreError::NotFound {
+                    kind: "transfer",
+                    id: txn_ref.to_string(),
+                })?;
+        if intent.state.is_final() {
+            return Ok(intent);
+        }
+
+        let response = BankService::new(self.engine.clone())
+            .verify_transfer(txn_ref.to_string())
+            .await?;
+
+        let update = if response.has_succeeded() {
+            let state = match reported_status(&response) {
+                Some(status) if is_success(&status) => TransferState::Succeeded,
+                Some(status) if is_failure(&status) => TransferState::Failed,
+                _ => TransferState::Submitted,
+            };
+            Some((state, None))
+        } else if response.get_status_code() == 404 && intent.state == TransferState::Pending {
+            Some((TransferState::Failed, Some(describe(&response))))
+        } else {
+            None
+        };
+
+        match update {
+            Some((state, last_error)) if state != intent.state || last_error.is_some() => Ok(self
+                .store
+                .set_transfer_state(&intent.idempotency_key, state, last_error.as_deref())
+                .await?),
+            _ => Ok(intent),
+        }
+    }
+
+    /**
+     * Verify every recorded transfer whose state is not final
+     *
+     * @return Vec<TransferIntent>
+     * @throws Box<dyn Error>
+     */
+    pub async fn verify_unsettled_transfers(&self) -> Result<Vec<TransferIntent>, Box<dyn Error>> {
+        let mut verified = Vec::new();
+        for intent in self.store.unsettled_transfers().await? {
+            verified.push(self.verify_transfer(intent.txn_ref()).await?);
+        }
+        Ok(verified)
+    }
+
+    /**
+     * Initialize a card payment at most once per txn ref
+     *
+     * The session is recorded before it is sent. One whose initialization never got an answer,
+     * or got a server error, is marked unknown, and starting it again sends the recorded session
+     * once more. Any other recorded session is returned as it is.
+     *
+     * @param session CardSession
+     *
+     * @return CheckoutOutcome
+     * @throws Box<dyn Error>
+     */
+    pub async fn start_card_payment(
+        &self,
+        session: CardSession,
+    ) -> Result<CheckoutOutcome, Box<dyn Error>> {
+        let mut session = match self.store.insert_card_session(&session).await? {
+            None => session,
+            Some(mut existing) if existing.state == CardSessionState::Unknown => {
+                existing.state = CardSessionState::Pending;
+                existing.updated_at = chrono::Utc::now();
+                self.store.save_card_session(&existing).await?;
+                existing
+            }
+            Some(existing) => return Ok(CheckoutOutcome::Existing(existing)),
+        };
+
+        let sent = CardService::new(self.engine.clone())
+            .initialize_new_payment(
+                session.amount.clone(),
+                session.txn_ref.clone(),
+                session.email_address.expose().clone(),
+                session.mobile_number.expose().clone(),
+                session.zainbox_code.clone(),
+                session.callback_url.clone(),
+            )
+            .await;
+
+        let response = match sent {
+            Ok(response) => response,
+            Err(error) => {
+                session.state = CardSessionState::Unknown;
+                session.last_error = Some(error.to_string());
+                session.updated_at = chrono::Utc::now();
+                self.store.save_card_session(&session).await?;
+                return Err(error);
+            }
+        };
+
+        if response.has_succeeded() {
+            session.state = CardSessionState::Initialized;
+            session.checkout_url = response.get_raw_data().and_then(checkout_url);
+            session.last_error = None;
+        } else if response.get_status_code() >= 500 {
+            session.state = CardSessionState::Unknown;
+            session.last_error = Some(describe(&response));
+        } else {
+            session.state = CardSessionState::Failed;
+            session.last_error = Some(describe(&response));
+        }
+        session.updated_at = chrono::Utc::now();
+        self.store.save_card_session(&session).await?;
+
+        Ok(CheckoutOutcome::Started { session, response })
+    }
+
+    /**
+     * Settle the state of a recorded card payment with Zainpay
+     *
+     * @param txn_ref &str
+     *
+     * @return CardSession
+     * @throws Box<dyn Error>
+     */
+    pub async fn verify_card_payment(&self, txn_ref: &str) -> Result<CardSession, Box<dyn Error>> {
+        let mut session =
+            self.store
+                .card_session(txn_ref)
+                .await?
+                .ok_or_else(|| StoreError::NotFound {
+                    kind: "card session",
+                    id: txn_ref.to_string(),
+                })?;
+        if session.state.is_final() {
+            return Ok(session);
+        }
+
+        let response = CardService::new(self.engine.clone())
+            .verify_card_payment(txn_ref.to_string())
+            .await?;
+        if response.has_failed() {
+            return Ok(session);
+        }
+
+        let state = match reported_status(&response) {
+            Some(status) if is_success(&status) => CardSessionState::Paid,
+            Some(status) if is_failure(&status) => CardSessionState::Failed,
+            _ => return Ok(session),
+        };
+        session.state = state;
+        session.updated_at = chrono::Utc::now();
+        self.store.save_card_session(&session).await?;
+        Ok(session)
+    }
+}
+
+fn describe(response: &Response) -> String {
+    format!(
+        "HTTP {} code {}: {}",
+        response.get_status_code(),
+        response.get_code().unwrap_or("-"),
+        response.get_description().unwrap_or("no description")
+    )
+}
+
+/// The status of the transaction in a verification response, lowercased.
+fn reported_status(response: &Response) -> Option<String> {
+    let data = response.get_raw_data()?;
+    ["txnStatus", "transactionStatus", "paymentStatus", "status"]
+        .iter()
+        .find_map(|key| data.get(*key)?.as_str())
+        .map(str::to_lowercase)
+}
+
+fn is_success(status: &str) -> bool {
+    status.contains("success") || status == "completed" || status == "paid"
+}
+
+fn is_failure(status: &str) -> bool {
+    status.contains("fail") || status.contains("revers") || status.contains("declin")
+}
+
+/// The checkout url of an initialized payment, sent either bare or as an object.
+fn checkout_url(data: &Value) -> Option<String> {
+    match data {
+        Value::String(url) => Some(url.clone()),
+        Value::Object(fields) => ["checkoutUrl", "paymentUrl", "url"]
+            .iter()
+            .find_map(|key| fields.get(*key)?.as_str())
+            .map(str::to_string),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enviroment::Environment;
+    use crate::secret::Secret;
+    use crate::store::MemoryStore;
+    use crate::transport::{
+        HttpRequest, HttpResponse, Method, MockTransport, Transport, TransportError,
+    };
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn request(txn_ref: &str, amount: &str) -> FundTransferRequest {
+        FundTransferRequest {
+            destination_account_number: Secret::from("0040000008"),
+            destination_bank_code: "000013".to_string(),
+            amount: amount.to_string(),
+            source_account_number: Secret::from("7966884043"),
+            source_bank_code: "0013".to_string(),
+            zainbox_code: "THbfnDvK5o".to_string(),
+            txn_ref: txn_ref.to_string(),
+            narration: "payout".to_string(),
+            callback_url: None,
+        }
+    }
+
+    fn service(transport: Arc<dyn Transport>) -> PaymentService {
+        let engine = Engine::builder(Environment::Sandbox, "key")
+            .transport(transport)
+            .build();
+        PaymentService::new(engine, Arc::new(MemoryStore::new()))
+    }
+
+    fn session() -> CardSession {
+        CardSession::new(
+            "CARD-1",
+            "THbfnDvK5o",
+            "10000",
+            "ada@example.com",
+            "08000000000",
+            "https://example.com/callback",
+        )
+    }
+
+    /// Fails the first `failures` requests as if the connection dropped, then answers with `ok`.
+    #[derive(Debug)]
+    struct Flaky {
+        failures: usize,
+        sent: AtomicUsize,
+        ok: &'static str,
+    }
+
+    #[async_trait]
+    impl Transport for Flaky {
+        async fn send(&self, _: HttpRequest) -> Result<HttpResponse, TransportError> {
+            if self.sent.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err("connection reset".into());
+            }
+            Ok(HttpResponse::new(200, self.ok))
+        }
+    }
+
+    #[tokio::test]
+    async fn a_transfer_is_sent_once_per_idempotency_key() {
+        let transport = Arc::new(MockTransport::new().route(
+            Method::Post,
+            "bank/transfer",
+            HttpResponse::new(200, r#"{"code":"00","status":"200 OK"}"#),
+        ));
+        let payments = service(transport.clone());
+
+        let first = payments
+            .transfer("key-1", request("TRF-1", "1500"))
+            .await
+            .unwrap();
+        let again = payments
+            .transfer("key-1", request("TRF-1", "1500"))
+            .await
+            .unwrap();
+        let same_ref = payments
+            .transfer("key-2", request("TRF-1", "1500"))
+            .await
+            .unwrap();
+        let conflict = payments.transfer("key-1", request("TRF-1", "9999")).await;
+
+        assert_eq!(first.intent().state, TransferState::Submitted);
+        assert!(matches!(again, TransferOutcome::Duplicate(_)));
+        assert!(matches!(same_ref, TransferOutcome::Duplicate(_)));
+        assert!(conflict.is_err());
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn an_unknown_transfer_stays_unknown_when_zainpay_has_no_record() {
+        let transport = Arc::new(Flaky {
+            failures: 1,
+            sent: AtomicUsize::new(0),
+            ok: r#"{"code":"04","status":"404 Not Found"}"#,
+        });
+        let payments = service(transport);
+
+        assert!(payments
+            .transfer("key-1", request("TRF-1", "1500"))
+            .await
+            .is_err());
+        let intent = payments.verify_transfer("TRF-1").await.unwrap();
+        assert_eq!(intent.state, TransferState::Unknown);
+        assert_eq!(intent.last_error.as_deref(), Some("connection reset"));
+    }
+
+    #[tokio::test]
+    async fn a_pending_transfer_zainpay_has_no_record_of_failed() {
+        let transport = Arc::new(MockTransport::new());
+        let payments = service(transport);
+        let intent = TransferIntent::new("key-1", request("TRF-1", "1500"));
+        payments.store().insert_transfer(&intent).await.unwrap();
+
+        let intent = payments.verify_transfer("TRF-1").await.unwrap();
+        assert_eq!(intent.state, TransferState::Failed);
+    }
+
+    #[tokio::test]
+    async fn a_card_payment_that_failed_to_send_can_be_started_again() {
+        let transport = Arc::new(Flaky {
+            failures: 1,
+            sent: AtomicUsize::new(0),
+            ok: r#"{"code":"00","data":"https://checkout.zainpay.ng/CARD-1"}"#,
+        });
+        let payments = service(transport.clone());
+
+        assert!(payments.start_card_payment(session()).await.is_err());
+        let recorded = payments
+            .store()
+            .card_session("CARD-1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(recorded.state, CardSessionState::Unknown);
+        assert_eq!(recorded.last_error.as_deref(), Some("connection reset"));
+
+        let retried = payments.start_card_payment(session()).await.unwrap();
+        assert!(matches!(retried, CheckoutOutcome::Started { .. }));
+        assert_eq!(retried.session().state, CardSessionState::Initialized);
+        assert_eq!(
+            retried.session().checkout_url.as_deref(),
+            Some("https://checkout.zainpay.ng/CARD-1")
+        );
+
+        let again = payments.start_card_payment(session()).await.unwrap();
+        assert!(matches!(again, CheckoutOutcome::Existing(_)));
+        assert_eq!(transport.sent.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,865 @@
+//! Zainpay setup as code.
+//!
+//! A [`Manifest`] describes zainboxes, their settlement plans and the virtual accounts they must
+//! hold. [`ProvisionService::plan`] diffs it against live state into a [`Plan`] of create and
+//! update operations, which prints as a readable summary and is carried out by
+//! [`ProvisionService::apply`]. Nothing is ever deleted.
+//!
+//! ```toml
+//! [[zainboxes]]
+//! name = "Shop"
+//! email_notification = "ops@example.com"
+//! callback_url = "https://example.com/zainpay/callback"
+//! tags = ["shop", "ng"]
+//!
+//! [zainboxes.settlement]
+//! name = "Daily payout"
+//! schedule_type = "T1"
+//! schedule_period = "Daily"
+//! accounts = [{ account_number = "0123456789", bank_code = "0013", percentage = 100.0 }]
+//!
+//! [[zainboxes.virtual_accounts]]
+//! label = "collections"
+//! bank_type = "wemaBank"
+//! # bvn, first_name, last_name, email, mobile, dob, gender, address, title, state
+//! ```
+
+#[cfg(any(feature = "toml", feature = "yaml"))]
+use crate::config::ConfigError;
+use crate::engine::Engine;
+use crate::error::ZainpayError;
+use crate::models::model::{SettlementAccount, SettlementInfo, VirtualAccountInfo, ZainboxInfo};
+use crate::response::Response;
+use crate::secret::Secret;
+use crate::settlement::SettlementService;
+use crate::virtual_account;
+use crate::zainbox::{ZainboxField, ZainboxService, ZainboxSpec};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub zainboxes: Vec<ZainboxManifest>,
+}
+
+impl Manifest {
+    #[cfg(feature = "toml")]
+    pub fn from_toml_file(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        Self::from_toml_str(
+            &crate::config::read_file(path)?,
+            &path.display().to_string(),
+        )
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(content: &str, source: &str) -> Result<Self, ConfigError> {
+        toml::from_str(content).map_err(|error| parse_error(source, error))
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_file(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        Self::from_yaml_str(
+            &crate::config::read_file(path)?,
+            &path.display().to_string(),
+        )
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_str(content: &str, source: &str) -> Result<Self, ConfigError> {
+        serde_yaml::from_str(content).map_err(|error| parse_error(source, error))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZainboxManifest {
+    pub name: String,
+    pub email_notification: Secret<String>,
+    pub callback_url: String,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub code_name_prefix: Option<String>,
+    #[serde(default)]
+    pub allow_auto_internal_transfer: Option<bool>,
+    #[serde(default)]
+    pub settlement: Option<SettlementPlan>,
+    #[serde(default)]
+    pub virtual_accounts: Vec<VirtualAccountManifest>,
+}
+
+impl ZainboxManifest {
+    pub fn spec(&self) -> ZainboxSpec {
+        ZainboxSpec {
+            name: self.name.clone(),
+            email_notification: self.email_notification.clone(),
+            callback_url: self.callback_url.clone(),
+            tags: self.tags.clone(),
+            description: self.description.clone(),
+            code_name_prefix: self.code_name_prefix.clone(),
+            allow_auto_internal_transfer: self.allow_auto_internal_transfer,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SettlementPlan {
+    pub name: String,
+    pub schedule_type: String,
+    pub schedule_period: String,
+    pub accounts: Vec<SettlementAccountPlan>,
+    #[serde(default = "active")]
+    pub status: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SettlementAccountPlan {
+    pub account_number: Secret<String>,
+    pub bank_code: String,
+    pub percentage: f64,
+}
+
+impl SettlementPlan {
+    /// Whether the live settlement already follows this plan. Accounts are compared regardless
+    /// of order; fields Zainpay did not return are unknown and not counted as differing.
+    pub fn matches(&self, live: &SettlementInfo) -> bool {
+        let mut wanted: Vec<_> = self
+            .accounts
+            .iter()
+            .map(|account| {
+                account_key(
+                    account.account_number.expose(),
+                    &account.bank_code,
+                    account.percentage,
+                )
+            })
+            .collect();
+        let mut current: Vec<_> = live
+            .settlement_account_list
+            .iter()
+            .map(|account| {
+                account_key(
+                    account.account_number.expose(),
+                    &account.bank_code,
+                    account.percentage,
+                )
+            })
+            .collect();
+        wanted.sort();
+        current.sort();
+
+        live.name.as_deref().is_none_or(|name| name == self.name)
+            && live
+                .schedule_type
+                .as_deref()
+                .is_none_or(|schedule_type| schedule_type.eq_ignore_ascii_case(&self.schedule_type))
+            && live
+                .schedule_period
+                .as_deref()
+                .is_none_or(|period| period.eq_ignore_ascii_case(&self.schedule_period))
+            && live.status.is_none_or(|status| status == self.status)
+            && wanted == current
+    }
+}
+
+/// A virtual account the zainbox must hold. It counts as present when the zainbox has an account
+/// of the same bank type whose name contains both the first and last name.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VirtualAccountManifest {
+    /// Shown in plans and logs instead of the holder's personal details.
+    pub label: String,
+    pub bank_type: String,
+    pub bvn: Secret<String>,
+    pub first_name: Secret<String>,
+    pub last_name: Secret<String>,
+    pub email: Secret<String>,
+    pub mobile: Secret<String>,
+    pub dob: Secret<String>,
+    pub gender: String,
+    pub address: Secret<String>,
+    pub title: String,
+    pub state: String,
+}
+
+impl VirtualAccountManifest {
+    pub fn matches(&self, live: &VirtualAccountInfo) -> bool {
+        let Some(account_name) = &live.account_name else {
+            return false;
+        };
+        let account_name = account_name.expose().to_lowercase();
+
+        live.bank_type
+            .as_deref()
+            .is_none_or(|bank_type| bank_type.eq_ignore_ascii_case(&self.bank_type))
+            && account_name.contains(&self.first_name.expose().trim().to_lowercase())
+            && account_name.contains(&self.last_name.expose().trim().to_lowercase())
+    }
+}
+
+/// A change to make on Zainpay. Settlements and virtual accounts refer to their zainbox by name,
+/// since a zainbox created by the same plan has no code yet.
+#[derive(Debug, Clone)]
+pub enum Operation {
+    CreateZainbox(ZainboxSpec),
+    UpdateZainbox {
+        zainbox_code: String,
+        spec: ZainboxSpec,
+        changes: Vec<ZainboxField>,
+    },
+    CreateSettlement {
+        zainbox: String,
+        plan: SettlementPlan,
+    },
+    UpdateSettlement {
+        zainbox: String,
+        plan: SettlementPlan,
+    },
+    CreateVirtualAccount {
+        zainbox: String,
+        account: VirtualAccountManifest,
+    },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::CreateZainbox(spec) => write!(f, "+ zainbox {:?}", spec.name),
+            Operation::UpdateZainbox {
+                zainbox_code,
+                spec,
+                changes,
+            } => {
+                let changes: Vec<String> = changes.iter().map(ToString::to_string).collect();
+                write!(
+                    f,
+                    "~ zainbox {:?} ({}): {}",
+                    spec.name,
+                    zainbox_code,
+                    changes.join(", ")
+                )
+            }
+            Operation::CreateSettlement { zainbox, plan } => {
+                write!(f, "+ settlement {:?} of zainbox {:?}", plan.name, zainbox)
+            }
+            Operation::UpdateSettlement { zainbox, plan } => {
+                write!(f, "~ settlement {:?} of zainbox {:?}", plan.name, zainbox)
+            }
+            Operation::CreateVirtualAccount { zainbox, account } => write!(
+                f,
+                "+ virtual account {:?} ({}) in zainbox {:?}",
+                account.label, account.bank_type, zainbox
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub operations: Vec<Operation>,
+    /// Codes of the zainboxes that already exist, by name.
+    zainbox_codes: HashMap<String, String>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.operations.is_empty() {
+            return writeln!(f, "No changes.");
+        }
+        for operation in &self.operations {
+            writeln!(f, "{}", operation)?;
+        }
+        writeln!(f, "{} operation(s).", self.operations.len())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Applied {
+    pub operation: Operation,
+    pub response: Response,
+}
+
+pub struct ProvisionService {
+    engine: Engine,
+}
+
+impl ProvisionService {
+    pub fn new(engine: Engine) -> Self {
+        Self { engine }
+    }
+
+    /**
+     * Diff a manifest against the live zainboxes, settlements and virtual accounts
+     *
+     * @param Manifest $manifest
+     * @return Plan
+     * @throws Box<dyn Error>
+     */
+    pub async fn plan(&self, manifest: &Manifest) -> Result<Plan, Box<dyn Error>> {
+        let zainboxes = ZainboxService::new(self.engine.clone());
+        let response = zainboxes.list(None).await?;
+        if response.has_failed() {
+            return Err(ZainpayError::from_response(&response).into());
+        }
+        let live = response
+            .parse_data::<Vec<ZainboxInfo>>()
+            .ok_or(ZainpayError::UnexpectedData)?;
+
+        let mut plan = Plan::default();
+        for wanted in &manifest.zainboxes {
+            let spec = wanted.spec();
+
+            let Some(existing) = live.iter().find(|zainbox| spec.matches(zainbox)) else {
+                plan.operations.push(Operation::CreateZainbox(spec));
+                if let Some(settlement) = &wanted.settlement {
+                    plan.operations.push(Operation::CreateSettlement {
+                        zainbox: wanted.name.clone(),
+                        plan: settlement.clone(),
+                    });
+                }
+                for account in &wanted.virtual_accounts {
+                    plan.operations.push(Operation::CreateVirtualAccount {
+                        zainbox: wanted.name.clone(),
+                        account: account.clone(),
+                    });
+                }
+                continue;
+            };
+            plan.zainbox_codes
+                .insert(wanted.name.clone(), existing.codeName.clone());
+
+            let profile = self.zainbox_profile(&existing.codeName).await?;
+            let changes = spec.changes(profile.as_ref().unwrap_or(existing));
+            if !changes.is_empty() {
+                plan.operations.push(Operation::UpdateZainbox {
+                    zainbox_code: existing.codeName.clone(),
+                    spec,
+                    changes,
+                });
+            }
+
+            if let Some(settlement) = &wanted.settlement {
+                match self.settlement(&existing.codeName).await? {
+                    Some(live) if settlement.matches(&live) => {}
+                    Some(_) => plan.operations.push(Operation::UpdateSettlement {
+                        zainbox: wanted.name.clone(),
+                        plan: settlement.clone(),
+                    }),
+                    None => plan.operations.push(Operation::CreateSettlement {
+                        zainbox: wanted.name.clone(),
+                        plan: settlement.clone(),
+                    }),
+                }
+            }
+
+            if !wanted.virtual_accounts.is_empty() {
+                let mut accounts = self.virtual_accounts(&existing.codeName).await?;
+                for account in &wanted.virtual_accounts {
+                    match accounts.iter().position(|live| account.matches(live)) {
+                        Some(index) => {
+                            accounts.swap_remove(index);
+                        }
+                        None => plan.operations.push(Operation::CreateVirtualAccount {
+                            zainbox: wanted.name.clone(),
+                            account: account.clone(),
+                        }),
+                    }
+                }
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /**
+     * Carry out a plan, in order. Stops at the first operation Zainpay rejects; planning again
+     * afterwards picks up where it stopped.
+     *
+     * @param Plan $plan
+     * @return Vec<Applied>
+     * @throws Box<dyn Error>
+     */
+    pub async fn apply(&self, plan: &Plan) -> Result<Vec<Applied>, Box<dyn Error>> {
+        let zainboxes = ZainboxService::new(self.engine.clone());
+        let settlements = SettlementService::new(self.engine.clone());
+        let virtual_accounts = virtual_account::CardService::new(self.engine.clone());
+
+        let mut zainbox_codes = plan.zainbox_codes.clone();
+        let mut applied = Vec::new();
+
+        for operation in &plan.operations {
+            let response = match operation {
+                Operation::CreateZainbox(spec) => {
+                    let response = zainboxes
+                        .create(
+                            spec.name.clone(),
+                            spec.email_notification.expose().clone(),
+                            spec.tags.clone(),
+                            spec.callback_url.clone(),
+                            spec.description.clone(),
+                            spec.code_name_prefix.clone(),
+                            spec.allow_auto_internal_transfer,
+                        )
+                        .await?;
+                    if response.has_succeeded() {
+                        let code = match response
+                            .get_raw_data()
+                            .and_then(|data| data.get("codeName"))
+                            .and_then(|code| code.as_str())
+                        {
+                            Some(code) => Some(code.to_string()),
+                            None => self.find_zainbox_code(spec).await?,
+                        };
+                        if let Some(code) = code {
+                            zainbox_codes.insert(spec.name.clone(), code);
+                        }
+                    }
+                    response
+                }
+                Operation::UpdateZainbox {
+                    zainbox_code,
+                    spec,
+                    changes,
+                } => {
+                    zainboxes
+                        .update_fields(zainbox_code, &spec.name, spec, changes)
+                        .await?
+                }
+                Operation::CreateSettlement { zainbox, plan }
+                | Operation::UpdateSettlement { zainbox, plan } => {
+                    settlements
+                        .create_or_update_zainbox_settlemet(
+                            plan.name.clone(),
+                            zainbox_code(&zainbox_codes, zainbox)?,
+                            plan.schedule_type.clone(),
+                            plan.schedule_period.clone(),
+                            plan.accounts
+                                .iter()
+                                .map(|account| {
+                                    SettlementAccount::new(
+                                        account.account_number.expose().clone(),
+                                        account.bank_code.clone(),
+                                        account.percentage,
+                                    )
+                                })
+                                .collect(),
+                            plan.status,
+                        )
+                        .await?
+                }
+                Operation::CreateVirtualAccount { zainbox, account } => {
+                    virtual_accounts
+                        .create(
+                            account.bank_type.clone(),
+                            account.bvn.expose().clone(),
+                            account.first_name.expose().clone(),
+                            account.last_name.expose().clone(),
+                            account.email.expose().clone(),
+                            account.mobile.expose().clone(),
+                            account.dob.expose().clone(),
+                            account.gender.clone(),
+                            account.address.expose().clone(),
+                            account.title.clone(),
+                            account.state.clone(),
+                            zainbox_code(&zainbox_codes, zainbox)?,
+                        )
+                        .await?
+                }
+            };
+
+            if response.has_failed() {
+                tracing::warn!(operation = %operation, "zainpay provisioning operation rejected");
+                return Err(ZainpayError::from_response(&response).into());
+            }
+            tracing::info!(operation = %operation, "zainpay provisioning operation applied");
+            applied.push(Applied {
+                operation: operation.clone(),
+                response,
+            });
+        }
+
+        Ok(applied)
+    }
+
+    async fn find_zainbox_code(
+        &self,
+        spec: &ZainboxSpec,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let response = ZainboxService::new(self.engine.clone()).list(None).await?;
+        Ok(response
+            .parse_data::<Vec<ZainboxInfo>>()
+            .unwrap_or_default()
+            .into_iter()
+            .find(|zainbox| spec.matches(zainbox))
+            .map(|zainbox| zainbox.codeName))
+    }
+
+    /// The full profile of a zainbox, `None` when Zainpay does not know it or returns an
+    /// unexpected shape.
+    async fn zainbox_profile(
+        &self,
+        zainbox_code: &str,
+    ) -> Result<Option<ZainboxInfo>, Box<dyn Error>> {
+        let response = ZainboxService::new(self.engine.clone())
+            .get_zainbox_profile(zainbox_code.to_string())
+            .await?;
+        if response.has_failed() {
+            return absent(&response, None);
+        }
+
+        Ok(response.parse_data::<ZainboxInfo>().or_else(|| {
+            let zainbox = response.get_raw_data()?.get("zainbox")?.clone();
+            serde_json::from_value(zainbox).ok()
+        }))
+    }
+
+    /// The settlement of a zainbox, `None` when it has none.
+    async fn settlement(
+        &self,
+        zainbox_code: &str,
+    ) -> Result<Option<SettlementInfo>, Box<dyn Error>> {
+        let response = SettlementService::new(self.engine.clone())
+            .get_settlement_info_for_zainbox(zainbox_code.to_string())
+            .await?;
+        if response.has_failed() {
+            return absent(&response, None);
+        }
+
+        Ok(response.parse_data::<SettlementInfo>().or_else(|| {
+            response
+                .parse_data::<Vec<SettlementInfo>>()?
+                .into_iter()
+                .next()
+        }))
+    }
+
+    /// The virtual accounts of a zainbox, empty when it has none.
+    async fn virtual_accounts(
+        &self,
+        zainbox_code: &str,
+    ) -> Result<Vec<VirtualAccountInfo>, Box<dyn Error>> {
+        let response = virtual_account::CardService::new(self.engine.clone())
+            .get_all_virtual_accounts_for_zainbox(zainbox_code.to_string())
+            .await?;
+        if response.has_failed() {
+            return absent(&response, Vec::new());
+        }
+
+        Ok(response
+            .parse_data::<Vec<VirtualAccountInfo>>()
+            .ok_or(ZainpayError::UnexpectedData)?)
+    }
+}
+
+/// `empty` when a failed `response` says the resource does not exist, its error otherwise. An
+/// outage or a rejected key must not read as "nothing there", or the plan would recreate it.
+fn absent<T>(response: &Response, empty: T) -> Result<T, Box<dyn Error>> {
+    let not_found = response.get_status_code() == 404
+        || response
+            .get_status()
+            .is_some_and(|status| status.starts_with("404"));
+    if not_found {
+        Ok(empty)
+    } else {
+        Err(ZainpayError::from_response(response).into())
+    }
+}
+
+fn active() -> bool {
+    true
+}
+
+fn account_key(account_number: &str, bank_code: &str, percentage: f64) -> (String, String, i64) {
+    (
+        account_number.trim().to_string(),
+        bank_code.trim().to_string(),
+        (percentage * 100.0).round() as i64,
+    )
+}
+
+fn zainbox_code(codes: &HashMap<String, String>, zainbox: &str) -> Result<String, ZainpayError> {
+    codes
+        .get(zainbox)
+        .cloned()
+        .ok_or_else(|| ZainpayError::ZainboxCodeUnknown(zainbox.to_string()))
+}
+
+#[cfg(any(feature = "toml", feature = "yaml"))]
+fn parse_error(source: &str, error: impl fmt::Display) -> ConfigError {
+    ConfigError::Parse {
+        path: source.to_string(),
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enviroment::Environment;
+    use crate::transport::{HttpResponse, Method, MockTransport};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    fn service(transport: MockTransport) -> ProvisionService {
+        let engine = Engine::builder(Environment::Sandbox, "key")
+            .transport(Arc::new(transport))
+            .build();
+        ProvisionService::new(engine)
+    }
+
+    #[tokio::test]
+    async fn not_found_reads_as_absent() {
+        let service = service(
+            MockTransport::new()
+                .route(
+                    Method::Get,
+                    "zainbox/settlement",
+                    HttpResponse::new(
+                        200,
+                        r#"{"code":"04","description":"no settlement for zainbox","status":"404 Not Found"}"#,
+                    ),
+                )
+                .route(
+                    Method::Get,
+                    "zainbox/virtual-accounts/THbfnDvK5o",
+                    HttpResponse::new(404, r#"{"code":"04","status":"404 Not Found"}"#),
+                ),
+        );
+
+        assert!(service.settlement("THbfnDvK5o").await.unwrap().is_none());
+        assert!(service
+            .virtual_accounts("THbfnDvK5o")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn other_failures_are_errors() {
+        let service = service(
+            MockTransport::new()
+                .route(
+                    Method::Get,
+                    "zainbox/settlement",
+                    HttpResponse::new(
+                        500,
+                        r#"{"code":"500","status":"500 Internal Server Error"}"#,
+                    ),
+                )
+                .route(
+                    Method::Get,
+                    "zainbox/virtual-accounts/THbfnDvK5o",
+                    HttpResponse::new(401, r#"{"code":"01","status":"401 Unauthorized"}"#),
+                )
+                .route(
+                    Method::Get,
+                    "zainbox/profile/THbfnDvK5o",
+                    HttpResponse::new(503, "upstream unavailable"),
+                ),
+        );
+
+        assert!(service.settlement("THbfnDvK5o").await.is_err());
+        assert!(service.virtual_accounts("THbfnDvK5o").await.is_err());
+        assert!(service.zainbox_profile("THbfnDvK5o").await.is_err());
+    }
+
+    /// Zainboxes and settlements held by a fake Zainpay, as returned by its list and get calls.
+    #[derive(Default)]
+    struct Live {
+        zainbox: Option<Value>,
+        settlement: Option<Value>,
+    }
+
+    fn ok(data: Value) -> HttpResponse {
+        let body = json!({ "code": "00", "status": "200 OK", "data": data });
+        HttpResponse::new(200, &body.to_string())
+    }
+
+    /// A fake Zainpay keeping one zainbox and its settlement in `live`.
+    fn fake_zainpay(live: Arc<Mutex<Live>>) -> MockTransport {
+        MockTransport::new().handler(move |request| {
+            let mut live = live.lock().unwrap();
+            let body: Option<Value> = request
+                .body
+                .as_deref()
+                .map(|body| serde_json::from_str(body).unwrap());
+            let not_found = HttpResponse::new(404, r#"{"code":"04","status":"404 Not Found"}"#);
+            let path = request.path().split('?').next().unwrap();
+            Some(match (request.method, path) {
+                (Method::Get, "zainbox/list") => ok(json!(live.zainbox.iter().collect::<Vec<_>>())),
+                (Method::Post, "zainbox/create/request") => {
+                    let mut zainbox = body.unwrap();
+                    zainbox["codeName"] = json!("THbfnDvK5o");
+                    zainbox["isActive"] = json!(true);
+                    live.zainbox = Some(zainbox);
+                    ok(json!({ "codeName": "THbfnDvK5o" }))
+                }
+                (Method::Patch, "zainbox/update") => {
+                    let zainbox = live.zainbox.as_mut().unwrap();
+                    for (key, value) in body.unwrap().as_object().unwrap() {
+                        zainbox[key] = value.clone();
+                    }
+                    ok(json!({}))
+                }
+                (Method::Get, "zainbox/profile/THbfnDvK5o") => match &live.zainbox {
+                    Some(zainbox) => ok(zainbox.clone()),
+                    None => not_found,
+                },
+                (Method::Get, "zainbox/settlement") => match &live.settlement {
+                    Some(settlement) => ok(settlement.clone()),
+                    None => not_found,
+                },
+                (Method::Post, "zainbox/settlement") => {
+                    let mut settlement = body.unwrap();
+                    let accounts: Vec<Value> = settlement["settlementAccountList"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|account| {
+                            json!({
+                                "accountNumber": account["account_number"],
+                                "bankCode": account["bank_code"],
+                                "percentage": account["percentage"],
+                            })
+                        })
+                        .collect();
+                    settlement["settlementAccountList"] = json!(accounts);
+                    live.settlement = Some(settlement);
+                    ok(json!({}))
+                }
+                _ => not_found,
+            })
+        })
+    }
+
+    fn manifest() -> Manifest {
+        serde_json::from_value(json!({
+            "zainboxes": [{
+                "name": "Shop",
+                "email_notification": "ops@example.com",
+                "callback_url": "https://example.com/zainpay/callback",
+                "tags": ["shop", "ng"],
+                "settlement": {
+                    "name": "Daily payout",
+                    "schedule_type": "T1",
+                    "schedule_period": "Daily",
+                    "accounts": [
+                        { "account_number": "0123456789", "bank_code": "000013", "percentage": 70.0 },
+                        { "account_number": "0234567890", "bank_code": "000014", "percentage": 30.0 },
+                    ],
+                },
+            }],
+        }))
+        .unwrap()
+    }
+
+    fn kinds(plan: &Plan) -> Vec<&'static str> {
+        plan.operations
+            .iter()
+            .map(|operation| match operation {
+                Operation::CreateZainbox(_) => "CreateZainbox",
+                Operation::UpdateZainbox { .. } => "UpdateZainbox",
+                Operation::CreateSettlement { .. } => "CreateSettlement",
+                Operation::UpdateSettlement { .. } => "UpdateSettlement",
+                Operation::CreateVirtualAccount { .. } => "CreateVirtualAccount",
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn applying_a_plan_converges() {
+        let live = Arc::new(Mutex::new(Live::default()));
+        let service = service(fake_zainpay(live.clone()));
+
+        let plan = service.plan(&manifest()).await.unwrap();
+        assert_eq!(kinds(&plan), ["CreateZainbox", "CreateSettlement"]);
+
+        let applied = service.apply(&plan).await.unwrap();
+        assert_eq!(applied.len(), 2);
+        let settlement = live.lock().unwrap().settlement.clone().unwrap();
+        assert_eq!(settlement["zainboxCode"], "THbfnDvK5o");
+
+        let plan = service.plan(&manifest()).await.unwrap();
+        assert!(plan.is_empty(), "{}", plan);
+        assert_eq!(plan.to_string(), "No changes.\n");
+    }
+
+    #[tokio::test]
+    async fn drift_is_planned_and_repaired() {
+        let live = Arc::new(Mutex::new(Live::default()));
+        let service = service(fake_zainpay(live.clone()));
+        service
+            .apply(&service.plan(&manifest()).await.unwrap())
+            .await
+            .unwrap();
+
+        {
+            let mut live = live.lock().unwrap();
+            live.zainbox.as_mut().unwrap()["callbackUrl"] = json!("https://example.com/old");
+            live.settlement.as_mut().unwrap()["settlementAccountList"][0]["percentage"] =
+                json!("50");
+        }
+
+        let plan = service.plan(&manifest()).await.unwrap();
+        assert_eq!(kinds(&plan), ["UpdateZainbox", "UpdateSettlement"]);
+        match &plan.operations[0] {
+            Operation::UpdateZainbox { changes, .. } => {
+                assert_eq!(changes, &[ZainboxField::CallbackUrl])
+            }
+            other => panic!("expected a zainbox update, got {:?}", other),
+        }
+
+        service.apply(&plan).await.unwrap();
+        assert!(service.plan(&manifest()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn settlement_fields_zainpay_leaves_out_are_not_drift() {
+        let live = Arc::new(Mutex::new(Live::default()));
+        let service = service(fake_zainpay(live.clone()));
+        service
+            .apply(&service.plan(&manifest()).await.unwrap())
+            .await
+            .unwrap();
+
+        {
+            let mut live = live.lock().unwrap();
+            let settlement = live.settlement.as_mut().unwrap().as_object_mut().unwrap();
+            settlement.remove("name");
+            settlement.remove("scheduleType");
+            settlement.remove("status");
+        }
+
+        let plan = service.plan(&manifest()).await.unwrap();
+        assert!(plan.is_empty(), "{}", plan);
+    }
+
+    #[tokio::test]
+    async fn a_zainbox_without_a_code_is_reported_by_name() {
+        let service = service(MockTransport::new());
+        let plan = Plan {
+            operations: vec![Operation::CreateSettlement {
+                zainbox: "Shop".to_string(),
+                plan: manifest().zainboxes[0].settlement.clone().unwrap(),
+            }],
+            zainbox_codes: HashMap::new(),
+        };
+
+        let error = service.apply(&plan).await.unwrap_err();
+        match error.downcast_ref::<ZainpayError>() {
+            Some(ZainpayError::ZainboxCodeUnknown(zainbox)) => assert_eq!(zainbox, "Shop"),
+            other => panic!("expected an unknown zainbox code, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Endpoints that share one of Zainpay's per-merchant rate limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EndpointGroup {
+    NameEnquiry,
+    Transfers,
+    History,
+    Other,
+}
+
+impl EndpointGroup {
+    pub fn for_path(path: &str) -> Self {
+        let path = path.split('?').next().unwrap_or_default();
+        if path.contains("name-enquiry") {
+            EndpointGroup::NameEnquiry
+        } else if path.contains("bank/transfer") {
+            EndpointGroup::Transfers
+        } else if path.contains("transactions") || path.contains("history") {
+            EndpointGroup::History
+        } else {
+            EndpointGroup::Other
+        }
+    }
+}
+
+/// A token bucket holding up to `burst` requests and refilled at `per_second` requests a second.
+/// A limit of zero requests means no limit: the group is not throttled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn per_second(requests: u32) -> Self {
+        Self {
+            per_second: requests as f64,
+            burst: requests.max(1),
+        }
+    }
+
+    pub fn per_minute(requests: u32) -> Self {
+        Self {
+            per_second: requests as f64 / 60.0,
+            burst: requests.max(1),
+        }
+    }
+
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    limit: RateLimit,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            state: Mutex::new(BucketState {
+                tokens: limit.burst as f64,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token, or returns how long to wait before one is available.
+    fn try_take(&self) -> Result<(), Duration> {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+        state.tokens =
+            (state.tokens + elapsed * self.limit.per_second).min(self.limit.burst as f64);
+        state.refilled_at = now;
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - state.tokens) / self.limit.per_second,
+            ))
+        }
+    }
+}
+
+/// Client-side rate limiter shared by every clone of an `Engine`.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: HashMap<EndpointGroup, TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: HashMap<EndpointGroup, RateLimit>) -> Self {
+        Self {
+            buckets: limits
+                .into_iter()
+                .filter(|(_, limit)| limit.per_second > 0.0)
+                .map(|(group, limit)| (group, TokenBucket::new(limit)))
+                .collect(),
+        }
+    }
+
+    /// Waits until a request of `group` may be sent. Groups without a configured limit never wait.
+    pub async fn acquire(&self, group: EndpointGroup) {
+        let Some(bucket) = self.buckets.get(&group) else {
+            return;
+        };
+        while let Err(wait) = bucket.try_take() {
+            tracing::debug!(
+                ?group,
+                wait_ms = wait.as_millis() as u64,
+                "zainpay rate limit reached"
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Reads a `Retry-After` header given either in seconds or as an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(wait.to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_a_burst_then_asks_to_wait() {
+        let bucket = TokenBucket::new(RateLimit::per_second(2));
+        assert!(bucket.try_take().is_ok());
+        assert!(bucket.try_take().is_ok());
+        let wait = bucket.try_take().unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let bucket = TokenBucket::new(RateLimit::per_second(1000).with_burst(1));
+        assert!(bucket.try_take().is_ok());
+        assert!(bucket.try_take().is_err());
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(bucket.try_take().is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_zero_limit_does_not_throttle() {
+        let limiter = RateLimiter::new(HashMap::from([
+            (EndpointGroup::Transfers, RateLimit::per_second(0)),
+            (EndpointGroup::History, RateLimit::per_minute(0)),
+        ]));
+        for _ in 0..10 {
+            tokio::time::timeout(
+                Duration::from_millis(100),
+                limiter.acquire(EndpointGroup::Transfers),
+            )
+            .await
+            .expect("a zero limit never waits");
+        }
+        assert!(limiter.buckets.is_empty());
+    }
+
+    #[test]
+    fn endpoint_groups_ignore_the_query_string() {
+        assert_eq!(
+            EndpointGroup::for_path("bank/name-enquiry?bankCode=1&accountNumber=2"),
+            EndpointGroup::NameEnquiry
+        );
+        assert_eq!(
+            EndpointGroup::for_path("zainbox/transactions/ZB/20?dateFrom=2024-01-01"),
+            EndpointGroup::History
+        );
+        assert_eq!(
+            EndpointGroup::for_path("zainbox/list?status=history"),
+            EndpointGroup::Other
+        );
+    }
+
+    #[test]
+    fn retry_after_reads_seconds_and_dates() {
+        assert_eq!(parse_retry_after(" 5 "), Some(Duration::from_secs(5)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
@@ -0,0 +1,497 @@
+use crate::bank::BankService;
+use crate::engine::Engine;
+use crate::error::ZainpayError;
+use crate::export::{ExportService, HistoryPager, HistorySource};
+use crate::models::model::Transaction;
+use crate::response::Response;
+use crate::zainbox::ZainboxService;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// A transaction as recorded in the caller's own ledger. Amounts are in kobo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalRecord {
+    pub txn_ref: String,
+    pub amount: i64,
+    pub date: NaiveDate,
+    /// Zainpay's transaction type, e.g. `transfer` or `fee`. A transfer and its fee share a txn
+    /// ref, so with a type set the record is only matched against a row of that type.
+    pub transaction_type: Option<String>,
+    pub status: Option<String>,
+    pub account_number: Option<String>,
+    pub session_id: Option<String>,
+}
+
+impl LocalRecord {
+    pub fn new(txn_ref: String, amount: i64, date: NaiveDate) -> Self {
+        Self {
+            txn_ref,
+            amount,
+            date,
+            transaction_type: None,
+            status: None,
+            account_number: None,
+            session_id: None,
+        }
+    }
+}
+
+/// A local record and the Zainpay transaction sharing its txn ref.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordPair {
+    pub local: LocalRecord,
+    pub remote: Transaction,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReconciliationReport {
+    pub matched: Vec<RecordPair>,
+    /// Known to Zainpay but absent from the local records.
+    pub missing_locally: Vec<Transaction>,
+    /// Present in the local records but absent from Zainpay's history.
+    pub missing_on_zainpay: Vec<LocalRecord>,
+    pub amount_mismatches: Vec<RecordPair>,
+    pub status_mismatches: Vec<RecordPair>,
+    /// Days whose history filled a whole page, so Zainpay may hold transactions not compared.
+    pub truncated_days: Vec<NaiveDate>,
+}
+
+impl ReconciliationReport {
+    pub fn is_balanced(&self) -> bool {
+        self.missing_locally.is_empty()
+            && self.missing_on_zainpay.is_empty()
+            && self.amount_mismatches.is_empty()
+            && self.status_mismatches.is_empty()
+            && self.truncated_days.is_empty()
+    }
+}
+
+/// What to ask Zainpay to do about a discrepancy.
+#[derive(Debug, Clone)]
+pub enum ResolutionAction {
+    /// Resend the deposit callback for every deposit missing locally.
+    RepushDeposit,
+
+    /// Ask Zainpay to re-check the bank for every local record it has no trace of.
+    /// Only records carrying an `account_number` can be reconciled.
+    ReconcileBankDeposit {
+        verification_type: String,
+        bank_type: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    pub txn_ref: String,
+    pub response: Response,
+}
+
+pub struct ReconcileService {
+    engine: Engine,
+}
+
+impl ReconcileService {
+    pub fn new(engine: Engine) -> Self {
+        Self { engine }
+    }
+
+    /**
+     * Match local records against Zainpay transactions by txn ref.
+     *
+     * Several Zainpay rows can share a txn ref, e.g. a transfer and its fee. Each row is matched
+     * to at most one local record: the row of the record's `transaction_type` when it has one,
+     * otherwise the row with the same amount, otherwise the first row left. Rows no local record
+     * matched are reported missing locally.
+     *
+     * A pair whose amounts differ is reported as an amount mismatch only, even if the statuses differ too.
+     * Statuses are compared case-insensitively and only when both sides carry one.
+     */
+    pub fn compare(local: &[LocalRecord], remote: &[Transaction]) -> ReconciliationReport {
+        let mut remote_by_ref: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, transaction) in remote.iter().enumerate() {
+            remote_by_ref
+                .entry(transaction.transaction_ref.as_str())
+                .or_default()
+                .push(index);
+        }
+
+        let mut report = ReconciliationReport::default();
+        let mut used = vec![false; remote.len()];
+
+        for record in local {
+            let candidates: Vec<usize> = remote_by_ref
+                .get(record.txn_ref.as_str())
+                .into_iter()
+                .flatten()
+                .copied()
+                .filter(|index| !used[*index])
+                .collect();
+            let found = match &record.transaction_type {
+                Some(kind) => candidates
+                    .iter()
+                    .find(|index| remote[**index].transaction_type.eq_ignore_ascii_case(kind)),
+                None => candidates
+                    .iter()
+                    .find(|index| remote[**index].amount == record.amount)
+                    .or(candidates.first()),
+            };
+            let Some(&index) = found else {
+                report.missing_on_zainpay.push(record.clone());
+                continue;
+            };
+            used[index] = true;
+            let transaction = &remote[index];
+
+            let pair = RecordPair {
+                local: record.clone(),
+                remote: transaction.clone(),
+            };
+
+            if record.amount != transaction.amount {
+                report.amount_mismatches.push(pair);
+            } else if !statuses_agree(record.status.as_deref(), transaction.status.as_deref()) {
+                report.status_mismatches.push(pair);
+            } else {
+                report.matched.push(pair);
+            }
+        }
+
+        for (transaction, used) in remote.iter().zip(used) {
+            if !used {
+                report.missing_locally.push(transaction.clone());
+            }
+        }
+
+        report
+    }
+
+    /**
+     * Fetch a zainbox's transaction history as typed transactions
+     *
+     * With both dates given as `YYYY-MM-DD`, the period is walked in windows of up to a week, and
+     * windows returning a full page of `count` transactions (500 by default) are split, so the
+     * history is not cut off at one page. Otherwise a single page of `count` is fetched.
+     *
+     * @param string $zainboxCode
+     * @param int|null $count
+     * @param string|null $dateFrom
+     * @param string|null $dateTo
+     * @return Vec<Transaction>
+     * @throws Box<dyn Error>
+     */
+    pub async fn fetch_zainbox_transactions(
+        &self,
+        zainbox_code: String,
+        count: Option<u32>,
+        date_from: Option<String>,
+        date_to: Option<String>,
+    ) -> Result<Vec<Transaction>, Box<dyn Error>> {
+        Ok(self.fetch(zainbox_code, count, date_from, date_to).await?.0)
+    }
+
+    /**
+     * Reconcile local records against a zainbox's transaction history for a period
+     *
+     * Local records dated outside the period are left out, so they are not reported missing on
+     * Zainpay. Days the history could not be fetched completely are listed in `truncated_days`.
+     * Dates that are not `YYYY-MM-DD` fail with `ZainpayError::InvalidDate`.
+     *
+     * @param string $zainboxCode
+     * @param array $local
+     * @param int|null $count
+     * @param string|null $dateFrom
+     * @param string|null $dateTo
+     * @return ReconciliationReport
+     * @throws Box<dyn Error>
+     */
+    pub async fn reconcile_zainbox(
+        &self,
+        zainbox_code: String,
+        local: &[LocalRecord],
+        count: Option<u32>,
+        date_from: Option<String>,
+        date_to: Option<String>,
+    ) -> Result<ReconciliationReport, Box<dyn Error>> {
+        let from = parse_date(date_from.as_deref())?;
+        let to = parse_date(date_to.as_deref())?;
+        let local: Vec<LocalRecord> = local
+            .iter()
+            .filter(|record| from.is_none_or(|from| record.date >= from))
+            .filter(|record| to.is_none_or(|to| record.date <= to))
+            .cloned()
+            .collect();
+
+        let (remote, truncated_days) = self.fetch(zainbox_code, count, date_from, date_to).await?;
+        let mut report = Self::compare(&local, &remote);
+        report.truncated_days = truncated_days;
+        Ok(report)
+    }
+
+    /// The transactions of a period and the days that could not be fetched completely.
+    pub(crate) async fn fetch(
+        &self,
+        zainbox_code: String,
+        count: Option<u32>,
+        date_from: Option<String>,
+        date_to: Option<String>,
+    ) -> Result<(Vec<Transaction>, Vec<NaiveDate>), Box<dyn Error>> {
+        let period = (
+            parse_date(date_from.as_deref())?,
+            parse_date(date_to.as_deref())?,
+        );
+        if let (Some(from), Some(to)) = period {
+            let exports = ExportService::new(self.engine.clone());
+            let source = HistorySource::Zainbox(zainbox_code);
+            let mut pager =
+                HistoryPager::new(&exports, &source, from, to, 7, count.unwrap_or(500))?;
+            let mut transactions = Vec::new();
+            while let Some(page) = pager.next().await? {
+                transactions.extend(page);
+            }
+            return Ok((transactions, pager.truncated_days));
+        }
+
+        let response = ZainboxService::new(self.engine.clone())
+            .get_zainbox_txn_history(zainbox_code, count, date_from, date_to, None, None, None)
+            .await?;
+
+        if response.has_failed() {
+            return Err(ZainpayError::from_response(&response).into());
+        }
+
+        Ok((
+            response
+                .parse_data::<Vec<Transaction>>()
+                .ok_or(ZainpayError::UnexpectedData)?,
+            Vec::new(),
+        ))
+    }
+
+    /**
+     * Ask Zainpay to act on the discrepancies of a report. Requests are sent one after another
+     * and every response is returned, whether or not Zainpay accepted it.
+     *
+     * @param ReconciliationReport $report
+     * @param ResolutionAction $action
+     * @return Vec<Resolution>
+     * @throws Box<dyn Error>
+     */
+    pub async fn resolve(
+        &self,
+        report: &ReconciliationReport,
+        action: &ResolutionAction,
+    ) -> Result<Vec<Resolution>, Box<dyn Error>> {
+        let bank = BankService::new(self.engine.clone());
+        let mut resolutions = Vec::new();
+
+        match action {
+            ResolutionAction::RepushDeposit => {
+                for transaction in report.missing_locally.iter().filter(|t| t.is_deposit()) {
+                    let response = bank
+                        .repush_deposit_event(transaction.transaction_ref.clone())
+                        .await?;
+                    resolutions.push(Resolution {
+                        txn_ref: transaction.transaction_ref.clone(),
+                        response,
+                    });
+                }
+            }
+            ResolutionAction::ReconcileBankDeposit {
+                verification_type,
+                bank_type,
+            } => {
+                for record in &report.missing_on_zainpay {
+                    let Some(account_number) = record.account_number.clone() else {
+                        continue;
+                    };
+                    let response = bank
+                        .reconcile_bank_deposit(
+                            verification_type.clone(),
+                            bank_type.clone(),
+                            account_number,
+                            record.session_id.clone(),
+                        )
+                        .await?;
+                    resolutions.push(Resolution {
+                        txn_ref: record.txn_ref.clone(),
+                        response,
+                    });
+                }
+            }
+        }
+
+        Ok(resolutions)
+    }
+}
+
+fn parse_date(date: Option<&str>) -> Result<Option<NaiveDate>, ZainpayError> {
+    date.map(|date| {
+        NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+            .map_err(|_| ZainpayError::InvalidDate(date.to_string()))
+    })
+    .transpose()
+}
+
+fn statuses_agree(local: Option<&str>, remote: Option<&str>) -> bool {
+    match (local, remote) {
+        (Some(local), Some(remote)) => normalize_status(local) == normalize_status(remote),
+        _ => true,
+    }
+}
+
+fn normalize_status(status: &str) -> String {
+    match status.trim().to_ascii_lowercase().as_str() {
+        "successful" | "succeeded" | "completed" => "success".to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enviroment::Environment;
+    use crate::transport::{HttpResponse, MockTransport};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn transaction(txn_ref: &str, date: &str) -> serde_json::Value {
+        json!({
+            "transactionRef": txn_ref,
+            "transactionType": "deposit",
+            "amount": "1000",
+            "transactionDate": format!("{}T10:00:00", date),
+        })
+    }
+
+    /// History of one deposit a day in March 2024, answering each request with the days asked.
+    fn engine() -> (Engine, Arc<MockTransport>) {
+        let transport = Arc::new(MockTransport::new().handler(|request| {
+            let from = request.query("dateFrom")?;
+            let to = request.query("dateTo")?;
+            let data: Vec<_> = (1..=31)
+                .map(|day| format!("2024-03-{:02}", day))
+                .filter(|date| date.as_str() >= from && date.as_str() <= to)
+                .map(|date| transaction(&format!("REF-{}", date), &date))
+                .collect();
+            Some(HttpResponse::new(
+                200,
+                &json!({ "code": "00", "data": data }).to_string(),
+            ))
+        }));
+        let engine = Engine::builder(Environment::Sandbox, "key")
+            .transport(transport.clone())
+            .build();
+        (engine, transport)
+    }
+
+    #[tokio::test]
+    async fn pages_through_the_period_and_ignores_local_records_outside_it() {
+        let (engine, transport) = engine();
+        let local = vec![
+            LocalRecord::new(
+                "REF-2024-03-05".to_string(),
+                1000,
+                NaiveDate::from_ymd_opt(2024, 3, 5).unwrap(),
+            ),
+            LocalRecord::new(
+                "REF-2024-02-28".to_string(),
+                1000,
+                NaiveDate::from_ymd_opt(2024, 2, 28).unwrap(),
+            ),
+        ];
+
+        let report = ReconcileService::new(engine)
+            .reconcile_zainbox(
+                "ZB".to_string(),
+                &local,
+                Some(4),
+                Some("2024-03-01".to_string()),
+                Some("2024-03-20".to_string()),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(report.matched.len(), 1);
+        assert!(report.missing_on_zainpay.is_empty());
+        assert_eq!(report.missing_locally.len(), 19);
+        assert!(report.truncated_days.is_empty());
+        assert!(transport.requests().len() > 3);
+    }
+
+    #[tokio::test]
+    async fn reports_days_that_fill_a_page() {
+        let (engine, _) = engine();
+        let report = ReconcileService::new(engine)
+            .reconcile_zainbox(
+                "ZB".to_string(),
+                &[],
+                Some(1),
+                Some("2024-03-01".to_string()),
+                Some("2024-03-02".to_string()),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(report.truncated_days.len(), 2);
+        assert!(!report.is_balanced());
+    }
+
+    fn row(txn_ref: &str, kind: &str, amount: &str) -> Transaction {
+        serde_json::from_value(json!({
+            "txnRef": txn_ref,
+            "txnType": kind,
+            "amount": amount,
+            "txnDate": "2024-03-01T10:00:00",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn a_fee_sharing_the_reference_is_not_compared_with_the_transfer() {
+        let day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let remote = vec![row("TRF-1", "fee", "10"), row("TRF-1", "transfer", "1500")];
+
+        let by_amount = LocalRecord::new("TRF-1".to_string(), 1500, day);
+        let report = ReconcileService::compare(&[by_amount], &remote);
+        assert_eq!(report.matched.len(), 1);
+        assert_eq!(report.matched[0].remote.transaction_type, "transfer");
+        assert!(report.amount_mismatches.is_empty());
+        assert_eq!(report.missing_locally.len(), 1);
+        assert_eq!(report.missing_locally[0].transaction_type, "fee");
+
+        let mut transfer = LocalRecord::new("TRF-1".to_string(), 1400, day);
+        transfer.transaction_type = Some("Transfer".to_string());
+        let mut fee = LocalRecord::new("TRF-1".to_string(), 10, day);
+        fee.transaction_type = Some("fee".to_string());
+        let report = ReconcileService::compare(&[transfer, fee], &remote);
+        assert_eq!(report.matched.len(), 1);
+        assert_eq!(report.amount_mismatches.len(), 1);
+        assert_eq!(
+            report.amount_mismatches[0].remote.transaction_type,
+            "transfer"
+        );
+        assert!(report.missing_locally.is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_dates_are_refused() {
+        let (engine, transport) = engine();
+        let error = ReconcileService::new(engine)
+            .reconcile_zainbox(
+                "ZB".to_string(),
+                &[],
+                None,
+                Some("01/03/2024".to_string()),
+                Some("2024-03-02".to_string()),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<ZainpayError>(),
+            Some(ZainpayError::InvalidDate(date)) if date == "01/03/2024"
+        ));
+        assert!(transport.requests().is_empty());
+    }
+}
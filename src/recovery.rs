@@ -0,0 +1,279 @@
+use crate::bank::BankService;
+use crate::engine::Engine;
+use crate::models::model::Transaction;
+use crate::reconcile::ReconcileService;
+use crate::response::Response;
+use chrono::NaiveDate;
+use std::collections::HashSet;
+use std::error::Error;
+use std::time::Duration;
+
+/// How a missed deposit is recovered.
+#[derive(Debug, Clone)]
+pub enum RecoveryAction {
+    /// Ask Zainpay to resend the deposit callback.
+    Repush,
+
+    /// Ask Zainpay to reconcile the deposit against the bank, using the deposit's account number.
+    Reconcile {
+        verification_type: String,
+        bank_type: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct RecoveryOptions {
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    /// Page size of the transaction history requests. With both dates set as `YYYY-MM-DD` the
+    /// whole window is scanned page by page; otherwise only one page of `count` entries is.
+    pub count: Option<u32>,
+    pub action: RecoveryAction,
+    /// Pause between two recovery requests, to stay under Zainpay's rate limits.
+    pub delay: Duration,
+    /// Report what would be recovered without calling Zainpay.
+    pub dry_run: bool,
+}
+
+impl Default for RecoveryOptions {
+    fn default() -> Self {
+        Self {
+            date_from: None,
+            date_to: None,
+            count: None,
+            action: RecoveryAction::Repush,
+            delay: Duration::from_millis(500),
+            dry_run: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RecoveryStatus {
+    DryRun,
+    /// Skipped because the deposit carries no account number to reconcile against.
+    Skipped,
+    Accepted(Response),
+    Rejected(Response),
+    /// The request did not get an answer from Zainpay, e.g. a transport error.
+    Errored(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct RecoveryOutcome {
+    pub deposit: Transaction,
+    pub status: RecoveryStatus,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RecoveryReport {
+    /// Number of deposits found in the window.
+    pub scanned: usize,
+    pub outcomes: Vec<RecoveryOutcome>,
+    /// Days whose history filled a whole page, so some deposits may not have been scanned.
+    pub truncated_days: Vec<NaiveDate>,
+}
+
+impl RecoveryReport {
+    pub fn missing(&self) -> impl Iterator<Item = &Transaction> {
+        self.outcomes.iter().map(|outcome| &outcome.deposit)
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &RecoveryOutcome> {
+        self.outcomes.iter().filter(|outcome| {
+            matches!(
+                outcome.status,
+                RecoveryStatus::Rejected(_) | RecoveryStatus::Errored(_)
+            )
+        })
+    }
+}
+
+pub struct RecoveryService {
+    engine: Engine,
+}
+
+impl RecoveryService {
+    pub fn new(engine: Engine) -> Self {
+        Self { engine }
+    }
+
+    /**
+     * Find the deposits of a zainbox that are not in the set of processed txn refs, and repush or
+     * reconcile each of them, pausing `options.delay` between two requests.
+     *
+     * A request that fails is recorded as `Errored` and the job moves on to the next deposit, so
+     * the report always lists what was already sent.
+     *
+     * @param string $zainboxCode
+     * @param HashSet<String> $processed
+     * @param RecoveryOptions $options
+     * @return RecoveryReport
+     * @throws Box<dyn Error>
+     */
+    pub async fn recover_deposits(
+        &self,
+        zainbox_code: String,
+        processed: &HashSet<String>,
+        options: &RecoveryOptions,
+    ) -> Result<RecoveryReport, Box<dyn Error>> {
+        let (transactions, truncated_days) = ReconcileService::new(self.engine.clone())
+            .fetch(
+                zainbox_code,
+                options.count,
+                options.date_from.clone(),
+                options.date_to.clone(),
+            )
+            .await?;
+        let deposits: Vec<Transaction> = transactions
+            .into_iter()
+            .filter(Transaction::is_deposit)
+            .collect();
+
+        let mut report = RecoveryReport {
+            scanned: deposits.len(),
+            outcomes: Vec::new(),
+            truncated_days,
+        };
+
+        let bank = BankService::new(self.engine.clone());
+        let mut sent_any = false;
+
+        for deposit in deposits {
+            if processed.contains(&deposit.transaction_ref) {
+                continue;
+            }
+
+            if options.dry_run {
+                report.outcomes.push(RecoveryOutcome {
+                    deposit,
+                    status: RecoveryStatus::DryRun,
+                });
+                continue;
+            }
+
+            let account_number = deposit.account_number.clone();
+            if matches!(options.action, RecoveryAction::Reconcile { .. })
+                && account_number.is_none()
+            {
+                report.outcomes.push(RecoveryOutcome {
+                    deposit,
+                    status: RecoveryStatus::Skipped,
+                });
+                continue;
+            }
+
+            if sent_any {
+                tokio::time::sleep(options.delay).await;
+            }
+            sent_any = true;
+
+            let sent = match &options.action {
+                RecoveryAction::Repush => {
+                    bank.repush_deposit_event(deposit.transaction_ref.clone())
+                        .await
+                }
+                RecoveryAction::Reconcile {
+                    verification_type,
+                    bank_type,
+                } => {
+                    bank.reconcile_bank_deposit(
+                        verification_type.clone(),
+                        bank_type.clone(),
+                        account_number.unwrap_or_default(),
+                        None,
+                    )
+                    .await
+                }
+            };
+
+            let status = match sent {
+                Ok(response) if response.has_succeeded() => RecoveryStatus::Accepted(response),
+                Ok(response) => RecoveryStatus::Rejected(response),
+                Err(error) => {
+                    tracing::warn!(txn_ref = %deposit.transaction_ref, error = %error, "deposit recovery request failed");
+                    RecoveryStatus::Errored(error.to_string())
+                }
+            };
+            report.outcomes.push(RecoveryOutcome { deposit, status });
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enviroment::Environment;
+    use crate::transport::{HttpRequest, HttpResponse, Transport, TransportError};
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    /// Serves a history of three deposits and fails the repush of `REF-2` at the transport level.
+    #[derive(Debug, Default)]
+    struct FlakyTransport {
+        repushed: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Transport for FlakyTransport {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+            let path = request
+                .path()
+                .split('?')
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            if let Some(txn_ref) = path.strip_prefix("zainbox/repush/deposit/") {
+                if txn_ref == "REF-2" {
+                    return Err("connection reset".into());
+                }
+                self.repushed.lock().unwrap().push(txn_ref.to_string());
+                return Ok(HttpResponse::new(200, r#"{"code":"00"}"#));
+            }
+            let data: Vec<_> = (1..=3)
+                .map(|n| {
+                    json!({
+                        "transactionRef": format!("REF-{}", n),
+                        "transactionType": "deposit",
+                        "amount": "1000",
+                        "transactionDate": "2024-03-01T10:00:00",
+                    })
+                })
+                .collect();
+            Ok(HttpResponse::new(
+                200,
+                &json!({ "code": "00", "data": data }).to_string(),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failed_request_is_reported_and_the_job_continues() {
+        let transport = Arc::new(FlakyTransport::default());
+        let engine = Engine::builder(Environment::Sandbox, "key")
+            .transport(transport.clone())
+            .build();
+        let options = RecoveryOptions {
+            date_from: Some("2024-03-01".to_string()),
+            date_to: Some("2024-03-01".to_string()),
+            delay: Duration::ZERO,
+            ..RecoveryOptions::default()
+        };
+
+        let report = RecoveryService::new(engine)
+            .recover_deposits("ZB".to_string(), &HashSet::new(), &options)
+            .await
+            .unwrap();
+
+        assert_eq!(report.outcomes.len(), 3);
+        assert!(matches!(
+            report.outcomes[1].status,
+            RecoveryStatus::Errored(_)
+        ));
+        assert_eq!(report.failed().count(), 1);
+        assert_eq!(*transport.repushed.lock().unwrap(), vec!["REF-1", "REF-3"]);
+    }
+}
@@ -1,8 +1,12 @@
+use crate::error::ErrorCode;
 use serde_json::Value;
 use serde::de::DeserializeOwned;
+use crate::utils::redact::redact;
 use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug, Clone)]
+/// `Debug` redacts personal data from the decoded body.
+#[derive(Clone)]
 pub struct Response {
     status_code: u16,
     decoded_response: Option<HashMap<String, Value>>,
@@ -11,17 +15,11 @@ pub struct Response {
 }
 
 impl Response {
-    pub async fn new(response: reqwest::Response) -> Self {
-        let status_code = response.status().as_u16();
-        let body_result = response.text().await;
-        println!("Response body: {:?}", body_result);
-        
-        let decoded_response = body_result
-            .ok()
-            .and_then(|body| serde_json::from_str::<HashMap<String, Value>>(&body).ok());
-        
+    pub fn new(status_code: u16, body: &str) -> Self {
+        let decoded_response = serde_json::from_str::<HashMap<String, Value>>(body).ok();
+
         let error = status_code >= 400;
-        
+
         Self {
             status_code,
             decoded_response,
@@ -31,13 +29,18 @@ impl Response {
     }
 
     pub fn has_succeeded(&self) -> bool {
-        !self.error && matches!(self.get_code().as_deref(), Some("200") | Some("00") | Some("21"))
+        !self.error && self.error_code().is_some_and(|code| code.is_accepted())
     }
 
     pub fn has_failed(&self) -> bool {
         self.error || !self.has_succeeded()
     }
 
+    /// Whether Zainpay rejected the request for exceeding the merchant's rate limit (HTTP 429).
+    pub fn is_rate_limited(&self) -> bool {
+        self.status_code == 429
+    }
+
     pub fn get_status(&self) -> Option<&str> {
         self.decoded_response
             .as_ref()
@@ -50,6 +53,11 @@ impl Response {
             .and_then(|map| map.get("code")?.as_str())
     }
 
+    /// The `code` of the response, looked up in the catalogue.
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        self.get_code().map(ErrorCode::parse)
+    }
+
     pub fn get_description(&self) -> Option<&str> {
         self.decoded_response
             .as_ref()
@@ -90,4 +98,40 @@ impl Response {
     pub fn full_json(&self) -> Option<&HashMap<String, Value>> {
         self.decoded_response.as_ref()
     }
-}
\ No newline at end of file
+}
+
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decoded_response = self
+            .decoded_response
+            .as_ref()
+            .and_then(|decoded| serde_json::to_value(decoded).ok())
+            .map(|decoded| redact(&decoded));
+        f.debug_struct("Response")
+            .field("status_code", &self.status_code)
+            .field("decoded_response", &decoded_response)
+            .field("error", &self.error)
+            .field("error_message", &self.error_message)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_redacts_the_body() {
+        let response = Response::new(
+            200,
+            r#"{"code":"00","data":{"email":"ada@example.com","txnRef":"REF-1"}}"#,
+        );
+        let debug = format!("{:?}", response);
+        assert!(!debug.contains("ada@example.com"), "{}", debug);
+        assert!(debug.contains("REF-1"), "{}", debug);
+        assert_eq!(
+            response.get_raw_data().unwrap()["email"],
+            "ada@example.com"
+        );
+    }
+}
@@ -52,8 +52,10 @@ impl SettlementService {
             "status": status,
         });
 
-        let http_response = self.engine.post("zainbox/settlement", &payload).await?;
-        Ok(Response::new(http_response).await)
+        self.engine
+            .endpoint("zainbox/settlement")
+            .post("zainbox/settlement", &payload)
+            .await
     }
 
     /**
@@ -69,11 +71,10 @@ impl SettlementService {
         &self,
         zainbox_code: String,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/settlement")
             .get(&format!("zainbox/settlement?zainboxCode={}", zainbox_code))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     /**
@@ -98,8 +99,8 @@ impl SettlementService {
         date_to: Option<String>,
         status: Option<String>,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/settlement/history/{}")
             .get(&format!(
                 "zainbox/settlement/history/{}?{}&{}",
                 zainbox_code,
@@ -108,8 +109,7 @@ impl SettlementService {
                     date_from, date_to, None, status, None, None, None, None
                 )
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     pub fn settlement_account_payload(
@@ -0,0 +1,485 @@
+//! Webhook deliveries for local development.
+//!
+//! [`WebhookSimulator`] posts deposit, transfer and card payment events to a callback URL, shaped
+//! like Zainpay's and optionally signed the way
+//! [`WebhookReceiver`](crate::webhook::WebhookReceiver) verifies them. The signature is the
+//! SDK's own scheme, see [`webhook`](crate::webhook), not one defined by Zainpay. Events can
+//! fail, be delivered several times, arrive out of order, or be tampered with, so handlers can be
+//! exercised without a public URL or sandbox traffic.
+
+use crate::secret::Secret;
+use crate::transport::{
+    HttpRequest, HttpResponse, Method, ReqwestTransport, Transport, TransportError,
+};
+use crate::webhook::{self, WebhookEvent, SIGNATURE_HEADER};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Deposit,
+    Transfer,
+    CardPayment,
+}
+
+/// Ways a delivery can be spoiled on purpose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tamper {
+    /// Signed, but the signature does not match the body.
+    BadSignature,
+    /// Sent without a signature header.
+    Unsigned,
+    /// The body is cut in half, so it is not valid JSON. It is still signed correctly.
+    MalformedBody,
+}
+
+/// One event to deliver. The amount is in kobo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedEvent {
+    pub kind: EventKind,
+    pub txn_ref: String,
+    pub amount: i64,
+    pub succeeded: bool,
+    pub account_number: String,
+    pub zainbox_code: String,
+    pub narration: String,
+    pub txn_date: DateTime<Utc>,
+    /// Extra deliveries of the same event.
+    pub duplicates: u32,
+    pub tamper: Option<Tamper>,
+}
+
+impl SimulatedEvent {
+    pub fn new(kind: EventKind, amount: i64) -> Self {
+        Self {
+            kind,
+            txn_ref: format!("SIM-{}", uuid::Uuid::new_v4().simple()),
+            amount,
+            succeeded: true,
+            account_number: "0000000000".to_string(),
+            zainbox_code: "SIMULATED".to_string(),
+            narration: "Simulated webhook".to_string(),
+            txn_date: Utc::now(),
+            duplicates: 0,
+            tamper: None,
+        }
+    }
+
+    pub fn deposit(amount: i64) -> Self {
+        Self::new(EventKind::Deposit, amount)
+    }
+
+    pub fn transfer(amount: i64) -> Self {
+        Self::new(EventKind::Transfer, amount)
+    }
+
+    pub fn card_payment(amount: i64) -> Self {
+        Self::new(EventKind::CardPayment, amount)
+    }
+
+    pub fn txn_ref(mut self, txn_ref: &str) -> Self {
+        self.txn_ref = txn_ref.to_string();
+        self
+    }
+
+    /// Report the transaction as failed rather than successful.
+    pub fn failed(mut self) -> Self {
+        self.succeeded = false;
+        self
+    }
+
+    pub fn account_number(mut self, account_number: &str) -> Self {
+        self.account_number = account_number.to_string();
+        self
+    }
+
+    pub fn zainbox_code(mut self, zainbox_code: &str) -> Self {
+        self.zainbox_code = zainbox_code.to_string();
+        self
+    }
+
+    pub fn narration(mut self, narration: &str) -> Self {
+        self.narration = narration.to_string();
+        self
+    }
+
+    pub fn at(mut self, txn_date: DateTime<Utc>) -> Self {
+        self.txn_date = txn_date;
+        self
+    }
+
+    /// Deliver the event `duplicates` more times.
+    pub fn duplicates(mut self, duplicates: u32) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
+
+    pub fn tamper(mut self, tamper: Tamper) -> Self {
+        self.tamper = Some(tamper);
+        self
+    }
+
+    /// `deposit.success`, `transfer.failed`, `card.success` and so on.
+    pub fn event_name(&self) -> String {
+        let kind = match self.kind {
+            EventKind::Deposit => "deposit",
+            EventKind::Transfer => "transfer",
+            EventKind::CardPayment => "card",
+        };
+        let outcome = if self.succeeded { "success" } else { "failed" };
+        format!("{}.{}", kind, outcome)
+    }
+
+    pub fn to_event(&self) -> WebhookEvent {
+        let date = self.txn_date.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let status = if self.succeeded { "success" } else { "failed" };
+        let amount = json!({ "amount": self.amount, "currency": "NGN" });
+        let data = match self.kind {
+            EventKind::Deposit => json!({
+                "accountName": "Simulated Customer",
+                "accountNumber": self.account_number,
+                "amount": amount,
+                "bankName": "Simulated Bank",
+                "narration": self.narration,
+                "paymentDate": date,
+                "paymentRef": self.txn_ref,
+                "sender": "0000000000",
+                "senderName": "Simulated Sender",
+                "txnDate": date,
+                "txnRef": self.txn_ref,
+                "txnStatus": status,
+                "txnType": "deposit",
+                "zainboxCode": self.zainbox_code,
+            }),
+            EventKind::Transfer => json!({
+                "amount": amount,
+                "beneficiaryAccountNumber": self.account_number,
+                "beneficiaryBankCode": "000",
+                "narration": self.narration,
+                "txnDate": date,
+                "txnRef": self.txn_ref,
+                "txnStatus": status,
+                "txnType": "transfer",
+                "zainboxCode": self.zainbox_code,
+            }),
+            EventKind::CardPayment => json!({
+                "amount": amount,
+                "emailAddress": "customer@example.com",
+                "narration": self.narration,
+                "paymentStatus": status,
+                "txnDate": date,
+                "txnRef": self.txn_ref,
+                "txnType": "card",
+                "zainboxCode": self.zainbox_code,
+            }),
+        };
+        WebhookEvent {
+            event: self.event_name(),
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeliveryOrder {
+    /// In the order events were added, each followed by its duplicates.
+    #[default]
+    AsAdded,
+    Reversed,
+    /// A shuffle that is the same for the same seed, so failures can be reproduced.
+    Shuffled {
+        seed: u64,
+    },
+}
+
+/// The outcome of one delivery.
+#[derive(Debug)]
+pub struct SimulatedDelivery {
+    pub event: String,
+    pub txn_ref: String,
+    /// 0 for the first delivery of an event, then 1, 2, ... for its duplicates.
+    pub copy: u32,
+    pub request: HttpRequest,
+    pub result: Result<HttpResponse, TransportError>,
+}
+
+pub struct WebhookSimulator {
+    callback_url: String,
+    transport: Arc<dyn Transport>,
+    signing_secret: Option<Secret<String>>,
+    order: DeliveryOrder,
+    events: Vec<SimulatedEvent>,
+}
+
+impl WebhookSimulator {
+    pub fn new(callback_url: &str) -> Self {
+        Self {
+            callback_url: callback_url.to_string(),
+            transport: Arc::new(ReqwestTransport::new()),
+            signing_secret: None,
+            order: DeliveryOrder::default(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Send through `transport` instead of the default `ReqwestTransport`.
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Sign deliveries with `secret`, using the SDK's own `Zainpay-Signature` scheme. Without one,
+    /// deliveries are sent unsigned.
+    pub fn signing_secret(mut self, secret: &str) -> Self {
+        self.signing_secret = Some(Secret::from(secret));
+        self
+    }
+
+    pub fn order(mut self, order: DeliveryOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn event(mut self, event: SimulatedEvent) -> Self {
+        self.events.push(event);
+        self
+    }
+
+    /// The requests `run` would send, in the order it would send them.
+    pub fn requests(&self) -> Vec<(&SimulatedEvent, u32, HttpRequest)> {
+        let mut requests: Vec<(&SimulatedEvent, u32, HttpRequest)> = self
+            .events
+            .iter()
+            .flat_map(|event| (0..=event.duplicates).map(move |copy| (event, copy)))
+            .map(|(event, copy)| (event, copy, self.request(event)))
+            .collect();
+
+        match self.order {
+            DeliveryOrder::AsAdded => {}
+            DeliveryOrder::Reversed => requests.reverse(),
+            DeliveryOrder::Shuffled { seed } => {
+                // xorshift64, enough to shuffle test traffic without a rand dependency.
+                let mut state = seed.max(1);
+                for index in (1..requests.len()).rev() {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    requests.swap(index, (state % (index as u64 + 1)) as usize);
+                }
+            }
+        }
+        requests
+    }
+
+    /**
+     * Deliver every event to the callback URL, one at a time
+     *
+     * A delivery that fails does not stop the others; its error is reported in its result.
+     *
+     * @return Vec<SimulatedDelivery>
+     */
+    pub async fn run(&self) -> Vec<SimulatedDelivery> {
+        let mut deliveries = Vec::new();
+        for (event, copy, request) in self.requests() {
+            let result = self.transport.send(request.clone()).await;
+            deliveries.push(SimulatedDelivery {
+                event: event.event_name(),
+                txn_ref: event.txn_ref.clone(),
+                copy,
+                request,
+                result,
+            });
+        }
+        deliveries
+    }
+
+    fn request(&self, event: &SimulatedEvent) -> HttpRequest {
+        let mut body = serde_json::to_string(&event.to_event()).unwrap_or_default();
+        if event.tamper == Some(Tamper::MalformedBody) {
+            let mut half = body.len() / 2;
+            while !body.is_char_boundary(half) {
+                half -= 1;
+            }
+            body.truncate(half);
+        }
+        let signature = self
+            .signing_secret
+            .as_ref()
+            .and_then(|secret| match event.tamper {
+                Some(Tamper::Unsigned) => None,
+                Some(Tamper::BadSignature) => {
+                    Some(webhook::sign(secret.expose(), &format!("{} ", body)))
+                }
+                _ => Some(webhook::sign(secret.expose(), &body)),
+            });
+
+        let mut headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+        if let Some(signature) = signature {
+            headers.push((SIGNATURE_HEADER.to_string(), signature));
+        }
+        HttpRequest {
+            method: Method::Post,
+            url: self.callback_url.clone(),
+            headers,
+            body: Some(body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+
+    const CALLBACK_URL: &str = "http://localhost:3000/zainpay/webhook";
+
+    fn simulator() -> WebhookSimulator {
+        WebhookSimulator::new(CALLBACK_URL).transport(Arc::new(MockTransport::new()))
+    }
+
+    fn body(request: &HttpRequest) -> &str {
+        request.body.as_deref().unwrap()
+    }
+
+    fn signed_by(request: &HttpRequest, secret: &str) -> bool {
+        request
+            .header(SIGNATURE_HEADER)
+            .is_some_and(|signature| webhook::verify_signature(secret, body(request), signature))
+    }
+
+    #[tokio::test]
+    async fn posts_signed_events_to_the_callback_url() {
+        let transport = Arc::new(MockTransport::new().route(
+            Method::Post,
+            "zainpay/webhook",
+            HttpResponse::new(200, "{}"),
+        ));
+        let simulator = WebhookSimulator::new(CALLBACK_URL)
+            .transport(transport.clone())
+            .signing_secret("secret")
+            .event(SimulatedEvent::deposit(500_000).txn_ref("DEP-1"))
+            .event(SimulatedEvent::transfer(150_000).txn_ref("TRF-1").failed());
+
+        let deliveries = simulator.run().await;
+
+        let events: Vec<_> = deliveries
+            .iter()
+            .map(|delivery| (delivery.event.as_str(), delivery.txn_ref.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            [("deposit.success", "DEP-1"), ("transfer.failed", "TRF-1")]
+        );
+        assert!(deliveries
+            .iter()
+            .all(|delivery| delivery.result.as_ref().unwrap().status == 200));
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].url, CALLBACK_URL);
+        assert!(requests.iter().all(|request| signed_by(request, "secret")));
+        let event = WebhookEvent::parse(body(&requests[0])).unwrap();
+        assert_eq!(event.id().unwrap(), "deposit.success:DEP-1");
+        assert_eq!(event.data["amount"]["amount"], 500_000);
+    }
+
+    #[test]
+    fn events_are_unsigned_without_a_secret() {
+        let simulator = simulator().event(SimulatedEvent::card_payment(10_000));
+        let requests = simulator.requests();
+        assert!(requests[0].2.header(SIGNATURE_HEADER).is_none());
+    }
+
+    #[test]
+    fn duplicates_repeat_the_same_delivery() {
+        let simulator = simulator()
+            .signing_secret("secret")
+            .event(SimulatedEvent::deposit(500_000).duplicates(2));
+
+        let requests = simulator.requests();
+
+        let copies: Vec<u32> = requests.iter().map(|(_, copy, _)| *copy).collect();
+        assert_eq!(copies, [0, 1, 2]);
+        assert!(requests
+            .iter()
+            .all(|(_, _, request)| body(request) == body(&requests[0].2)));
+        assert!(requests
+            .iter()
+            .all(|(_, _, request)| signed_by(request, "secret")));
+    }
+
+    #[test]
+    fn a_shuffle_depends_only_on_its_seed() {
+        let order = |order: DeliveryOrder| -> Vec<String> {
+            let mut simulator = simulator().order(order);
+            for index in 0..10 {
+                simulator = simulator
+                    .event(SimulatedEvent::deposit(100).txn_ref(&format!("DEP-{}", index)));
+            }
+            simulator
+                .requests()
+                .iter()
+                .map(|(event, _, _)| event.txn_ref.clone())
+                .collect()
+        };
+
+        let as_added = order(DeliveryOrder::AsAdded);
+        let shuffled = order(DeliveryOrder::Shuffled { seed: 42 });
+        assert_eq!(shuffled, order(DeliveryOrder::Shuffled { seed: 42 }));
+        assert_ne!(shuffled, as_added);
+        assert_ne!(shuffled, order(DeliveryOrder::Shuffled { seed: 7 }));
+
+        let mut sorted = shuffled.clone();
+        sorted.sort();
+        let mut expected = as_added.clone();
+        expected.sort();
+        assert_eq!(sorted, expected);
+
+        let mut reversed = as_added;
+        reversed.reverse();
+        assert_eq!(order(DeliveryOrder::Reversed), reversed);
+    }
+
+    #[test]
+    fn each_tamper_spoils_the_delivery_its_own_way() {
+        let request = |tamper: Tamper| {
+            let simulator = simulator()
+                .signing_secret("secret")
+                .event(SimulatedEvent::deposit(500_000).tamper(tamper));
+            simulator.requests().remove(0).2
+        };
+
+        let bad_signature = request(Tamper::BadSignature);
+        assert!(bad_signature.header(SIGNATURE_HEADER).is_some());
+        assert!(!signed_by(&bad_signature, "secret"));
+        assert!(WebhookEvent::parse(body(&bad_signature)).is_ok());
+
+        let unsigned = request(Tamper::Unsigned);
+        assert!(unsigned.header(SIGNATURE_HEADER).is_none());
+        assert!(WebhookEvent::parse(body(&unsigned)).is_ok());
+
+        let malformed = request(Tamper::MalformedBody);
+        assert!(signed_by(&malformed, "secret"));
+        assert!(WebhookEvent::parse(body(&malformed)).is_err());
+    }
+
+    #[test]
+    fn a_malformed_body_is_cut_between_characters() {
+        for padding in 0..3 {
+            // Long enough that the middle of the body falls inside the narration.
+            let narration = format!("{}{}", "x".repeat(padding), "₦".repeat(100));
+            let event = SimulatedEvent::deposit(500_000)
+                .txn_ref("DEP-1")
+                .narration(&narration)
+                .tamper(Tamper::MalformedBody);
+            let full = serde_json::to_string(&event.to_event()).unwrap();
+
+            let simulator = simulator().event(event);
+            let request = &simulator.requests()[0].2;
+
+            assert!(full.starts_with(body(request)));
+            assert!(body(request).len() <= full.len() / 2);
+        }
+    }
+}
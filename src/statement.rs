@@ -0,0 +1,487 @@
+//! Account statements for virtual accounts.
+//!
+//! Zainpay only reports the current balance of an account, so the closing balance of a period is
+//! worked back from it through the transactions made since, and the opening balance from the
+//! closing balance through the transactions of the period. All amounts are in kobo.
+
+use crate::engine::Engine;
+use crate::error::ZainpayError;
+use crate::export::{signed_amount, AmountFormat, ExportService, HistoryPager, HistorySource};
+use crate::models::model::{parse_kobo, Transaction, VirtualAccountBalance};
+use crate::virtual_account;
+use crate::zainbox::ZainboxService;
+use chrono::{NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt::Write as _;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementLine {
+    pub date: String,
+    pub reference: String,
+    /// Narration of the transaction, or its type when it has none.
+    pub description: String,
+    pub credit: Option<i64>,
+    pub debit: Option<i64>,
+    pub fee: Option<i64>,
+    pub balance: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Statement {
+    pub account_number: String,
+    pub account_name: Option<String>,
+    pub bank_type: Option<String>,
+    pub zainbox_code: Option<String>,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub opening_balance: i64,
+    pub total_credits: i64,
+    /// Debits other than fees.
+    pub total_debits: i64,
+    pub total_fees: i64,
+    pub closing_balance: i64,
+    /// What the whole zainbox collected over the period, when a zainbox was given.
+    pub zainbox_collected: Option<i64>,
+    pub lines: Vec<StatementLine>,
+    /// Days whose history filled a whole page. Transactions may be missing from these days, and
+    /// the opening balance and totals are then unreliable.
+    pub truncated_days: Vec<NaiveDate>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StatementBuilder {
+    account_number: String,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+    zainbox_code: Option<String>,
+    fee_types: Vec<String>,
+    page_size: u32,
+}
+
+impl Statement {
+    pub fn builder(
+        account_number: &str,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+    ) -> StatementBuilder {
+        StatementBuilder {
+            account_number: account_number.to_string(),
+            period_start,
+            period_end,
+            zainbox_code: None,
+            fee_types: vec!["fee".to_string(), "charge".to_string()],
+            page_size: 500,
+        }
+    }
+
+    /// Whether the whole history of the period was fetched.
+    pub fn is_complete(&self) -> bool {
+        self.truncated_days.is_empty()
+    }
+
+    pub fn to_text(&self) -> String {
+        let naira = |kobo: i64| AmountFormat::Naira.format(kobo);
+        let optional = |kobo: Option<i64>| kobo.map(naira).unwrap_or_default();
+
+        let mut text = String::new();
+        let _ = writeln!(text, "STATEMENT OF ACCOUNT");
+        let _ = writeln!(text, "Account:  {}", self.account_heading());
+        let _ = writeln!(
+            text,
+            "Period:   {} to {}",
+            self.period_start, self.period_end
+        );
+        if let Some(zainbox_code) = &self.zainbox_code {
+            let _ = writeln!(text, "Zainbox:  {}", zainbox_code);
+        }
+        if let Some(warning) = self.incomplete_warning() {
+            let _ = writeln!(text, "Warning:  {}", warning);
+        }
+        let _ = writeln!(text);
+
+        let summary = self.summary();
+        let width = summary
+            .iter()
+            .map(|(label, _)| label.len())
+            .max()
+            .unwrap_or(0);
+        for (label, amount) in &summary {
+            let _ = writeln!(
+                text,
+                "{:width$}  {:>16}",
+                label,
+                naira(*amount),
+                width = width
+            );
+        }
+        let _ = writeln!(text);
+
+        let headers = [
+            "Date",
+            "Reference",
+            "Description",
+            "Credit",
+            "Debit",
+            "Fee",
+            "Balance",
+        ];
+        let rows: Vec<[String; 7]> = self
+            .lines
+            .iter()
+            .map(|line| {
+                [
+                    line.date.clone(),
+                    line.reference.clone(),
+                    line.description.clone(),
+                    optional(line.credit),
+                    optional(line.debit),
+                    optional(line.fee),
+                    naira(line.balance),
+                ]
+            })
+            .collect();
+        let widths: Vec<usize> = (0..headers.len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].chars().count())
+                    .chain(std::iter::once(headers[column].len()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let mut write_row = |cells: &[&str]| {
+            let line: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(column, (cell, width))| {
+                    if column >= 3 {
+                        format!("{:>width$}", cell, width = width)
+                    } else {
+                        format!("{:width$}", cell, width = width)
+                    }
+                })
+                .collect();
+            let _ = writeln!(text, "{}", line.join("  ").trim_end());
+        };
+        write_row(&headers);
+        for row in &rows {
+            write_row(&row.iter().map(String::as_str).collect::<Vec<_>>());
+        }
+        if rows.is_empty() {
+            let _ = writeln!(text, "No transactions in this period.");
+        }
+
+        text
+    }
+
+    /// A standalone HTML page, with every value escaped.
+    pub fn to_html(&self) -> String {
+        let naira = |kobo: i64| AmountFormat::Naira.format(kobo);
+        let optional = |kobo: Option<i64>| kobo.map(naira).unwrap_or_default();
+
+        let mut html = String::new();
+        let _ = writeln!(html, "<!DOCTYPE html>");
+        let _ = writeln!(html, "<html><head><meta charset=\"utf-8\">");
+        let _ = writeln!(
+            html,
+            "<title>Statement {}</title>",
+            escape(&self.account_number)
+        );
+        let _ = writeln!(
+            html,
+            "<style>table{{border-collapse:collapse}}td,th{{padding:4px 8px;border:1px solid #ccc}}.amount{{text-align:right}}</style>"
+        );
+        let _ = writeln!(html, "</head><body>");
+        let _ = writeln!(html, "<h1>Statement of account</h1>");
+        let _ = writeln!(html, "<p>Account: {}<br>", escape(&self.account_heading()));
+        let _ = write!(html, "Period: {} to {}", self.period_start, self.period_end);
+        if let Some(zainbox_code) = &self.zainbox_code {
+            let _ = write!(html, "<br>Zainbox: {}", escape(zainbox_code));
+        }
+        let _ = writeln!(html, "</p>");
+        if let Some(warning) = self.incomplete_warning() {
+            let _ = writeln!(html, "<p class=\"warning\">{}</p>", escape(&warning));
+        }
+
+        let _ = writeln!(html, "<table class=\"summary\">");
+        for (label, amount) in self.summary() {
+            let _ = writeln!(
+                html,
+                "<tr><th>{}</th><td class=\"amount\">{}</td></tr>",
+                label,
+                naira(amount)
+            );
+        }
+        let _ = writeln!(html, "</table>");
+
+        let _ = writeln!(html, "<table class=\"transactions\">");
+        let _ = writeln!(html, "<tr><th>Date</th><th>Reference</th><th>Description</th><th>Credit</th><th>Debit</th><th>Fee</th><th>Balance</th></tr>");
+        for line in &self.lines {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"amount\">{}</td><td class=\"amount\">{}</td><td class=\"amount\">{}</td><td class=\"amount\">{}</td></tr>",
+                escape(&line.date),
+                escape(&line.reference),
+                escape(&line.description),
+                optional(line.credit),
+                optional(line.debit),
+                optional(line.fee),
+                naira(line.balance)
+            );
+        }
+        let _ = writeln!(html, "</table>");
+        let _ = writeln!(html, "</body></html>");
+
+        html
+    }
+
+    fn account_heading(&self) -> String {
+        let details: Vec<&str> = [self.account_name.as_deref(), self.bank_type.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        if details.is_empty() {
+            self.account_number.clone()
+        } else {
+            format!("{} ({})", self.account_number, details.join(", "))
+        }
+    }
+
+    fn incomplete_warning(&self) -> Option<String> {
+        if self.is_complete() {
+            return None;
+        }
+        let days: Vec<String> = self
+            .truncated_days
+            .iter()
+            .map(|day| day.to_string())
+            .collect();
+        Some(format!(
+            "history incomplete on {}, balances may be wrong",
+            days.join(", ")
+        ))
+    }
+
+    fn summary(&self) -> Vec<(&'static str, i64)> {
+        let mut summary = vec![
+            ("Opening balance", self.opening_balance),
+            ("Credits", self.total_credits),
+            ("Debits", self.total_debits),
+            ("Fees", self.total_fees),
+            ("Closing balance", self.closing_balance),
+        ];
+        if let Some(collected) = self.zainbox_collected {
+            summary.push(("Collected by zainbox", collected));
+        }
+        summary
+    }
+}
+
+impl StatementBuilder {
+    /// Also report what this zainbox collected over the period.
+    pub fn zainbox(mut self, zainbox_code: &str) -> Self {
+        self.zainbox_code = Some(zainbox_code.to_string());
+        self
+    }
+
+    /// Transaction types counted as fees rather than debits, compared case-insensitively.
+    /// Defaults to `fee` and `charge`.
+    pub fn fee_types(mut self, fee_types: Vec<String>) -> Self {
+        self.fee_types = fee_types;
+        self
+    }
+
+    /// `count` of the history requests, see `ExportOptions::page_size`. Defaults to 500.
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    pub async fn build(self, engine: &Engine) -> Result<Statement, Box<dyn Error>> {
+        let response = virtual_account::CardService::new(engine.clone())
+            .get_virtual_account_balance(self.account_number.clone())
+            .await?;
+        if response.has_failed() {
+            return Err(ZainpayError::from_response(&response).into());
+        }
+        let balance = response
+            .parse_data::<VirtualAccountBalance>()
+            .ok_or(ZainpayError::UnexpectedData)?;
+
+        let exports = ExportService::new(engine.clone());
+        let source = HistorySource::VirtualAccount(self.account_number.clone());
+        let today = Utc::now().date_naive();
+        let mut pager = HistoryPager::new(
+            &exports,
+            &source,
+            self.period_start,
+            self.period_end.max(today),
+            7,
+            self.page_size,
+        )?;
+
+        let mut in_period: Vec<Transaction> = Vec::new();
+        let mut since_period = 0;
+        while let Some(transactions) = pager.next().await? {
+            for transaction in transactions {
+                match transaction.date() {
+                    Some(date) if date > self.period_end => {
+                        since_period += signed_amount(&transaction)
+                    }
+                    Some(date) if date < self.period_start => {}
+                    _ => in_period.push(transaction),
+                }
+            }
+        }
+
+        let closing_balance = balance.balance_amount - since_period;
+        let opening_balance = closing_balance - in_period.iter().map(signed_amount).sum::<i64>();
+
+        let mut statement = Statement {
+            account_name: balance.account_name,
+            bank_type: balance.bank_type,
+            account_number: self.account_number,
+            zainbox_code: None,
+            period_start: self.period_start,
+            period_end: self.period_end,
+            opening_balance,
+            total_credits: 0,
+            total_debits: 0,
+            total_fees: 0,
+            closing_balance,
+            zainbox_collected: None,
+            lines: Vec::with_capacity(in_period.len()),
+            truncated_days: pager.truncated_days,
+        };
+
+        let mut running = opening_balance;
+        for transaction in &in_period {
+            let amount = signed_amount(transaction);
+            running += amount;
+
+            let is_fee = self
+                .fee_types
+                .iter()
+                .any(|fee_type| fee_type.eq_ignore_ascii_case(&transaction.transaction_type));
+            let (credit, debit, fee) = if amount >= 0 {
+                statement.total_credits += amount;
+                (Some(amount), None, None)
+            } else if is_fee {
+                statement.total_fees -= amount;
+                (None, None, Some(-amount))
+            } else {
+                statement.total_debits -= amount;
+                (None, Some(-amount), None)
+            };
+
+            statement.lines.push(StatementLine {
+                date: transaction.transaction_date.clone(),
+                reference: transaction.transaction_ref.clone(),
+                description: transaction
+                    .narration
+                    .clone()
+                    .unwrap_or_else(|| transaction.transaction_type.clone()),
+                credit,
+                debit,
+                fee,
+                balance: running,
+            });
+        }
+
+        if let Some(zainbox_code) = self.zainbox_code {
+            let response = ZainboxService::new(engine.clone())
+                .get_total_payment_collected_by_zainbox(
+                    zainbox_code.clone(),
+                    Some(self.period_start.format("%Y-%m-%d").to_string()),
+                    Some(self.period_end.format("%Y-%m-%d").to_string()),
+                )
+                .await?;
+            if response.has_failed() {
+                return Err(ZainpayError::from_response(&response).into());
+            }
+            statement.zainbox_collected = response.get_raw_data().and_then(collected_amount);
+            statement.zainbox_code = Some(zainbox_code);
+        }
+
+        Ok(statement)
+    }
+}
+
+/// The total of a payment summary, sent either bare or under one of a few keys.
+fn collected_amount(data: &Value) -> Option<i64> {
+    match data {
+        Value::Object(summary) => ["totalAmount", "amount", "total"]
+            .iter()
+            .find_map(|key| parse_kobo(summary.get(*key)?)),
+        other => parse_kobo(other),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enviroment::Environment;
+    use crate::transport::{HttpResponse, Method, MockTransport};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn keeps_same_reference_fees_and_reports_truncated_days() {
+        let transport = MockTransport::new()
+            .route(
+                Method::Get,
+                "virtual-account/wallet/balance/7966884043",
+                HttpResponse::new(200, r#"{"code":"00","data":{"balanceAmount":"3490"}}"#),
+            )
+            .handler(|request| {
+                let from = request.query("dateFrom")?;
+                let to = request.query("dateTo")?;
+                let data = if from <= "2024-03-01" && to >= "2024-03-01" {
+                    json!([
+                        { "txnRef": "DEP-1", "txnType": "deposit", "amount": "5000", "txnDate": "2024-03-01T09:00:00" },
+                        { "txnRef": "TRF-1", "txnType": "transfer", "amount": "1500", "txnDate": "2024-03-01T10:00:00" },
+                        { "txnRef": "TRF-1", "txnType": "fee", "amount": "10", "txnDate": "2024-03-01T10:00:00" },
+                    ])
+                } else {
+                    json!([])
+                };
+                Some(HttpResponse::new(
+                    200,
+                    &json!({ "code": "00", "data": data }).to_string(),
+                ))
+            });
+        let engine = Engine::builder(Environment::Sandbox, "key")
+            .transport(Arc::new(transport))
+            .build();
+        let day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+
+        let statement = Statement::builder("7966884043", day, day)
+            .page_size(3)
+            .build(&engine)
+            .await
+            .unwrap();
+
+        assert_eq!(statement.lines.len(), 3);
+        assert_eq!(statement.total_credits, 5000);
+        assert_eq!(statement.total_debits, 1500);
+        assert_eq!(statement.total_fees, 10);
+        assert_eq!(statement.opening_balance, 0);
+        assert_eq!(statement.truncated_days, vec![day]);
+        assert!(!statement.is_complete());
+        assert!(statement
+            .to_text()
+            .contains("history incomplete on 2024-03-01"));
+    }
+}
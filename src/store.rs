@@ -0,0 +1,1022 @@
+//! Persistence for the state the SDK's idempotent flows need: transfer intents and their
+//! states, card checkout sessions, and the webhook events already handled.
+//!
+//! [`MemoryStore`] keeps everything in the process. [`SqliteStore`] (`sqlite` feature) keeps it
+//! in a SQLite database, so it survives restarts and can be shared by processes on one host.
+//! Implement [`Store`] to use any other database.
+
+use crate::models::model::FundTransferRequest;
+use crate::secret::Secret;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("no {kind} recorded for `{id}`")]
+    NotFound { kind: &'static str, id: String },
+
+    #[error("claim {attempt} on webhook event `{event_id}` was taken over by a later delivery")]
+    StaleClaim { event_id: String, attempt: u32 },
+
+    #[error("store backend failed: {0}")]
+    Backend(#[source] Box<dyn Error + Send + Sync>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferState {
+    /// Recorded, not sent to Zainpay yet.
+    Pending,
+    /// Accepted by Zainpay, not settled yet.
+    Submitted,
+    Succeeded,
+    Failed,
+    /// The request may or may not have reached Zainpay, e.g. it timed out. Verify the transfer
+    /// before sending it again.
+    Unknown,
+}
+
+impl TransferState {
+    pub fn is_final(&self) -> bool {
+        matches!(self, TransferState::Succeeded | TransferState::Failed)
+    }
+}
+
+/// A transfer the application asked for, keyed by an idempotency key of its choosing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferIntent {
+    pub idempotency_key: String,
+    pub request: FundTransferRequest,
+    pub state: TransferState,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TransferIntent {
+    pub fn new(idempotency_key: &str, request: FundTransferRequest) -> Self {
+        let now = Utc::now();
+        Self {
+            idempotency_key: idempotency_key.to_string(),
+            request,
+            state: TransferState::Pending,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn txn_ref(&self) -> &str {
+        &self.request.txn_ref
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CardSessionState {
+    /// Recorded, not initialized with Zainpay yet.
+    Pending,
+    /// Initialized, waiting for the customer to pay.
+    Initialized,
+    Paid,
+    Failed,
+    /// Initializing failed before Zainpay answered. Starting the payment again retries it.
+    Unknown,
+}
+
+impl CardSessionState {
+    pub fn is_final(&self) -> bool {
+        matches!(self, CardSessionState::Paid | CardSessionState::Failed)
+    }
+}
+
+/// A card payment started with `initialize_new_payment`. The amount is in kobo.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardSession {
+    pub txn_ref: String,
+    pub zainbox_code: String,
+    pub amount: String,
+    pub email_address: Secret<String>,
+    pub mobile_number: Secret<String>,
+    pub callback_url: String,
+    /// Where the customer pays, once Zainpay initialized the session.
+    pub checkout_url: Option<String>,
+    pub state: CardSessionState,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl CardSession {
+    pub fn new(
+        txn_ref: &str,
+        zainbox_code: &str,
+        amount: &str,
+        email_address: &str,
+        mobile_number: &str,
+        callback_url: &str,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            txn_ref: txn_ref.to_string(),
+            zainbox_code: zainbox_code.to_string(),
+            amount: amount.to_string(),
+            email_address: Secret::from(email_address),
+            mobile_number: Secret::from(mobile_number),
+            callback_url: callback_url.to_string(),
+            checkout_url: None,
+            state: CardSessionState::Pending,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookState {
+    /// Claimed by a handler that has not finished.
+    Processing,
+    Processed,
+    /// The handler failed; the event can be claimed again.
+    Failed,
+}
+
+/// A webhook event seen by the receiver, with the payload it was delivered with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookRecord {
+    pub event_id: String,
+    pub payload: String,
+    pub state: WebhookState,
+    /// How many times a handler was started for the event.
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub received_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookClaim {
+    /// The caller now owns the event and must complete or fail it.
+    Claimed {
+        attempt: u32,
+    },
+    AlreadyProcessed,
+    /// Another handler holds a claim that has not expired.
+    InProgress,
+}
+
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Record `intent`, unless a transfer with the same idempotency key or txn ref was recorded
+    /// before. That transfer is returned instead, and nothing is written.
+    async fn insert_transfer(
+        &self,
+        intent: &TransferIntent,
+    ) -> Result<Option<TransferIntent>, StoreError>;
+
+    async fn transfer(&self, idempotency_key: &str) -> Result<Option<TransferIntent>, StoreError>;
+
+    async fn transfer_by_ref(&self, txn_ref: &str) -> Result<Option<TransferIntent>, StoreError>;
+
+    async fn set_transfer_state(
+        &self,
+        idempotency_key: &str,
+        state: TransferState,
+        last_error: Option<&str>,
+    ) -> Result<TransferIntent, StoreError>;
+
+    /// Transfers whose state is not final, oldest first.
+    async fn unsettled_transfers(&self) -> Result<Vec<TransferIntent>, StoreError>;
+
+    /// Record `session`, unless a session with the same txn ref was recorded before. That
+    /// session is returned instead, and nothing is written.
+    async fn insert_card_session(
+        &self,
+        session: &CardSession,
+    ) -> Result<Option<CardSession>, StoreError>;
+
+    /// Insert or replace the session with the same txn ref.
+    async fn save_card_session(&self, session: &CardSession) -> Result<(), StoreError>;
+
+    async fn card_session(&self, txn_ref: &str) -> Result<Option<CardSession>, StoreError>;
+
+    /// Claim `event_id` for handling. A claim older than `lease` is assumed abandoned by a
+    /// crashed handler and can be taken over, so a slow handler may run alongside the one that
+    /// took over. Failed events can be claimed again.
+    async fn claim_webhook(
+        &self,
+        event_id: &str,
+        payload: &str,
+        lease: Duration,
+    ) -> Result<WebhookClaim, StoreError>;
+
+    /// Mark the event handled by claim `attempt`. Fails with `StoreError::StaleClaim` when the
+    /// claim was taken over or the event is no longer being processed.
+    async fn complete_webhook(&self, event_id: &str, attempt: u32) -> Result<(), StoreError>;
+
+    /// Mark the event failed by claim `attempt`, with the same ownership check as
+    /// `complete_webhook`.
+    async fn fail_webhook(
+        &self,
+        event_id: &str,
+        attempt: u32,
+        error: &str,
+    ) -> Result<(), StoreError>;
+
+    async fn webhook(&self, event_id: &str) -> Result<Option<WebhookRecord>, StoreError>;
+
+    /// Events whose last handler failed, oldest first.
+    async fn failed_webhooks(&self) -> Result<Vec<WebhookRecord>, StoreError>;
+}
+
+/// The outcome of claiming `event_id`, given what the store holds for it. Updates `record` in
+/// place when the claim is granted. Shared by the implementations so they agree on the rules.
+fn claim(
+    record: Option<&mut WebhookRecord>,
+    event_id: &str,
+    payload: &str,
+    lease: Duration,
+    now: DateTime<Utc>,
+) -> (WebhookClaim, Option<WebhookRecord>) {
+    let Some(record) = record else {
+        let record = WebhookRecord {
+            event_id: event_id.to_string(),
+            payload: payload.to_string(),
+            state: WebhookState::Processing,
+            attempts: 1,
+            last_error: None,
+            received_at: now,
+            updated_at: now,
+        };
+        return (WebhookClaim::Claimed { attempt: 1 }, Some(record));
+    };
+
+    let expired = chrono::Duration::from_std(lease)
+        .map(|lease| record.updated_at + lease <= now)
+        .unwrap_or(false);
+    match record.state {
+        WebhookState::Processed => (WebhookClaim::AlreadyProcessed, None),
+        WebhookState::Processing if !expired => (WebhookClaim::InProgress, None),
+        WebhookState::Processing | WebhookState::Failed => {
+            record.state = WebhookState::Processing;
+            record.attempts += 1;
+            record.updated_at = now;
+            (
+                WebhookClaim::Claimed {
+                    attempt: record.attempts,
+                },
+                None,
+            )
+        }
+    }
+}
+
+/// Finish the claim `attempt` on `record`, unless a later claim took the event over.
+fn finish(
+    record: &mut WebhookRecord,
+    attempt: u32,
+    state: WebhookState,
+    error: Option<&str>,
+) -> Result<(), StoreError> {
+    if record.state != WebhookState::Processing || record.attempts != attempt {
+        return Err(StoreError::StaleClaim {
+            event_id: record.event_id.clone(),
+            attempt,
+        });
+    }
+    record.state = state;
+    record.last_error = error.map(str::to_string);
+    record.updated_at = Utc::now();
+    Ok(())
+}
+
+/// A `Store` that keeps everything in memory. State is lost when the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    inner: Mutex<MemoryInner>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryInner {
+    transfers: HashMap<String, TransferIntent>,
+    card_sessions: HashMap<String, CardSession>,
+    webhooks: HashMap<String, WebhookRecord>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemoryInner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn insert_transfer(
+        &self,
+        intent: &TransferIntent,
+    ) -> Result<Option<TransferIntent>, StoreError> {
+        let mut inner = self.lock();
+        if let Some(existing) = inner.transfers.values().find(|existing| {
+            existing.idempotency_key == intent.idempotency_key
+                || existing.txn_ref() == intent.txn_ref()
+        }) {
+            return Ok(Some(existing.clone()));
+        }
+        inner
+            .transfers
+            .insert(intent.idempotency_key.clone(), intent.clone());
+        Ok(None)
+    }
+
+    async fn transfer(&self, idempotency_key: &str) -> Result<Option<TransferIntent>, StoreError> {
+        Ok(self.lock().transfers.get(idempotency_key).cloned())
+    }
+
+    async fn transfer_by_ref(&self, txn_ref: &str) -> Result<Option<TransferIntent>, StoreError> {
+        Ok(self
+            .lock()
+            .transfers
+            .values()
+            .find(|intent| intent.txn_ref() == txn_ref)
+            .cloned())
+    }
+
+    async fn set_transfer_state(
+        &self,
+        idempotency_key: &str,
+        state: TransferState,
+        last_error: Option<&str>,
+    ) -> Result<TransferIntent, StoreError> {
+        let mut inner = self.lock();
+        let intent =
+            inner
+                .transfers
+                .get_mut(idempotency_key)
+                .ok_or_else(|| StoreError::NotFound {
+                    kind: "transfer",
+                    id: idempotency_key.to_string(),
+                })?;
+        intent.state = state;
+        intent.last_error = last_error.map(str::to_string);
+        intent.updated_at = Utc::now();
+        Ok(intent.clone())
+    }
+
+    async fn unsettled_transfers(&self) -> Result<Vec<TransferIntent>, StoreError> {
+        let mut unsettled: Vec<TransferIntent> = self
+            .lock()
+            .transfers
+            .values()
+            .filter(|intent| !intent.state.is_final())
+            .cloned()
+            .collect();
+        unsettled.sort_by_key(|intent| intent.created_at);
+        Ok(unsettled)
+    }
+
+    async fn insert_card_session(
+        &self,
+        session: &CardSession,
+    ) -> Result<Option<CardSession>, StoreError> {
+        let mut inner = self.lock();
+        if let Some(existing) = inner.card_sessions.get(&session.txn_ref) {
+            return Ok(Some(existing.clone()));
+        }
+        inner
+            .card_sessions
+            .insert(session.txn_ref.clone(), session.clone());
+        Ok(None)
+    }
+
+    async fn save_card_session(&self, session: &CardSession) -> Result<(), StoreError> {
+        self.lock()
+            .card_sessions
+            .insert(session.txn_ref.clone(), session.clone());
+        Ok(())
+    }
+
+    async fn card_session(&self, txn_ref: &str) -> Result<Option<CardSession>, StoreError> {
+        Ok(self.lock().card_sessions.get(txn_ref).cloned())
+    }
+
+    async fn claim_webhook(
+        &self,
+        event_id: &str,
+        payload: &str,
+        lease: Duration,
+    ) -> Result<WebhookClaim, StoreError> {
+        let mut inner = self.lock();
+        let (outcome, created) = claim(
+            inner.webhooks.get_mut(event_id),
+            event_id,
+            payload,
+            lease,
+            Utc::now(),
+        );
+        if let Some(record) = created {
+            inner.webhooks.insert(event_id.to_string(), record);
+        }
+        Ok(outcome)
+    }
+
+    async fn complete_webhook(&self, event_id: &str, attempt: u32) -> Result<(), StoreError> {
+        let mut inner = self.lock();
+        let record = inner
+            .webhooks
+            .get_mut(event_id)
+            .ok_or_else(|| StoreError::NotFound {
+                kind: "webhook event",
+                id: event_id.to_string(),
+            })?;
+        finish(record, attempt, WebhookState::Processed, None)
+    }
+
+    async fn fail_webhook(
+        &self,
+        event_id: &str,
+        attempt: u32,
+        error: &str,
+    ) -> Result<(), StoreError> {
+        let mut inner = self.lock();
+        let record = inner
+            .webhooks
+            .get_mut(event_id)
+            .ok_or_else(|| StoreError::NotFound {
+                kind: "webhook event",
+                id: event_id.to_string(),
+            })?;
+        finish(record, attempt, WebhookState::Failed, Some(error))
+    }
+
+    async fn webhook(&self, event_id: &str) -> Result<Option<WebhookRecord>, StoreError> {
+        Ok(self.lock().webhooks.get(event_id).cloned())
+    }
+
+    async fn failed_webhooks(&self) -> Result<Vec<WebhookRecord>, StoreError> {
+        let mut failed: Vec<WebhookRecord> = self
+            .lock()
+            .webhooks
+            .values()
+            .filter(|record| record.state == WebhookState::Failed)
+            .cloned()
+            .collect();
+        failed.sort_by_key(|record| record.received_at);
+        Ok(failed)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::*;
+    use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+    use serde::de::DeserializeOwned;
+    use std::path::Path;
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS zainpay_transfers (
+            idempotency_key TEXT PRIMARY KEY,
+            txn_ref TEXT NOT NULL UNIQUE,
+            state TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            record TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS zainpay_card_sessions (
+            txn_ref TEXT PRIMARY KEY,
+            record TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS zainpay_webhooks (
+            event_id TEXT PRIMARY KEY,
+            state TEXT NOT NULL,
+            received_at TEXT NOT NULL,
+            record TEXT NOT NULL
+        );
+    ";
+
+    /// How long a write waits for another connection, e.g. another process, to release the
+    /// database before failing.
+    const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    impl From<rusqlite::Error> for StoreError {
+        fn from(error: rusqlite::Error) -> Self {
+            StoreError::Backend(Box::new(error))
+        }
+    }
+
+    impl From<serde_json::Error> for StoreError {
+        fn from(error: serde_json::Error) -> Self {
+            StoreError::Backend(Box::new(error))
+        }
+    }
+
+    /// A `Store` backed by SQLite. Records are kept as JSON next to the columns they are looked
+    /// up by; the `zainpay_*` tables are created when the store is opened.
+    ///
+    /// Account numbers, emails and phone numbers are stored unredacted, so protect the database
+    /// file accordingly. Queries run on the calling task, which is fine for a local file.
+    ///
+    /// Read-modify-write operations run in immediate transactions, so processes sharing the file
+    /// take turns instead of both claiming the same webhook event.
+    #[derive(Debug)]
+    pub struct SqliteStore {
+        connection: Mutex<Connection>,
+    }
+
+    impl SqliteStore {
+        pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+            Self::from_connection(Connection::open(path)?)
+        }
+
+        pub fn open_in_memory() -> Result<Self, StoreError> {
+            Self::from_connection(Connection::open_in_memory()?)
+        }
+
+        pub fn from_connection(connection: Connection) -> Result<Self, StoreError> {
+            connection.busy_timeout(BUSY_TIMEOUT)?;
+            connection.execute_batch(SCHEMA)?;
+            Ok(Self {
+                connection: Mutex::new(connection),
+            })
+        }
+
+        fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+            self.connection
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+        }
+    }
+
+    fn state_name<T: Serialize>(state: &T) -> Result<String, StoreError> {
+        Ok(serde_json::to_value(state)?
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    fn decode<T: DeserializeOwned>(record: Option<String>) -> Result<Option<T>, StoreError> {
+        record
+            .map(|record| serde_json::from_str(&record))
+            .transpose()
+            .map_err(StoreError::from)
+    }
+
+    fn decode_all<T: DeserializeOwned>(
+        connection: &Connection,
+        sql: &str,
+    ) -> Result<Vec<T>, StoreError> {
+        let mut statement = connection.prepare(sql)?;
+        let records = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        records
+            .iter()
+            .map(|record| serde_json::from_str(record).map_err(StoreError::from))
+            .collect()
+    }
+
+    fn write_webhook(connection: &Connection, record: &WebhookRecord) -> Result<(), StoreError> {
+        connection.execute(
+            "INSERT OR REPLACE INTO zainpay_webhooks (event_id, state, received_at, record)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                record.event_id,
+                state_name(&record.state)?,
+                record.received_at.to_rfc3339(),
+                serde_json::to_string(record)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn read_webhook(
+        connection: &Connection,
+        event_id: &str,
+    ) -> Result<Option<WebhookRecord>, StoreError> {
+        decode(
+            connection
+                .query_row(
+                    "SELECT record FROM zainpay_webhooks WHERE event_id = ?1",
+                    params![event_id],
+                    |row| row.get(0),
+                )
+                .optional()?,
+        )
+    }
+
+    fn finish_webhook(
+        connection: &mut Connection,
+        event_id: &str,
+        attempt: u32,
+        state: WebhookState,
+        error: Option<&str>,
+    ) -> Result<(), StoreError> {
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut record =
+            read_webhook(&transaction, event_id)?.ok_or_else(|| StoreError::NotFound {
+                kind: "webhook event",
+                id: event_id.to_string(),
+            })?;
+        finish(&mut record, attempt, state, error)?;
+        write_webhook(&transaction, &record)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    #[async_trait]
+    impl Store for SqliteStore {
+        async fn insert_transfer(
+            &self,
+            intent: &TransferIntent,
+        ) -> Result<Option<TransferIntent>, StoreError> {
+            let connection = self.lock();
+            let inserted = connection.execute(
+                "INSERT INTO zainpay_transfers (idempotency_key, txn_ref, state, created_at, record)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT DO NOTHING",
+                params![
+                    intent.idempotency_key,
+                    intent.txn_ref(),
+                    state_name(&intent.state)?,
+                    intent.created_at.to_rfc3339(),
+                    serde_json::to_string(intent)?
+                ],
+            )?;
+            if inserted > 0 {
+                return Ok(None);
+            }
+            decode(
+                connection
+                    .query_row(
+                        "SELECT record FROM zainpay_transfers
+                         WHERE idempotency_key = ?1 OR txn_ref = ?2",
+                        params![intent.idempotency_key, intent.txn_ref()],
+                        |row| row.get(0),
+                    )
+                    .optional()?,
+            )
+        }
+
+        async fn transfer(
+            &self,
+            idempotency_key: &str,
+        ) -> Result<Option<TransferIntent>, StoreError> {
+            decode(
+                self.lock()
+                    .query_row(
+                        "SELECT record FROM zainpay_transfers WHERE idempotency_key = ?1",
+                        params![idempotency_key],
+                        |row| row.get(0),
+                    )
+                    .optional()?,
+            )
+        }
+
+        async fn transfer_by_ref(
+            &self,
+            txn_ref: &str,
+        ) -> Result<Option<TransferIntent>, StoreError> {
+            decode(
+                self.lock()
+                    .query_row(
+                        "SELECT record FROM zainpay_transfers WHERE txn_ref = ?1",
+                        params![txn_ref],
+                        |row| row.get(0),
+                    )
+                    .optional()?,
+            )
+        }
+
+        async fn set_transfer_state(
+            &self,
+            idempotency_key: &str,
+            state: TransferState,
+            last_error: Option<&str>,
+        ) -> Result<TransferIntent, StoreError> {
+            let mut connection = self.lock();
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let record: Option<String> = transaction
+                .query_row(
+                    "SELECT record FROM zainpay_transfers WHERE idempotency_key = ?1",
+                    params![idempotency_key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let mut intent: TransferIntent =
+                decode(record)?.ok_or_else(|| StoreError::NotFound {
+                    kind: "transfer",
+                    id: idempotency_key.to_string(),
+                })?;
+            intent.state = state;
+            intent.last_error = last_error.map(str::to_string);
+            intent.updated_at = Utc::now();
+            transaction.execute(
+                "UPDATE zainpay_transfers SET state = ?2, record = ?3 WHERE idempotency_key = ?1",
+                params![
+                    idempotency_key,
+                    state_name(&state)?,
+                    serde_json::to_string(&intent)?
+                ],
+            )?;
+            transaction.commit()?;
+            Ok(intent)
+        }
+
+        async fn unsettled_transfers(&self) -> Result<Vec<TransferIntent>, StoreError> {
+            decode_all(
+                &self.lock(),
+                "SELECT record FROM zainpay_transfers WHERE state NOT IN ('succeeded', 'failed')
+                 ORDER BY created_at",
+            )
+        }
+
+        async fn insert_card_session(
+            &self,
+            session: &CardSession,
+        ) -> Result<Option<CardSession>, StoreError> {
+            let connection = self.lock();
+            let inserted = connection.execute(
+                "INSERT OR IGNORE INTO zainpay_card_sessions (txn_ref, record) VALUES (?1, ?2)",
+                params![session.txn_ref, serde_json::to_string(session)?],
+            )?;
+            if inserted > 0 {
+                return Ok(None);
+            }
+            decode(
+                connection
+                    .query_row(
+                        "SELECT record FROM zainpay_card_sessions WHERE txn_ref = ?1",
+                        params![session.txn_ref],
+                        |row| row.get(0),
+                    )
+                    .optional()?,
+            )
+        }
+
+        async fn save_card_session(&self, session: &CardSession) -> Result<(), StoreError> {
+            self.lock().execute(
+                "INSERT OR REPLACE INTO zainpay_card_sessions (txn_ref, record) VALUES (?1, ?2)",
+                params![session.txn_ref, serde_json::to_string(session)?],
+            )?;
+            Ok(())
+        }
+
+        async fn card_session(&self, txn_ref: &str) -> Result<Option<CardSession>, StoreError> {
+            decode(
+                self.lock()
+                    .query_row(
+                        "SELECT record FROM zainpay_card_sessions WHERE txn_ref = ?1",
+                        params![txn_ref],
+                        |row| row.get(0),
+                    )
+                    .optional()?,
+            )
+        }
+
+        async fn claim_webhook(
+            &self,
+            event_id: &str,
+            payload: &str,
+            lease: Duration,
+        ) -> Result<WebhookClaim, StoreError> {
+            let mut connection = self.lock();
+            let transaction =
+                connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let mut existing = read_webhook(&transaction, event_id)?;
+            let (outcome, created) = claim(existing.as_mut(), event_id, payload, lease, Utc::now());
+            if let Some(record) = created.as_ref() {
+                write_webhook(&transaction, record)?;
+            } else if let (WebhookClaim::Claimed { .. }, Some(record)) = (outcome, &existing) {
+                write_webhook(&transaction, record)?;
+            }
+            transaction.commit()?;
+            Ok(outcome)
+        }
+
+        async fn complete_webhook(&self, event_id: &str, attempt: u32) -> Result<(), StoreError> {
+            finish_webhook(
+                &mut self.lock(),
+                event_id,
+                attempt,
+                WebhookState::Processed,
+                None,
+            )
+        }
+
+        async fn fail_webhook(
+            &self,
+            event_id: &str,
+            attempt: u32,
+            error: &str,
+        ) -> Result<(), StoreError> {
+            finish_webhook(
+                &mut self.lock(),
+                event_id,
+                attempt,
+                WebhookState::Failed,
+                Some(error),
+            )
+        }
+
+        async fn webhook(&self, event_id: &str) -> Result<Option<WebhookRecord>, StoreError> {
+            read_webhook(&self.lock(), event_id)
+        }
+
+        async fn failed_webhooks(&self) -> Result<Vec<WebhookRecord>, StoreError> {
+            decode_all(
+                &self.lock(),
+                "SELECT record FROM zainpay_webhooks WHERE state = 'failed' ORDER BY received_at",
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intent(idempotency_key: &str, txn_ref: &str) -> TransferIntent {
+        TransferIntent::new(
+            idempotency_key,
+            FundTransferRequest {
+                destination_account_number: Secret::from("0040000008"),
+                destination_bank_code: "000013".to_string(),
+                amount: "1500".to_string(),
+                source_account_number: Secret::from("7966884043"),
+                source_bank_code: "0013".to_string(),
+                zainbox_code: "THbfnDvK5o".to_string(),
+                txn_ref: txn_ref.to_string(),
+                narration: "payout".to_string(),
+                callback_url: None,
+            },
+        )
+    }
+
+    fn session(amount: &str) -> CardSession {
+        CardSession::new(
+            "CARD-1",
+            "THbfnDvK5o",
+            amount,
+            "ada@example.com",
+            "08000000000",
+            "https://example.com/callback",
+        )
+    }
+
+    async fn inserts_each_record_once(store: &dyn Store) {
+        assert!(store
+            .insert_transfer(&intent("key-1", "TRF-1"))
+            .await
+            .unwrap()
+            .is_none());
+        let by_key = store
+            .insert_transfer(&intent("key-1", "TRF-2"))
+            .await
+            .unwrap();
+        let by_ref = store
+            .insert_transfer(&intent("key-2", "TRF-1"))
+            .await
+            .unwrap();
+        assert_eq!(by_key.unwrap().txn_ref(), "TRF-1");
+        assert_eq!(by_ref.unwrap().idempotency_key, "key-1");
+        assert!(store.transfer("key-2").await.unwrap().is_none());
+
+        assert!(store
+            .insert_card_session(&session("10000"))
+            .await
+            .unwrap()
+            .is_none());
+        let existing = store.insert_card_session(&session("20000")).await.unwrap();
+        assert_eq!(existing.unwrap().amount, "10000");
+        let recorded = store.card_session("CARD-1").await.unwrap().unwrap();
+        assert_eq!(recorded.amount, "10000");
+    }
+
+    async fn only_the_latest_claim_finishes_an_event(store: &dyn Store) {
+        let lease = Duration::from_secs(60);
+        let claimed = store
+            .claim_webhook("deposit:DEP-1", "{}", lease)
+            .await
+            .unwrap();
+        assert_eq!(claimed, WebhookClaim::Claimed { attempt: 1 });
+        let busy = store
+            .claim_webhook("deposit:DEP-1", "{}", lease)
+            .await
+            .unwrap();
+        assert_eq!(busy, WebhookClaim::InProgress);
+
+        // A zero lease has already expired, so the claim is taken over.
+        let taken = store
+            .claim_webhook("deposit:DEP-1", "{}", Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(taken, WebhookClaim::Claimed { attempt: 2 });
+        assert!(matches!(
+            store.complete_webhook("deposit:DEP-1", 1).await,
+            Err(StoreError::StaleClaim { attempt: 1, .. })
+        ));
+        assert!(matches!(
+            store.fail_webhook("deposit:DEP-1", 1, "timed out").await,
+            Err(StoreError::StaleClaim { .. })
+        ));
+
+        store
+            .fail_webhook("deposit:DEP-1", 2, "ledger down")
+            .await
+            .unwrap();
+        assert_eq!(store.failed_webhooks().await.unwrap().len(), 1);
+        let retried = store
+            .claim_webhook("deposit:DEP-1", "{}", lease)
+            .await
+            .unwrap();
+        assert_eq!(retried, WebhookClaim::Claimed { attempt: 3 });
+        store.complete_webhook("deposit:DEP-1", 3).await.unwrap();
+        assert!(matches!(
+            store.complete_webhook("deposit:DEP-1", 3).await,
+            Err(StoreError::StaleClaim { .. })
+        ));
+
+        let done = store
+            .claim_webhook("deposit:DEP-1", "{}", Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(done, WebhookClaim::AlreadyProcessed);
+        let record = store.webhook("deposit:DEP-1").await.unwrap().unwrap();
+        assert_eq!(record.state, WebhookState::Processed);
+        assert_eq!(record.attempts, 3);
+        assert!(record.last_error.is_none());
+    }
+
+    #[tokio::test]
+    async fn memory_store_inserts_each_record_once() {
+        inserts_each_record_once(&MemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn memory_store_lets_only_the_latest_claim_finish_an_event() {
+        only_the_latest_claim_finishes_an_event(&MemoryStore::new()).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_inserts_each_record_once() {
+        inserts_each_record_once(&SqliteStore::open_in_memory().unwrap()).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_lets_only_the_latest_claim_finish_an_event() {
+        only_the_latest_claim_finishes_an_event(&SqliteStore::open_in_memory().unwrap()).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn sqlite_stores_sharing_a_file_claim_an_event_once() {
+        let path = std::env::temp_dir().join(format!(
+            "zainpay-store-{}-{}.db",
+            std::process::id(),
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        SqliteStore::open(&path).unwrap();
+
+        let claims: Vec<_> = (0..8)
+            .map(|_| {
+                let store = SqliteStore::open(&path).unwrap();
+                tokio::spawn(async move {
+                    let intent = store.insert_transfer(&intent("key-1", "TRF-1")).await;
+                    let claim = store
+                        .claim_webhook("deposit:DEP-1", "{}", Duration::from_secs(60))
+                        .await;
+                    (intent.unwrap().is_none(), claim.unwrap())
+                })
+            })
+            .collect();
+        let mut inserted = 0;
+        let mut claimed = 0;
+        for claim in claims {
+            let (first_insert, claim) = claim.await.unwrap();
+            inserted += first_insert as usize;
+            claimed += matches!(claim, WebhookClaim::Claimed { .. }) as usize;
+        }
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(inserted, 1);
+        assert_eq!(claimed, 1);
+    }
+}
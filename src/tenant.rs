@@ -0,0 +1,241 @@
+use crate::bank::BankService;
+use crate::card::CardService;
+use crate::circuit_breaker::CircuitState;
+use crate::engine::{Engine, EngineBuilder};
+use crate::enviroment::Environment;
+use crate::error::ZainpayError;
+use crate::settlement::SettlementService;
+use crate::transport::{ReqwestTransport, Transport};
+use crate::virtual_account;
+use crate::zainbox::ZainboxService;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+type Configure = Arc<dyn Fn(&str, EngineBuilder) -> EngineBuilder + Send + Sync>;
+
+/// Engines for several merchants, one per tenant, sharing a single transport and its
+/// connection pool.
+///
+/// Each tenant gets its own engine, so rate limits and circuit breakers configured through
+/// [`MerchantRegistry::configure`] are tracked separately for every tenant.
+pub struct MerchantRegistry {
+    transport: Arc<dyn Transport>,
+    configure: Configure,
+    engines: HashMap<String, Engine>,
+}
+
+impl Default for MerchantRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MerchantRegistry {
+    pub fn new() -> Self {
+        Self::with_transport(Arc::new(ReqwestTransport::new()))
+    }
+
+    pub fn with_transport(transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport,
+            configure: Arc::new(|_, builder| builder),
+            engines: HashMap::new(),
+        }
+    }
+
+    /// Customise the engine of every tenant added afterwards. The closure receives the tenant ID,
+    /// so settings may differ per tenant; the shared transport is set after it runs.
+    pub fn configure<F>(mut self, configure: F) -> Self
+    where
+        F: Fn(&str, EngineBuilder) -> EngineBuilder + Send + Sync + 'static,
+    {
+        self.configure = Arc::new(configure);
+        self
+    }
+
+    pub fn tenant(mut self, tenant_id: &str, enviroment: Environment, merchant_key: &str) -> Self {
+        self.add_tenant(tenant_id, enviroment, merchant_key);
+        self
+    }
+
+    /// Register a tenant, replacing and returning any engine it already had.
+    pub fn add_tenant(
+        &mut self,
+        tenant_id: &str,
+        enviroment: Environment,
+        merchant_key: &str,
+    ) -> Option<Engine> {
+        let builder = (self.configure)(tenant_id, Engine::builder(enviroment, merchant_key));
+        let engine = builder.transport(self.transport.clone()).build();
+        self.engines.insert(tenant_id.to_string(), engine)
+    }
+
+    pub fn remove_tenant(&mut self, tenant_id: &str) -> Option<Engine> {
+        self.engines.remove(tenant_id)
+    }
+
+    pub fn tenants(&self) -> impl Iterator<Item = &str> {
+        self.engines.keys().map(String::as_str)
+    }
+
+    pub fn engine(&self, tenant_id: &str) -> Result<&Engine, ZainpayError> {
+        self.engines
+            .get(tenant_id)
+            .ok_or_else(|| ZainpayError::UnknownTenant(tenant_id.to_string()))
+    }
+
+    /// Circuit breaker state of every tenant, for health checks.
+    pub fn circuit_states(&self) -> HashMap<String, Option<CircuitState>> {
+        self.engines
+            .iter()
+            .map(|(tenant_id, engine)| (tenant_id.clone(), engine.circuit_state()))
+            .collect()
+    }
+
+    pub fn bank(&self, tenant_id: &str) -> Result<BankService, ZainpayError> {
+        Ok(BankService::new(self.engine(tenant_id)?.clone()))
+    }
+
+    pub fn card(&self, tenant_id: &str) -> Result<CardService, ZainpayError> {
+        Ok(CardService::new(self.engine(tenant_id)?.clone()))
+    }
+
+    pub fn settlement(&self, tenant_id: &str) -> Result<SettlementService, ZainpayError> {
+        Ok(SettlementService::new(self.engine(tenant_id)?.clone()))
+    }
+
+    pub fn virtual_account(
+        &self,
+        tenant_id: &str,
+    ) -> Result<virtual_account::CardService, ZainpayError> {
+        Ok(virtual_account::CardService::new(
+            self.engine(tenant_id)?.clone(),
+        ))
+    }
+
+    pub fn zainbox(&self, tenant_id: &str) -> Result<ZainboxService, ZainpayError> {
+        Ok(ZainboxService::new(self.engine(tenant_id)?.clone()))
+    }
+}
+
+impl fmt::Debug for MerchantRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MerchantRegistry")
+            .field("transport", &self.transport)
+            .field("engines", &self.engines)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit_breaker::CircuitBreakerConfig;
+    use crate::rate_limit::{EndpointGroup, RateLimit};
+    use crate::transport::{HttpResponse, MockTransport};
+    use std::time::Duration;
+
+    /// Answers tenant "a" with a server error and everyone else with an empty list.
+    fn failing_for_a() -> Arc<MockTransport> {
+        Arc::new(MockTransport::new().handler(|request| {
+            Some(match request.header("Authorization") {
+                Some("Bearer key-a") => HttpResponse::new(500, "{}"),
+                _ => HttpResponse::new(200, r#"{"code":"00","status":"success","data":[]}"#),
+            })
+        }))
+    }
+
+    fn registry(transport: Arc<MockTransport>) -> MerchantRegistry {
+        MerchantRegistry::with_transport(transport)
+            .configure(|tenant_id, builder| {
+                let builder = builder.circuit_breaker(CircuitBreakerConfig {
+                    failure_ratio: 0.5,
+                    minimum_calls: 2,
+                    window: 2,
+                    open_duration: Duration::from_secs(30),
+                    half_open_probes: 1,
+                });
+                if tenant_id == "a" {
+                    builder.rate_limit(EndpointGroup::Other, RateLimit::per_minute(1).with_burst(3))
+                } else {
+                    builder
+                }
+            })
+            .tenant("a", Environment::Sandbox, "key-a")
+            .tenant("b", Environment::Sandbox, "key-b")
+    }
+
+    #[tokio::test]
+    async fn tenants_share_the_transport() {
+        let transport = failing_for_a();
+        let registry = registry(transport.clone());
+
+        registry.zainbox("a").unwrap().list(None).await.unwrap();
+        registry.zainbox("b").unwrap().list(None).await.unwrap();
+
+        let keys: Vec<_> = transport
+            .requests()
+            .iter()
+            .map(|request| request.header("Authorization").unwrap().to_string())
+            .collect();
+        assert_eq!(keys, ["Bearer key-a", "Bearer key-b"]);
+    }
+
+    #[tokio::test]
+    async fn an_open_circuit_leaves_other_tenants_alone() {
+        let registry = registry(failing_for_a());
+
+        for _ in 0..2 {
+            registry.zainbox("a").unwrap().list(None).await.unwrap();
+        }
+        let error = registry.zainbox("a").unwrap().list(None).await.unwrap_err();
+        assert!(error.to_string().contains("circuit"), "{}", error);
+
+        let response = registry.zainbox("b").unwrap().list(None).await.unwrap();
+        assert_eq!(response.get_code(), Some("00"));
+
+        let states = registry.circuit_states();
+        assert_eq!(states["a"], Some(CircuitState::Open));
+        assert_eq!(states["b"], Some(CircuitState::Closed));
+    }
+
+    #[tokio::test]
+    async fn a_rate_limited_tenant_does_not_hold_up_the_others() {
+        let registry = registry(Arc::new(MockTransport::new()));
+        for _ in 0..3 {
+            registry
+                .engine("a")
+                .unwrap()
+                .get("zainbox/list")
+                .await
+                .unwrap();
+        }
+
+        let limited = tokio::time::timeout(
+            Duration::from_millis(100),
+            registry.engine("a").unwrap().get("zainbox/list"),
+        )
+        .await;
+        assert!(limited.is_err(), "tenant a has used up its burst");
+
+        for _ in 0..5 {
+            tokio::time::timeout(
+                Duration::from_millis(100),
+                registry.engine("b").unwrap().get("zainbox/list"),
+            )
+            .await
+            .expect("tenant b has no limit of its own")
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn unknown_tenants_are_refused() {
+        let registry = registry(Arc::new(MockTransport::new()));
+        assert!(matches!(
+            registry.bank("c"),
+            Err(ZainpayError::UnknownTenant(tenant)) if tenant == "c"
+        ));
+    }
+}
@@ -0,0 +1,340 @@
+use crate::utils::redact::{redact_body, redact_url, REDACTED};
+use async_trait::async_trait;
+use reqwest::Client;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+
+pub type TransportError = Box<dyn Error + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Post,
+    Patch,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Patch => "PATCH",
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Everything a transport needs to send one request to Zainpay.
+///
+/// `Debug` redacts the merchant key, the query string, account numbers in the path and personal
+/// data in the body.
+#[derive(Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+impl HttpRequest {
+    /// The url without its scheme and host, e.g. `bank/list` or `zainbox/list?status=true`.
+    pub fn path(&self) -> &str {
+        let without_scheme = self
+            .url
+            .split_once("://")
+            .map_or(self.url.as_str(), |(_, rest)| rest);
+        without_scheme
+            .split_once('/')
+            .map_or("", |(_, path)| path.trim_start_matches('/'))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Value of the query parameter `name`, as sent.
+    #[cfg(test)]
+    pub(crate) fn query(&self, name: &str) -> Option<&str> {
+        let (_, query) = self.url.split_once('?')?;
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find_map(|(key, value)| (key == name).then_some(value))
+    }
+}
+
+impl fmt::Debug for HttpRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let headers: Vec<(&str, &str)> = self
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                if name.eq_ignore_ascii_case("authorization") {
+                    (name.as_str(), REDACTED)
+                } else {
+                    (name.as_str(), value.as_str())
+                }
+            })
+            .collect();
+        f.debug_struct("HttpRequest")
+            .field("method", &self.method)
+            .field("url", &redact_url(&self.url))
+            .field("headers", &headers)
+            .field("body", &self.body.as_deref().map(redact_body))
+            .finish()
+    }
+}
+
+/// `Debug` redacts personal data from the body.
+#[derive(Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+impl fmt::Debug for HttpResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpResponse")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .field("body", &redact_body(&self.body))
+            .finish()
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Sends requests on behalf of an `Engine`. Every service goes through the engine's transport,
+/// so swapping it swaps the HTTP stack of the whole client.
+#[async_trait]
+pub trait Transport: fmt::Debug + Send + Sync {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError>;
+}
+
+/// Default transport, backed by a pooled `reqwest::Client`.
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl ReqwestTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_client(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl Transport for ReqwestTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        let mut builder = match request.method {
+            Method::Get => self.client.get(&request.url),
+            Method::Post => self.client.post(&request.url),
+            Method::Patch => self.client.patch(&request.url),
+        };
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        // reqwest errors quote the url, query string and account numbers included.
+        let response = builder.send().await.map_err(reqwest::Error::without_url)?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.text().await.map_err(reqwest::Error::without_url)?;
+
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+type Handler = Box<dyn Fn(&HttpRequest) -> Option<HttpResponse> + Send + Sync>;
+
+/// In-memory transport for tests. Answers from registered routes, then from a queue of
+/// responses, and with a 404 otherwise. Every request it receives is recorded.
+#[derive(Default)]
+pub struct MockTransport {
+    routes: Vec<(Method, String, HttpResponse)>,
+    handler: Option<Handler>,
+    queue: Mutex<VecDeque<HttpResponse>>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer every `method` request to `path` (query string ignored) with `response`.
+    pub fn route(mut self, method: Method, path: &str, response: HttpResponse) -> Self {
+        self.routes
+            .push((method, path.trim_start_matches('/').to_string(), response));
+        self
+    }
+
+    /// Answer requests with `handler` when no route matches. Returning `None` falls through to the queue.
+    pub fn handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> Option<HttpResponse> + Send + Sync + 'static,
+    {
+        self.handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Queue a response for the next request no route or handler answers.
+    pub fn push_response(&self, response: HttpResponse) {
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push_back(response);
+    }
+
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+impl fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockTransport")
+            .field("routes", &self.routes.len())
+            .field("handler", &self.handler.is_some())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(request.clone());
+
+        let path = request.path().split('?').next().unwrap_or_default();
+        if let Some((_, _, response)) = self
+            .routes
+            .iter()
+            .find(|(method, route, _)| *method == request.method && route == path)
+        {
+            return Ok(response.clone());
+        }
+
+        if let Some(response) = self.handler.as_ref().and_then(|handler| handler(&request)) {
+            return Ok(response);
+        }
+
+        Ok(self
+            .queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .pop_front()
+            .unwrap_or_else(|| {
+                HttpResponse::new(404, r#"{"code":"404","description":"no mock response"}"#)
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_redacts_bodies_and_the_authorization_header() {
+        let request = HttpRequest {
+            method: Method::Post,
+            url: "https://sandbox.zainpay.ng/virtual-account/create/request".to_string(),
+            headers: vec![("Authorization".to_string(), "Bearer secret-key".to_string())],
+            body: Some(r#"{"bvn":"22222222222","bankType":"wemaBank"}"#.to_string()),
+        };
+        let debug = format!("{:?}", request);
+        assert!(!debug.contains("secret-key"), "{}", debug);
+        assert!(!debug.contains("22222222222"), "{}", debug);
+        assert!(debug.contains("wemaBank"), "{}", debug);
+
+        let response = HttpResponse::new(200, r#"{"data":{"accountNumber":"7966884043"}}"#);
+        assert!(!format!("{:?}", response).contains("7966884043"));
+
+        let response = HttpResponse::new(502, "upstream 7966884043 unavailable");
+        assert!(!format!("{:?}", response).contains("7966884043"));
+    }
+
+    #[test]
+    fn debug_redacts_the_query_and_account_numbers_in_the_url() {
+        let request = |url: &str| HttpRequest {
+            method: Method::Get,
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+        };
+
+        let debug = format!(
+            "{:?}",
+            request("https://sandbox.zainpay.ng/bank/name-enquiry?bankCode=000013&accountNumber=0123456789")
+        );
+        assert!(!debug.contains("0123456789"), "{}", debug);
+        assert!(
+            debug.contains("sandbox.zainpay.ng/bank/name-enquiry?[REDACTED]"),
+            "{}",
+            debug
+        );
+
+        let debug = format!(
+            "{:?}",
+            request("https://sandbox.zainpay.ng/virtual-account/wallet/balance/7966884043")
+        );
+        assert!(!debug.contains("7966884043"), "{}", debug);
+        assert!(
+            debug.contains("virtual-account/wallet/balance/[REDACTED]"),
+            "{}",
+            debug
+        );
+    }
+}
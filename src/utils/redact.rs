@@ -0,0 +1,77 @@
+use serde_json::{Map, Value};
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Lowercased fragments of JSON keys whose values must never reach logs.
+const SENSITIVE_KEYS: &[&str] = &[
+    "bvn",
+    "accountnumber",
+    "accountname",
+    "balance",
+    "email",
+    "mobile",
+    "phone",
+    "dob",
+    "address",
+    "firstname",
+    "lastname",
+    "surname",
+    "sendername",
+    "beneficiaryname",
+];
+
+pub fn is_sensitive_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SENSITIVE_KEYS.iter().any(|fragment| key.contains(fragment))
+}
+
+/// Copy of `value` with the values of every sensitive key, at any depth, replaced by `[REDACTED]`.
+pub fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    let value = if is_sensitive_key(key) && !value.is_null() {
+                        Value::from(REDACTED)
+                    } else {
+                        redact(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect::<Map<String, Value>>(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        other => other.clone(),
+    }
+}
+
+/// A url for `Debug` output: the query is replaced as a whole, and path segments of ten or more
+/// digits (account numbers, BVNs) are redacted.
+pub fn redact_url(url: &str) -> String {
+    let (url, query) = match url.split_once('?') {
+        Some((url, _)) => (url, format!("?{}", REDACTED)),
+        None => (url, String::new()),
+    };
+    let url = url
+        .split('/')
+        .map(|segment| {
+            if segment.len() >= 10 && segment.bytes().all(|byte| byte.is_ascii_digit()) {
+                REDACTED
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    url + &query
+}
+
+/// A request or response body for `Debug` output: JSON with its sensitive values redacted, and
+/// anything that is not JSON replaced as a whole, since it cannot be inspected.
+pub fn redact_body(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(value) => redact(&value).to_string(),
+        Err(_) if body.is_empty() => String::new(),
+        Err(_) => REDACTED.to_string(),
+    }
+}
@@ -1,4 +1,6 @@
 use crate::engine::Engine;
+use crate::models::model::CreateVirtualAccountRequest;
+use crate::secret::Secret;
 use crate::utils::filter::FilterUtil;
 use crate::response::Response;
 use serde_json::{json};
@@ -48,27 +50,25 @@ impl CardService {
         state: String,
         zainbox_code: String,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
-            .post(
-                "virtual-account/create/request",
-                &json!({
-                    "bankType": bank_type,
-                    "firstName": first_name,
-                    "bvn": bvn,
-                    "lastName": last_name,
-                    "email": email,
-                    "mobile": mobile,
-                    "dob": dob,
-                    "gender":gender,
-                    "address": address,
-                    "title": title,
-                    "state": state,
-                    "zainboxCode": zainbox_code
-                }),
-            )
-            .await?;
-        Ok(Response::new(http_response).await)
+        let payload = CreateVirtualAccountRequest {
+            bank_type,
+            first_name: Secret::new(first_name),
+            bvn: Secret::new(bvn),
+            last_name: Secret::new(last_name),
+            email: Secret::new(email),
+            mobile: Secret::new(mobile),
+            dob: Secret::new(dob),
+            gender,
+            address: Secret::new(address),
+            title,
+            state,
+            zainbox_code,
+        };
+
+        self.engine
+            .endpoint("virtual-account/create/request")
+            .post("virtual-account/create/request", &payload)
+            .await
     }
 
     /**
@@ -79,26 +79,35 @@ impl CardService {
      * @throws GuzzleException
      * @link https://zainpay.ng/developers/api-endpoints?section=virtual-account-balance
      */
-    pub async fn get_viirtual_account_balance(&self, account_number: String) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+    pub async fn get_virtual_account_balance(
+        &self,
+        account_number: String,
+    ) -> Result<Response, Box<dyn Error>> {
+        self.engine
+            .endpoint("virtual-account/wallet/balance/{}")
             .get(&format!(
                 "virtual-account/wallet/balance/{}",
                 account_number
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
+    }
+
+    #[deprecated(note = "renamed to `get_virtual_account_balance`")]
+    pub async fn get_viirtual_account_balance(
+        &self,
+        account_number: String,
+    ) -> Result<Response, Box<dyn Error>> {
+        self.get_virtual_account_balance(account_number).await
     }
 
     pub async fn get_all_virtual_accounts_balance_for_zainbox(
         &self,
         zainbox_code: String,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/accounts/balance/{}")
             .get(&format!("zainbox/accounts/balance/{}", zainbox_code))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     pub async fn change_virtual_account_status(
@@ -107,8 +116,8 @@ impl CardService {
         account_number: String,
         status: bool,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("virtual-account/change/account/status")
             .patch(
                 "/virtual-account/change/account/status",
                 &json!({
@@ -117,8 +126,7 @@ impl CardService {
                     "status": status
                 }),
             )
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
      /**
@@ -130,16 +138,14 @@ impl CardService {
      *
      * @link https://zainpay.ng/developers/api-endpoints?section=zainbox-virtual-accounts-balances
      */
-
      pub async fn get_all_virtual_accounts_for_zainbox(
         &self,
         zainbox_code: String,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/virtual-accounts/{}")
             .get(&format!("zainbox/virtual-accounts/{}", zainbox_code))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     /**
@@ -159,8 +165,8 @@ impl CardService {
         txn_type: Option<String>,
         payment_channel: Option<String>,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("virtual-account/wallet/transactions/{}/{}")
             .get(&format!(
                 "virtual-account/wallet/transactions/{}/{}?{}",
                 account_number,
@@ -176,7 +182,6 @@ impl CardService {
                     None
                 )
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 }
@@ -0,0 +1,518 @@
+//! Receiving Zainpay webhooks.
+//!
+//! Zainpay may deliver a callback more than once, and `repush_deposit_event` resends one on
+//! purpose. [`WebhookReceiver`] identifies each event by its name and txn ref, claims it in a
+//! [`Store`] before running the handler, and records whether the handler succeeded. Deliveries
+//! are deduplicated, even when they arrive concurrently or on several processes sharing the
+//! store, and failed events are kept for [`WebhookReceiver::replay_failed`].
+//!
+//! Handlers run at least once per event, not exactly once: a handler that outlives its
+//! [`lease`](WebhookReceiver::lease) is assumed to have crashed, and the next delivery takes the
+//! event over. Make handlers idempotent. The outcome of the overtaken handler is refused with
+//! [`StoreError::StaleClaim`], so it cannot overwrite the one that took over.
+//!
+//! With a signing secret configured, deliveries must carry the hex HMAC-SHA256 of the body under
+//! [`SIGNATURE_HEADER`], and unsigned or tampered payloads are refused before anything is stored.
+//!
+//! This signature scheme is local to this SDK: it is not defined by Zainpay, and Zainpay's own
+//! deliveries do not carry it. Use it between services you control, e.g. a relay that forwards
+//! Zainpay callbacks, and with [`WebhookSimulator`] in tests. Leave the secret unset when
+//! receiving straight from Zainpay.
+//!
+//! [`WebhookSimulator`]: crate::simulator::WebhookSimulator
+
+use crate::secret::Secret;
+use crate::store::{Store, StoreError, WebhookClaim};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::error::Error;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Header carrying the signature of a webhook body. Set by this SDK, not by Zainpay.
+pub const SIGNATURE_HEADER: &str = "Zainpay-Signature";
+
+#[derive(Debug, Error)]
+pub enum WebhookError {
+    #[error("webhook signature is missing or does not match the payload")]
+    InvalidSignature,
+
+    #[error("webhook payload is not a Zainpay event: {0}")]
+    InvalidPayload(#[from] serde_json::Error),
+
+    #[error("webhook event `{0}` carries no txn ref to identify it")]
+    MissingTxnRef(String),
+
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+/// A webhook payload: the event name and its `data`, e.g. `deposit.success` and the deposit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    #[serde(alias = "eventType")]
+    pub event: String,
+
+    #[serde(default)]
+    pub data: Value,
+}
+
+impl WebhookEvent {
+    pub fn parse(payload: &str) -> Result<Self, WebhookError> {
+        Ok(serde_json::from_str(payload)?)
+    }
+
+    pub fn txn_ref(&self) -> Option<&str> {
+        ["txnRef", "transactionRef", "paymentRef"]
+            .iter()
+            .find_map(|key| self.data.get(*key)?.as_str())
+    }
+
+    /// `<event>:<txn ref>`, the key deliveries of the same event are deduplicated by.
+    pub fn id(&self) -> Result<String, WebhookError> {
+        let txn_ref = self
+            .txn_ref()
+            .ok_or_else(|| WebhookError::MissingTxnRef(self.event.clone()))?;
+        Ok(format!("{}:{}", self.event, txn_ref))
+    }
+
+    pub fn parse_data<T: DeserializeOwned>(&self) -> Option<T> {
+        serde_json::from_value(self.data.clone()).ok()
+    }
+}
+
+/// Application code run for each webhook event. Closures returning a future implement it.
+#[async_trait]
+pub trait WebhookHandler: Send + Sync {
+    async fn handle(&self, event: &WebhookEvent) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+#[async_trait]
+impl<F, Fut> WebhookHandler for F
+where
+    F: Fn(WebhookEvent) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send,
+{
+    async fn handle(&self, event: &WebhookEvent) -> Result<(), Box<dyn Error + Send + Sync>> {
+        (self)(event.clone()).await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery {
+    /// The handler ran and succeeded.
+    Handled { event_id: String, attempt: u32 },
+    /// The event was handled before; the handler did not run.
+    Duplicate { event_id: String },
+    /// Another delivery of the event is being handled right now; the handler did not run.
+    InProgress { event_id: String },
+    /// The handler ran and failed. The event is recorded for replay.
+    Failed {
+        event_id: String,
+        attempt: u32,
+        error: String,
+    },
+}
+
+impl Delivery {
+    pub fn event_id(&self) -> &str {
+        match self {
+            Delivery::Handled { event_id, .. }
+            | Delivery::Duplicate { event_id }
+            | Delivery::InProgress { event_id }
+            | Delivery::Failed { event_id, .. } => event_id,
+        }
+    }
+
+    /// Whether to answer Zainpay with a 2xx. Deliveries still in progress elsewhere are not
+    /// acknowledged, so Zainpay retries them and they are then reported as duplicates.
+    pub fn should_acknowledge(&self) -> bool {
+        !matches!(self, Delivery::InProgress { .. })
+    }
+}
+
+/// Hex HMAC-SHA256 of `payload` under `secret`.
+pub fn sign(secret: &str, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Whether `signature` is the signature of `payload` under `secret`, compared in constant time.
+pub fn verify_signature(secret: &str, payload: &str, signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature.trim()) else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+pub struct WebhookReceiver {
+    store: Arc<dyn Store>,
+    handler: Arc<dyn WebhookHandler>,
+    lease: Duration,
+    signing_secret: Option<Secret<String>>,
+}
+
+impl WebhookReceiver {
+    pub fn new(store: Arc<dyn Store>, handler: Arc<dyn WebhookHandler>) -> Self {
+        Self {
+            store,
+            handler,
+            lease: Duration::from_secs(300),
+            signing_secret: None,
+        }
+    }
+
+    /// Require deliveries to be signed with `secret`, see [`receive_signed`](Self::receive_signed).
+    /// Zainpay's deliveries do not carry this signature, so only set it behind a relay that signs
+    /// them.
+    pub fn signing_secret(mut self, secret: &str) -> Self {
+        self.signing_secret = Some(Secret::from(secret));
+        self
+    }
+
+    /// How long a handler may run before its claim is considered abandoned and another delivery
+    /// may take the event over. Defaults to five minutes; keep it above your slowest handler.
+    pub fn lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /**
+     * Handle one unsigned webhook delivery. Refused when a signing secret is set.
+     *
+     * @param payload &str the raw request body
+     *
+     * @return Delivery
+     * @throws WebhookError
+     */
+    pub async fn receive(&self, payload: &str) -> Result<Delivery, WebhookError> {
+        self.receive_signed(payload, None).await
+    }
+
+    /**
+     * Handle one webhook delivery, checking its signature first when a signing secret is set
+     *
+     * @param payload &str the raw request body
+     * @param signature Option<&str> the value of the `Zainpay-Signature` header
+     *
+     * @return Delivery
+     * @throws WebhookError
+     */
+    pub async fn receive_signed(
+        &self,
+        payload: &str,
+        signature: Option<&str>,
+    ) -> Result<Delivery, WebhookError> {
+        if let Some(secret) = &self.signing_secret {
+            let signed = signature
+                .is_some_and(|signature| verify_signature(secret.expose(), payload, signature));
+            if !signed {
+                return Err(WebhookError::InvalidSignature);
+            }
+        }
+
+        let event = WebhookEvent::parse(payload)?;
+        let event_id = event.id()?;
+        self.dispatch(event_id, payload, &event).await
+    }
+
+    /**
+     * Run the handler again for every event whose last handler failed
+     *
+     * @return Vec<Delivery>
+     * @throws WebhookError
+     */
+    pub async fn replay_failed(&self) -> Result<Vec<Delivery>, WebhookError> {
+        let mut deliveries = Vec::new();
+        for record in self.store.failed_webhooks().await? {
+            let event = WebhookEvent::parse(&record.payload)?;
+            deliveries.push(
+                self.dispatch(record.event_id, &record.payload, &event)
+                    .await?,
+            );
+        }
+        Ok(deliveries)
+    }
+
+    async fn dispatch(
+        &self,
+        event_id: String,
+        payload: &str,
+        event: &WebhookEvent,
+    ) -> Result<Delivery, WebhookError> {
+        let attempt = match self
+            .store
+            .claim_webhook(&event_id, payload, self.lease)
+            .await?
+        {
+            WebhookClaim::Claimed { attempt } => attempt,
+            WebhookClaim::AlreadyProcessed => return Ok(Delivery::Duplicate { event_id }),
+            WebhookClaim::InProgress => return Ok(Delivery::InProgress { event_id }),
+        };
+
+        match self.handler.handle(event).await {
+            Ok(()) => {
+                self.store.complete_webhook(&event_id, attempt).await?;
+                Ok(Delivery::Handled { event_id, attempt })
+            }
+            Err(error) => {
+                let error = error.to_string();
+                tracing::warn!(event_id = %event_id, attempt, error = %error, "webhook handler failed");
+                self.store.fail_webhook(&event_id, attempt, &error).await?;
+                Ok(Delivery::Failed {
+                    event_id,
+                    attempt,
+                    error,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "sqlite")]
+    use crate::store::SqliteStore;
+    use crate::store::{MemoryStore, WebhookState};
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use tokio::sync::Notify;
+
+    const PAYLOAD: &str =
+        r#"{"event":"deposit.success","data":{"txnRef":"DEP-1","amount":"5000"}}"#;
+    const EVENT_ID: &str = "deposit.success:DEP-1";
+
+    /// Counts its calls, fails when told to, and can be held inside its next call until released.
+    #[derive(Default)]
+    struct TestHandler {
+        calls: AtomicU32,
+        fail_next: AtomicBool,
+        hold_next: AtomicBool,
+        started: Notify,
+        release: Notify,
+    }
+
+    #[async_trait]
+    impl WebhookHandler for TestHandler {
+        async fn handle(&self, _event: &WebhookEvent) -> Result<(), Box<dyn Error + Send + Sync>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.hold_next.swap(false, Ordering::SeqCst) {
+                self.started.notify_one();
+                self.release.notified().await;
+            }
+            if self.fail_next.swap(false, Ordering::SeqCst) {
+                return Err("ledger down".into());
+            }
+            Ok(())
+        }
+    }
+
+    fn receiver(store: Arc<dyn Store>) -> (Arc<WebhookReceiver>, Arc<TestHandler>) {
+        let handler = Arc::new(TestHandler::default());
+        (
+            Arc::new(WebhookReceiver::new(store, handler.clone())),
+            handler,
+        )
+    }
+
+    fn delivered(delivery: Delivery) -> (&'static str, u32) {
+        match delivery {
+            Delivery::Handled { attempt, .. } => ("handled", attempt),
+            Delivery::Duplicate { .. } => ("duplicate", 0),
+            Delivery::InProgress { .. } => ("in progress", 0),
+            Delivery::Failed { attempt, .. } => ("failed", attempt),
+        }
+    }
+
+    async fn repeated_deliveries_are_duplicates(store: Arc<dyn Store>) {
+        let (receiver, handler) = receiver(store);
+
+        let first = receiver.receive(PAYLOAD).await.unwrap();
+        assert_eq!(first.event_id(), EVENT_ID);
+        assert_eq!(delivered(first), ("handled", 1));
+        let again = receiver.receive(PAYLOAD).await.unwrap();
+        assert!(again.should_acknowledge());
+        assert_eq!(delivered(again), ("duplicate", 0));
+        assert_eq!(handler.calls.load(Ordering::SeqCst), 1);
+    }
+
+    async fn a_delivery_being_handled_is_in_progress(store: Arc<dyn Store>) {
+        let (receiver, handler) = receiver(store);
+        handler.hold_next.store(true, Ordering::SeqCst);
+
+        let first = tokio::spawn({
+            let receiver = receiver.clone();
+            async move { receiver.receive(PAYLOAD).await }
+        });
+        handler.started.notified().await;
+
+        let concurrent = receiver.receive(PAYLOAD).await.unwrap();
+        assert!(!concurrent.should_acknowledge());
+        assert_eq!(delivered(concurrent), ("in progress", 0));
+
+        handler.release.notify_one();
+        assert_eq!(delivered(first.await.unwrap().unwrap()), ("handled", 1));
+        assert_eq!(handler.calls.load(Ordering::SeqCst), 1);
+    }
+
+    async fn an_expired_lease_is_taken_over(store: Arc<dyn Store>) {
+        let handler = Arc::new(TestHandler::default());
+        let receiver =
+            Arc::new(WebhookReceiver::new(store.clone(), handler.clone()).lease(Duration::ZERO));
+        handler.hold_next.store(true, Ordering::SeqCst);
+
+        let overtaken = tokio::spawn({
+            let receiver = receiver.clone();
+            async move { receiver.receive(PAYLOAD).await }
+        });
+        handler.started.notified().await;
+
+        let takeover = receiver.receive(PAYLOAD).await.unwrap();
+        assert_eq!(delivered(takeover), ("handled", 2));
+
+        handler.release.notify_one();
+        assert!(matches!(
+            overtaken.await.unwrap(),
+            Err(WebhookError::Store(StoreError::StaleClaim {
+                attempt: 1,
+                ..
+            }))
+        ));
+        let record = store.webhook(EVENT_ID).await.unwrap().unwrap();
+        assert_eq!(record.state, WebhookState::Processed);
+        assert_eq!(record.attempts, 2);
+    }
+
+    async fn failed_events_are_replayed(store: Arc<dyn Store>) {
+        let (receiver, handler) = receiver(store.clone());
+        handler.fail_next.store(true, Ordering::SeqCst);
+
+        let failed = receiver.receive(PAYLOAD).await.unwrap();
+        assert!(matches!(&failed, Delivery::Failed { error, .. } if error == "ledger down"));
+        assert_eq!(store.failed_webhooks().await.unwrap().len(), 1);
+
+        let replayed: Vec<_> = receiver
+            .replay_failed()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(delivered)
+            .collect();
+        assert_eq!(replayed, [("handled", 2)]);
+        assert!(receiver.replay_failed().await.unwrap().is_empty());
+        assert_eq!(
+            delivered(receiver.receive(PAYLOAD).await.unwrap()),
+            ("duplicate", 0)
+        );
+        assert_eq!(handler.calls.load(Ordering::SeqCst), 2);
+    }
+
+    async fn unsigned_or_tampered_deliveries_are_refused(store: Arc<dyn Store>) {
+        let handler = Arc::new(TestHandler::default());
+        let receiver =
+            WebhookReceiver::new(store.clone(), handler.clone()).signing_secret("secret");
+        let signature = sign("secret", PAYLOAD);
+        let tampered = PAYLOAD.replace("5000", "9000");
+
+        for (payload, signature) in [
+            (PAYLOAD, None),
+            (PAYLOAD, Some("not hex")),
+            (PAYLOAD, Some(sign("other", PAYLOAD).as_str())),
+            (tampered.as_str(), Some(signature.as_str())),
+        ] {
+            assert!(matches!(
+                receiver.receive_signed(payload, signature).await,
+                Err(WebhookError::InvalidSignature)
+            ));
+        }
+        assert!(store.webhook(EVENT_ID).await.unwrap().is_none());
+        assert_eq!(handler.calls.load(Ordering::SeqCst), 0);
+
+        let delivery = receiver
+            .receive_signed(PAYLOAD, Some(&signature))
+            .await
+            .unwrap();
+        assert_eq!(delivered(delivery), ("handled", 1));
+    }
+
+    #[tokio::test]
+    async fn memory_store_reports_repeated_deliveries_as_duplicates() {
+        repeated_deliveries_are_duplicates(Arc::new(MemoryStore::new())).await;
+    }
+
+    #[tokio::test]
+    async fn memory_store_reports_a_delivery_being_handled_as_in_progress() {
+        a_delivery_being_handled_is_in_progress(Arc::new(MemoryStore::new())).await;
+    }
+
+    #[tokio::test]
+    async fn memory_store_lets_an_expired_lease_be_taken_over() {
+        an_expired_lease_is_taken_over(Arc::new(MemoryStore::new())).await;
+    }
+
+    #[tokio::test]
+    async fn memory_store_replays_failed_events() {
+        failed_events_are_replayed(Arc::new(MemoryStore::new())).await;
+    }
+
+    #[tokio::test]
+    async fn memory_store_refuses_unsigned_or_tampered_deliveries() {
+        unsigned_or_tampered_deliveries_are_refused(Arc::new(MemoryStore::new())).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_reports_repeated_deliveries_as_duplicates() {
+        repeated_deliveries_are_duplicates(Arc::new(SqliteStore::open_in_memory().unwrap())).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_reports_a_delivery_being_handled_as_in_progress() {
+        a_delivery_being_handled_is_in_progress(Arc::new(SqliteStore::open_in_memory().unwrap()))
+            .await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_lets_an_expired_lease_be_taken_over() {
+        an_expired_lease_is_taken_over(Arc::new(SqliteStore::open_in_memory().unwrap())).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_replays_failed_events() {
+        failed_events_are_replayed(Arc::new(SqliteStore::open_in_memory().unwrap())).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_refuses_unsigned_or_tampered_deliveries() {
+        unsigned_or_tampered_deliveries_are_refused(Arc::new(
+            SqliteStore::open_in_memory().unwrap(),
+        ))
+        .await;
+    }
+
+    #[test]
+    fn events_without_a_txn_ref_are_refused() {
+        let event = WebhookEvent::parse(r#"{"eventType":"deposit.success","data":{}}"#).unwrap();
+        assert!(
+            matches!(event.id(), Err(WebhookError::MissingTxnRef(name)) if name == "deposit.success")
+        );
+        let event =
+            WebhookEvent::parse(r#"{"event":"card.paid","data":{"paymentRef":"C-1"}}"#).unwrap();
+        assert_eq!(event.id().unwrap(), "card.paid:C-1");
+    }
+}
@@ -1,8 +1,150 @@
 use crate::engine::Engine;
-use crate::utils::filter::FilterUtil;
+use crate::error::ZainpayError;
+use crate::models::model::ZainboxInfo;
 use crate::response::Response;
+use crate::secret::Secret;
+use crate::utils::filter::FilterUtil;
 use serde_json::{json, Value};
+use std::collections::BTreeSet;
 use std::error::Error;
+use std::fmt;
+
+/// Desired state of a zainbox, for [`ZainboxService::ensure_zainbox`].
+///
+/// Optional fields left as `None` are not compared nor updated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZainboxSpec {
+    pub name: String,
+    pub email_notification: Secret<String>,
+    pub callback_url: String,
+    pub tags: Option<Vec<String>>,
+    pub description: Option<String>,
+    /// Only zainboxes whose code starts with this prefix are considered the same zainbox.
+    pub code_name_prefix: Option<String>,
+    pub allow_auto_internal_transfer: Option<bool>,
+}
+
+impl ZainboxSpec {
+    pub fn new(name: String, email_notification: String, callback_url: String) -> Self {
+        Self {
+            name,
+            email_notification: Secret::new(email_notification),
+            callback_url,
+            tags: None,
+            description: None,
+            code_name_prefix: None,
+            allow_auto_internal_transfer: None,
+        }
+    }
+
+    /// Whether `zainbox` is the one this spec describes: same name, ignoring case, and code
+    /// starting with the code name prefix if any.
+    pub fn matches(&self, zainbox: &ZainboxInfo) -> bool {
+        zainbox.name.trim().eq_ignore_ascii_case(self.name.trim())
+            && self
+                .code_name_prefix
+                .as_deref()
+                .is_none_or(|prefix| zainbox.codeName.starts_with(prefix))
+    }
+
+    /// Fields of `zainbox` that differ from this spec. A field Zainpay did not return is unknown
+    /// and not counted as differing; tags are compared as a set.
+    pub fn changes(&self, zainbox: &ZainboxInfo) -> Vec<ZainboxField> {
+        let mut changes = Vec::new();
+
+        if zainbox.callbackUrl != self.callback_url {
+            changes.push(ZainboxField::CallbackUrl);
+        }
+        if let Some(email) = &zainbox.emailNotification {
+            if !email
+                .expose()
+                .trim()
+                .eq_ignore_ascii_case(self.email_notification.expose().trim())
+            {
+                changes.push(ZainboxField::EmailNotification);
+            }
+        }
+        if let (Some(wanted), Some(current)) = (&self.tags, &zainbox.tags) {
+            let wanted: BTreeSet<&str> = wanted.iter().map(|tag| tag.trim()).collect();
+            let current: BTreeSet<&str> = current.iter().map(|tag| tag.trim()).collect();
+            if current != wanted {
+                changes.push(ZainboxField::Tags);
+            }
+        }
+        if let (Some(wanted), Some(current)) = (&self.description, &zainbox.description) {
+            if current != wanted {
+                changes.push(ZainboxField::Description);
+            }
+        }
+        if let (Some(wanted), Some(current)) = (
+            self.allow_auto_internal_transfer,
+            zainbox.allowAutoInternalTransfer,
+        ) {
+            if current != wanted {
+                changes.push(ZainboxField::AllowAutoInternalTransfer);
+            }
+        }
+
+        changes
+    }
+
+    /// Whether `zainbox` lacks a field this spec sets, so [`ZainboxSpec::changes`] cannot tell
+    /// whether it differs.
+    fn is_unknown_in(&self, zainbox: &ZainboxInfo) -> bool {
+        zainbox.emailNotification.is_none()
+            || (self.tags.is_some() && zainbox.tags.is_none())
+            || (self.description.is_some() && zainbox.description.is_none())
+            || (self.allow_auto_internal_transfer.is_some()
+                && zainbox.allowAutoInternalTransfer.is_none())
+    }
+}
+
+/// A zainbox setting [`ZainboxService::ensure_zainbox`] can update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ZainboxField {
+    CallbackUrl,
+    EmailNotification,
+    Tags,
+    Description,
+    AllowAutoInternalTransfer,
+}
+
+impl fmt::Display for ZainboxField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ZainboxField::CallbackUrl => "callbackUrl",
+            ZainboxField::EmailNotification => "emailNotification",
+            ZainboxField::Tags => "tags",
+            ZainboxField::Description => "description",
+            ZainboxField::AllowAutoInternalTransfer => "allowAutoInternalTransfer",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum EnsureOutcome {
+    /// No zainbox matched the spec, so one was created.
+    Created(Response),
+
+    /// A matching zainbox existed and the fields in `changes` were updated.
+    Updated {
+        zainbox: ZainboxInfo,
+        changes: Vec<ZainboxField>,
+        response: Response,
+    },
+
+    /// A matching zainbox existed and already agreed with the spec.
+    Unchanged(ZainboxInfo),
+}
+
+impl EnsureOutcome {
+    pub fn changes(&self) -> &[ZainboxField] {
+        match self {
+            EnsureOutcome::Updated { changes, .. } => changes,
+            EnsureOutcome::Created(_) | EnsureOutcome::Unchanged(_) => &[],
+        }
+    }
+}
 
 pub struct ZainboxService {
     engine: Engine,
@@ -57,16 +199,27 @@ impl ZainboxService {
             payload["allowAutoInternalTransfer"] = Value::from(allow_auto_internal_transfer);
         }
 
-        let http_response = self.engine.post("zainbox/create/request", &payload).await?;
-        Ok(Response::new(http_response).await)
+        self.engine
+            .endpoint("zainbox/create/request")
+            .post("zainbox/create/request", &payload)
+            .await
     }
 
     pub async fn list(&self, status: Option<bool>) -> Result<Response, Box<dyn Error>> {
-        let http_response = match status {
-            Some(s) => self.engine.get(&format!("zainbox/list?status={}", s)).await?,
-            None => self.engine.get("zainbox/list").await?,
-        };
-        Ok(Response::new(http_response).await)
+        match status {
+            Some(s) => {
+                self.engine
+                    .endpoint("zainbox/list")
+                    .get(&format!("zainbox/list?status={}", s))
+                    .await
+            }
+            None => {
+                self.engine
+                    .endpoint("zainbox/list")
+                    .get("zainbox/list")
+                    .await
+            }
+        }
     }
 
     /**
@@ -120,8 +273,10 @@ impl ZainboxService {
             payload["status"] = Value::from(status);
         }
 
-        let http_response = self.engine.patch("zainbox/update", &payload).await?;
-        Ok(Response::new(http_response).await)
+        self.engine
+            .endpoint("zainbox/update")
+            .patch("zainbox/update", &payload)
+            .await
     }
 
     /**
@@ -137,11 +292,10 @@ impl ZainboxService {
         &self,
         zainbox_code: String,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/profile/{}")
             .get(&format!("zainbox/profile/{}", zainbox_code))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     /**
@@ -162,8 +316,8 @@ impl ZainboxService {
         date_from: Option<String>,
         date_to: Option<String>,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/transfer/deposit/summary/{}")
             .get(&format!(
                 "zainbox/transfer/deposit/summary/{}?{}",
                 zainbox_code,
@@ -171,8 +325,7 @@ impl ZainboxService {
                     date_from, date_to, None, None, None, None, None, None
                 )
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     /**
@@ -191,16 +344,15 @@ impl ZainboxService {
         date_from: Option<String>,
         date_to: Option<String>,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/transactions/summary")
             .get(&format!(
                 "zainbox/transactions/summary?{}",
                 FilterUtil::construct_filter_params(
                     date_from, date_to, None, None, None, None, None, None
                 )
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     /**
@@ -222,8 +374,8 @@ impl ZainboxService {
         payment_channel: Option<String>,
         account_number: Option<String>,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/transactions/{}/{}")
             .get(&format!(
                 "zainbox/transactions/{}/{}?{}",
                 zainbox_code,
@@ -239,8 +391,7 @@ impl ZainboxService {
                     account_number
                 )
             ))
-            .await?;
-        Ok(Response::new(http_response).await)
+            .await
     }
 
     /**
@@ -260,8 +411,8 @@ impl ZainboxService {
         payment_channel: Option<String>,
         account_number: Option<String>,
     ) -> Result<Response, Box<dyn Error>> {
-        let http_response = self
-            .engine
+        self.engine
+            .endpoint("zainbox/transactions")
             .get(&format!(
                 "zainbox/transactions?count={}&{}",
                 count.unwrap_or(20),
@@ -276,7 +427,257 @@ impl ZainboxService {
                     account_number
                 )
             ))
+            .await
+    }
+
+    /**
+     * Create the zainbox described by a spec, or bring the existing one in line with it.
+     *
+     * The zainbox is looked up by name (and code name prefix) among all zainboxes. When several
+     * match, the first one is used. Its profile is fetched when the list leaves out a field the
+     * spec sets. Only the fields that differ are sent in the update; fields Zainpay returns in
+     * neither are left alone.
+     *
+     * @param ZainboxSpec $spec
+     * @return EnsureOutcome
+     * @throws Box<dyn Error>
+     */
+    pub async fn ensure_zainbox(
+        &self,
+        spec: &ZainboxSpec,
+    ) -> Result<EnsureOutcome, Box<dyn Error>> {
+        let response = self.list(None).await?;
+        if response.has_failed() {
+            return Err(ZainpayError::from_response(&response).into());
+        }
+        let zainboxes = response
+            .parse_data::<Vec<ZainboxInfo>>()
+            .ok_or(ZainpayError::UnexpectedData)?;
+
+        let Some(mut zainbox) = zainboxes.into_iter().find(|zainbox| spec.matches(zainbox)) else {
+            let response = self
+                .create(
+                    spec.name.clone(),
+                    spec.email_notification.expose().clone(),
+                    spec.tags.clone(),
+                    spec.callback_url.clone(),
+                    spec.description.clone(),
+                    spec.code_name_prefix.clone(),
+                    spec.allow_auto_internal_transfer,
+                )
+                .await?;
+            if response.has_failed() {
+                return Err(ZainpayError::from_response(&response).into());
+            }
+            return Ok(EnsureOutcome::Created(response));
+        };
+
+        if spec.is_unknown_in(&zainbox) {
+            let response = self.get_zainbox_profile(zainbox.codeName.clone()).await?;
+            if response.has_failed() {
+                return Err(ZainpayError::from_response(&response).into());
+            }
+            zainbox = response
+                .parse_data::<ZainboxInfo>()
+                .ok_or(ZainpayError::UnexpectedData)?;
+        }
+
+        let changes = spec.changes(&zainbox);
+        if changes.is_empty() {
+            return Ok(EnsureOutcome::Unchanged(zainbox));
+        }
+
+        let response = self
+            .update_fields(&zainbox.codeName, &zainbox.name, spec, &changes)
             .await?;
-        Ok(Response::new(http_response).await)
+        if response.has_failed() {
+            return Err(ZainpayError::from_response(&response).into());
+        }
+
+        Ok(EnsureOutcome::Updated {
+            zainbox,
+            changes,
+            response,
+        })
+    }
+
+    /// Update the `changes` fields of a zainbox to their values in `spec`, leaving the others be.
+    pub(crate) async fn update_fields(
+        &self,
+        zainbox_code: &str,
+        name: &str,
+        spec: &ZainboxSpec,
+        changes: &[ZainboxField],
+    ) -> Result<Response, Box<dyn Error>> {
+        let changed = |field: ZainboxField| changes.contains(&field);
+        self.update(
+            name.to_string(),
+            changed(ZainboxField::EmailNotification)
+                .then(|| spec.email_notification.expose().clone()),
+            spec.tags.clone().filter(|_| changed(ZainboxField::Tags)),
+            changed(ZainboxField::CallbackUrl).then(|| spec.callback_url.clone()),
+            spec.description
+                .clone()
+                .filter(|_| changed(ZainboxField::Description)),
+            spec.allow_auto_internal_transfer
+                .filter(|_| changed(ZainboxField::AllowAutoInternalTransfer)),
+            zainbox_code.to_string(),
+            None,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enviroment::Environment;
+    use crate::transport::{HttpResponse, Method, MockTransport};
+    use std::sync::Arc;
+
+    fn spec() -> ZainboxSpec {
+        let mut spec = ZainboxSpec::new(
+            "Oshodi Traders".to_string(),
+            "ops@example.com".to_string(),
+            "https://example.com/zainpay/callback".to_string(),
+        );
+        spec.tags = Some(vec!["lagos".to_string(), "retail".to_string()]);
+        spec
+    }
+
+    fn success(data: Value) -> HttpResponse {
+        let body = json!({ "code": "00", "status": "200 OK", "data": data });
+        HttpResponse::new(200, &body.to_string())
+    }
+
+    fn listed(zainbox: Value) -> MockTransport {
+        MockTransport::new().route(Method::Get, "zainbox/list", success(json!([zainbox])))
+    }
+
+    fn service(transport: &Arc<MockTransport>) -> ZainboxService {
+        ZainboxService::new(
+            Engine::builder(Environment::Sandbox, "key")
+                .transport(transport.clone())
+                .build(),
+        )
+    }
+
+    fn sent(transport: &MockTransport) -> Vec<(Method, String)> {
+        transport
+            .requests()
+            .iter()
+            .map(|request| (request.method, request.path().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn fields_zainpay_did_not_return_are_not_changes() {
+        let zainbox: ZainboxInfo = serde_json::from_value(json!({
+            "name": "Oshodi Traders",
+            "codeName": "THbfnDvK5o",
+            "callbackUrl": "https://example.com/zainpay/callback",
+            "isActive": true,
+        }))
+        .unwrap();
+
+        assert!(spec().changes(&zainbox).is_empty());
+        assert!(spec().is_unknown_in(&zainbox));
+    }
+
+    #[tokio::test]
+    async fn creates_a_missing_zainbox() {
+        let transport = Arc::new(
+            MockTransport::new()
+                .route(Method::Get, "zainbox/list", success(json!([])))
+                .route(
+                    Method::Post,
+                    "zainbox/create/request",
+                    success(json!({ "codeName": "THbfnDvK5o" })),
+                ),
+        );
+
+        let outcome = service(&transport).ensure_zainbox(&spec()).await.unwrap();
+
+        assert!(matches!(outcome, EnsureOutcome::Created(_)));
+        assert_eq!(
+            sent(&transport),
+            [
+                (Method::Get, "zainbox/list".to_string()),
+                (Method::Post, "zainbox/create/request".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_a_matching_zainbox_alone_once_its_profile_agrees() {
+        let transport = Arc::new(
+            listed(json!({
+                "name": "oshodi traders",
+                "codeName": "THbfnDvK5o",
+                "callbackUrl": "https://example.com/zainpay/callback",
+                "isActive": true,
+            }))
+            .route(
+                Method::Get,
+                "zainbox/profile/THbfnDvK5o",
+                success(json!({
+                    "name": "Oshodi Traders",
+                    "codeName": "THbfnDvK5o",
+                    "callbackUrl": "https://example.com/zainpay/callback",
+                    "emailNotification": "OPS@example.com",
+                    "tags": "retail, lagos",
+                    "isActive": true,
+                })),
+            ),
+        );
+
+        let outcome = service(&transport).ensure_zainbox(&spec()).await.unwrap();
+
+        assert!(
+            matches!(outcome, EnsureOutcome::Unchanged(_)),
+            "{:?}",
+            outcome
+        );
+        assert_eq!(
+            sent(&transport),
+            [
+                (Method::Get, "zainbox/list".to_string()),
+                (Method::Get, "zainbox/profile/THbfnDvK5o".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn updates_only_the_fields_that_differ() {
+        let transport = Arc::new(
+            listed(json!({
+                "name": "Oshodi Traders",
+                "codeName": "THbfnDvK5o",
+                "callbackUrl": "https://example.com/old",
+                "emailNotification": "ops@example.com",
+                "tags": ["retail"],
+                "isActive": true,
+            }))
+            .route(Method::Patch, "zainbox/update", success(json!({}))),
+        );
+
+        let outcome = service(&transport).ensure_zainbox(&spec()).await.unwrap();
+
+        assert_eq!(
+            outcome.changes(),
+            [ZainboxField::CallbackUrl, ZainboxField::Tags]
+        );
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2, "the list returned every field");
+        let payload: Value = serde_json::from_str(requests[1].body.as_deref().unwrap()).unwrap();
+        assert_eq!(
+            payload,
+            json!({
+                "codeName": "THbfnDvK5o",
+                "name": "Oshodi Traders",
+                "callbackUrl": "https://example.com/zainpay/callback",
+                "tags": "lagos,retail",
+            })
+        );
     }
 }
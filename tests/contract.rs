@@ -0,0 +1,815 @@
+//! Contract tests against the fixture corpus in `tests/fixtures`.
+//!
+//! Every endpoint has a success and an error body, recorded as Zainpay sends them. Each endpoint is
+//! called against a mock transport answering with its fixtures, and the responses that have a typed
+//! model must parse into it and survive a serialize/deserialize round trip. When Zainpay changes a
+//! response shape, update the fixture and these tests show what the change breaks.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt::Debug;
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use zainpay::bank::BankService;
+use zainpay::card::CardService;
+use zainpay::engine::Engine;
+use zainpay::enviroment::Environment;
+use zainpay::error::{ErrorCode, ZainpayError};
+use zainpay::models::model::{
+    SettlementAccount, SettlementInfo, Transaction, VirtualAccountBalance, VirtualAccountInfo,
+    ZainboxInfo,
+};
+use zainpay::response::Response;
+use zainpay::settlement::SettlementService;
+use zainpay::transport::{HttpResponse, Method, MockTransport};
+use zainpay::virtual_account::CardService as VirtualAccountService;
+use zainpay::zainbox::ZainboxService;
+
+type Call = fn(Engine) -> Pin<Box<dyn Future<Output = Result<Response, Box<dyn Error>>>>>;
+
+struct Endpoint {
+    service: &'static str,
+    name: &'static str,
+    method: Method,
+    path: &'static str,
+    call: Call,
+}
+
+const ZAINBOX: &str = "THbfnDvK5o";
+const ACCOUNT: &str = "7966884043";
+const TXN_REF: &str = "Z5YKRB8HJ3WQ";
+
+fn endpoints() -> Vec<Endpoint> {
+    vec![
+        Endpoint {
+            service: "bank",
+            name: "get_bank_list",
+            method: Method::Get,
+            path: "bank/list",
+            call: |engine| Box::pin(async move { BankService::new(engine).get_bank_list().await }),
+        },
+        Endpoint {
+            service: "bank",
+            name: "make_account_name_enquiry",
+            method: Method::Get,
+            path: "bank/name-enquiry",
+            call: |engine| {
+                Box::pin(async move {
+                    BankService::new(engine)
+                        .make_account_name_enquiry("000013".into(), "0123456789".into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "bank",
+            name: "make_fund_transfer",
+            method: Method::Post,
+            path: "bank/transfer",
+            call: |engine| {
+                Box::pin(async move {
+                    BankService::new(engine)
+                        .make_fund_transfer(
+                            "0123456789".into(),
+                            "000013".into(),
+                            "100000".into(),
+                            ACCOUNT.into(),
+                            "0017".into(),
+                            ZAINBOX.into(),
+                            "TRF-20240304-0007".into(),
+                            "Supplier payout".into(),
+                            None,
+                        )
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "bank",
+            name: "verify_transfer",
+            method: Method::Get,
+            path: "virtual-account/wallet/transaction/verify/TRF-20240304-0007",
+            call: |engine| {
+                Box::pin(async move {
+                    BankService::new(engine)
+                        .verify_transfer("TRF-20240304-0007".into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "bank",
+            name: "verify_deposit",
+            method: Method::Get,
+            path: "virtual-account/wallet/transaction/deposit/verify/Z5YKRB8HJ3WQ",
+            call: |engine| {
+                Box::pin(async move {
+                    BankService::new(engine)
+                        .verify_deposit(TXN_REF.into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "bank",
+            name: "verify_deposit_v2",
+            method: Method::Get,
+            path: "virtual-account/wallet/transaction/deposit/verify/v2/Z5YKRB8HJ3WQ",
+            call: |engine| {
+                Box::pin(async move {
+                    BankService::new(engine)
+                        .verify_deposit_v2(TXN_REF.into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "bank",
+            name: "repush_deposit_event",
+            method: Method::Get,
+            path: "zainbox/repush/deposit/Z5YKRB8HJ3WQ",
+            call: |engine| {
+                Box::pin(async move {
+                    BankService::new(engine)
+                        .repush_deposit_event(TXN_REF.into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "bank",
+            name: "reconcile_bank_deposit",
+            method: Method::Patch,
+            path: "virtual-account/wallet/transaction/reconcile/bank-deposit",
+            call: |engine| {
+                Box::pin(async move {
+                    BankService::new(engine)
+                        .reconcile_bank_deposit(
+                            "depositAccountNumber".into(),
+                            "wemaBank".into(),
+                            ACCOUNT.into(),
+                            Some("000013240304102133000000000001".into()),
+                        )
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "card",
+            name: "initialize_new_payment",
+            method: Method::Post,
+            path: "zainbox/card/initialize/payment",
+            call: |engine| {
+                Box::pin(async move {
+                    CardService::new(engine)
+                        .initialize_new_payment(
+                            "500000".into(),
+                            "CARD-8841".into(),
+                            "customer@example.com".into(),
+                            "08000000000".into(),
+                            ZAINBOX.into(),
+                            "https://example.com/zainpay/callback".into(),
+                        )
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "card",
+            name: "verify_card_payment",
+            method: Method::Get,
+            path: "virtual-account/wallet/deposit/verify/CARD-8841",
+            call: |engine| {
+                Box::pin(async move {
+                    CardService::new(engine)
+                        .verify_card_payment("CARD-8841".into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "card",
+            name: "verify_card_payment_v2",
+            method: Method::Get,
+            path: "virtual-account/wallet/deposit/verify/v2/CARD-8841",
+            call: |engine| {
+                Box::pin(async move {
+                    CardService::new(engine)
+                        .verify_card_payment_v2("CARD-8841".into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "card",
+            name: "reconcile_card_payment",
+            method: Method::Get,
+            path: "virtual-account/wallet/transaction/reconcile/card-payment",
+            call: |engine| {
+                Box::pin(async move {
+                    CardService::new(engine)
+                        .reconcile_card_payment("CARD-8841".into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "card",
+            name: "get_zainbox_card_payment_txn_history",
+            method: Method::Get,
+            path: "zainbox/card/transactions/THbfnDvK5o",
+            call: |engine| {
+                Box::pin(async move {
+                    CardService::new(engine)
+                        .get_zainbox_card_payment_txn_history(
+                            ZAINBOX.into(),
+                            None,
+                            None,
+                            None,
+                            None,
+                            None,
+                            None,
+                        )
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "virtual_account",
+            name: "create",
+            method: Method::Post,
+            path: "virtual-account/create/request",
+            call: |engine| {
+                Box::pin(async move {
+                    VirtualAccountService::new(engine)
+                        .create(
+                            "wemaBank".into(),
+                            "22222222222".into(),
+                            "Ada".into(),
+                            "Obi".into(),
+                            "ada@example.com".into(),
+                            "08000000000".into(),
+                            "01-01-1990".into(),
+                            "F".into(),
+                            "1 Marina, Lagos".into(),
+                            "Ms".into(),
+                            "Lagos".into(),
+                            ZAINBOX.into(),
+                        )
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "virtual_account",
+            name: "get_virtual_account_balance",
+            method: Method::Get,
+            path: "virtual-account/wallet/balance/7966884043",
+            call: |engine| {
+                Box::pin(async move {
+                    VirtualAccountService::new(engine)
+                        .get_virtual_account_balance(ACCOUNT.into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "virtual_account",
+            name: "get_all_virtual_accounts_balance_for_zainbox",
+            method: Method::Get,
+            path: "zainbox/accounts/balance/THbfnDvK5o",
+            call: |engine| {
+                Box::pin(async move {
+                    VirtualAccountService::new(engine)
+                        .get_all_virtual_accounts_balance_for_zainbox(ZAINBOX.into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "virtual_account",
+            name: "change_virtual_account_status",
+            method: Method::Patch,
+            path: "virtual-account/change/account/status",
+            call: |engine| {
+                Box::pin(async move {
+                    VirtualAccountService::new(engine)
+                        .change_virtual_account_status(ZAINBOX.into(), ACCOUNT.into(), false)
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "virtual_account",
+            name: "get_all_virtual_accounts_for_zainbox",
+            method: Method::Get,
+            path: "zainbox/virtual-accounts/THbfnDvK5o",
+            call: |engine| {
+                Box::pin(async move {
+                    VirtualAccountService::new(engine)
+                        .get_all_virtual_accounts_for_zainbox(ZAINBOX.into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "virtual_account",
+            name: "get_virtual_account_txn_history",
+            method: Method::Get,
+            path: "virtual-account/wallet/transactions/7966884043/20",
+            call: |engine| {
+                Box::pin(async move {
+                    VirtualAccountService::new(engine)
+                        .get_virtual_account_txn_history(
+                            ACCOUNT.into(),
+                            None,
+                            None,
+                            None,
+                            None,
+                            None,
+                        )
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "zainbox",
+            name: "create",
+            method: Method::Post,
+            path: "zainbox/create/request",
+            call: |engine| {
+                Box::pin(async move {
+                    ZainboxService::new(engine)
+                        .create(
+                            "Oshodi Traders".into(),
+                            "ops@example.com".into(),
+                            Some(vec!["retail".into(), "lagos".into()]),
+                            "https://example.com/zainpay/callback".into(),
+                            Some("Collections for the Oshodi branch".into()),
+                            None,
+                            Some(false),
+                        )
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "zainbox",
+            name: "list",
+            method: Method::Get,
+            path: "zainbox/list",
+            call: |engine| Box::pin(async move { ZainboxService::new(engine).list(None).await }),
+        },
+        Endpoint {
+            service: "zainbox",
+            name: "update",
+            method: Method::Patch,
+            path: "zainbox/update",
+            call: |engine| {
+                Box::pin(async move {
+                    ZainboxService::new(engine)
+                        .update(
+                            "Oshodi Traders".into(),
+                            None,
+                            None,
+                            Some("https://example.com/zainpay/v2/callback".into()),
+                            None,
+                            None,
+                            ZAINBOX.into(),
+                            None,
+                        )
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "zainbox",
+            name: "get_zainbox_profile",
+            method: Method::Get,
+            path: "zainbox/profile/THbfnDvK5o",
+            call: |engine| {
+                Box::pin(async move {
+                    ZainboxService::new(engine)
+                        .get_zainbox_profile(ZAINBOX.into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "zainbox",
+            name: "get_total_payment_collected_by_zainbox",
+            method: Method::Get,
+            path: "zainbox/transfer/deposit/summary/THbfnDvK5o",
+            call: |engine| {
+                Box::pin(async move {
+                    ZainboxService::new(engine)
+                        .get_total_payment_collected_by_zainbox(ZAINBOX.into(), None, None)
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "zainbox",
+            name: "get_total_payment_collected_for_all_zainboxes",
+            method: Method::Get,
+            path: "zainbox/transactions/summary",
+            call: |engine| {
+                Box::pin(async move {
+                    ZainboxService::new(engine)
+                        .get_total_payment_collected_for_all_zainboxes(None, None)
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "zainbox",
+            name: "get_zainbox_txn_history",
+            method: Method::Get,
+            path: "zainbox/transactions/THbfnDvK5o/20",
+            call: |engine| {
+                Box::pin(async move {
+                    ZainboxService::new(engine)
+                        .get_zainbox_txn_history(ZAINBOX.into(), None, None, None, None, None, None)
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "zainbox",
+            name: "get_all_zainboxes_txn_history",
+            method: Method::Get,
+            path: "zainbox/transactions",
+            call: |engine| {
+                Box::pin(async move {
+                    ZainboxService::new(engine)
+                        .get_all_zainboxes_txn_history(None, None, None, None, None, None)
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "settlement",
+            name: "create_or_update_zainbox_settlemet",
+            method: Method::Post,
+            path: "zainbox/settlement",
+            call: |engine| {
+                Box::pin(async move {
+                    SettlementService::new(engine)
+                        .create_or_update_zainbox_settlemet(
+                            "Daily sweep".into(),
+                            ZAINBOX.into(),
+                            "T1".into(),
+                            "Daily".into(),
+                            vec![
+                                SettlementAccount::new("0123456789".into(), "000013".into(), 70.0),
+                                SettlementAccount::new("0234567890".into(), "000014".into(), 30.0),
+                            ],
+                            true,
+                        )
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "settlement",
+            name: "get_settlement_info_for_zainbox",
+            method: Method::Get,
+            path: "zainbox/settlement",
+            call: |engine| {
+                Box::pin(async move {
+                    SettlementService::new(engine)
+                        .get_settlement_info_for_zainbox(ZAINBOX.into())
+                        .await
+                })
+            },
+        },
+        Endpoint {
+            service: "settlement",
+            name: "get_settlement_payment_history_for_zainbox",
+            method: Method::Get,
+            path: "zainbox/settlement/history/THbfnDvK5o",
+            call: |engine| {
+                Box::pin(async move {
+                    SettlementService::new(engine)
+                        .get_settlement_payment_history_for_zainbox(
+                            ZAINBOX.into(),
+                            None,
+                            None,
+                            None,
+                            None,
+                        )
+                        .await
+                })
+            },
+        },
+    ]
+}
+
+fn fixture_path(service: &str, name: &str, outcome: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(service)
+        .join(format!("{}.{}.json", name, outcome))
+}
+
+fn fixture(service: &str, name: &str, outcome: &str) -> String {
+    let path = fixture_path(service, name, outcome);
+    fs::read_to_string(&path).unwrap_or_else(|error| panic!("{}: {}", path.display(), error))
+}
+
+/// The HTTP status a fixture was recorded with, taken from its `status`, e.g. `404 Not Found`.
+fn http_status(body: &str) -> u16 {
+    let body: Value = serde_json::from_str(body).expect("fixture is JSON");
+    body["status"]
+        .as_str()
+        .and_then(|status| status.split_whitespace().next())
+        .and_then(|status| status.parse().ok())
+        .expect("fixture has a `status` starting with the HTTP status")
+}
+
+/// Call `endpoint` against a transport answering with its `outcome` fixture.
+async fn call(endpoint: &Endpoint, outcome: &str) -> Response {
+    let body = fixture(endpoint.service, endpoint.name, outcome);
+    let transport = Arc::new(MockTransport::new().route(
+        endpoint.method,
+        endpoint.path,
+        HttpResponse::new(http_status(&body), &body),
+    ));
+    let engine = Engine::builder(Environment::Sandbox, "contract-test-key")
+        .transport(transport.clone())
+        .build();
+
+    let response = (endpoint.call)(engine)
+        .await
+        .unwrap_or_else(|error| panic!("{}/{}: {}", endpoint.service, endpoint.name, error));
+
+    let requests = transport.requests();
+    assert_eq!(
+        requests.len(),
+        1,
+        "{}/{} sent {} requests",
+        endpoint.service,
+        endpoint.name,
+        requests.len()
+    );
+    assert_eq!(requests[0].method, endpoint.method, "{}", endpoint.name);
+    assert_eq!(
+        requests[0].path().split('?').next(),
+        Some(endpoint.path),
+        "{}/{}",
+        endpoint.service,
+        endpoint.name
+    );
+    response
+}
+
+async fn response_of(service: &str, name: &str) -> Response {
+    let endpoint = endpoints()
+        .into_iter()
+        .find(|endpoint| endpoint.service == service && endpoint.name == name)
+        .unwrap_or_else(|| panic!("no endpoint {}/{}", service, name));
+    call(&endpoint, "success").await
+}
+
+/// Parse the `data` of `response` into `T`, then check serializing and parsing it again gives the
+/// same value.
+fn assert_round_trip<T>(response: &Response, what: &str) -> T
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let data = response
+        .get_raw_data()
+        .unwrap_or_else(|| panic!("{} has no data", what));
+    let parsed: T = serde_json::from_value(data.clone())
+        .unwrap_or_else(|error| panic!("{} does not parse: {}", what, error));
+    assert_eq!(
+        response.parse_data::<T>().as_ref(),
+        Some(&parsed),
+        "{}",
+        what
+    );
+
+    let serialized = serde_json::to_value(&parsed).expect("model serializes");
+    let reparsed: T = serde_json::from_value(serialized)
+        .unwrap_or_else(|error| panic!("{} does not parse after a round trip: {}", what, error));
+    assert_eq!(reparsed, parsed, "{}", what);
+    parsed
+}
+
+#[test]
+fn every_endpoint_has_fixtures_with_the_envelope() {
+    for endpoint in endpoints() {
+        for outcome in ["success", "error"] {
+            let body: Value =
+                serde_json::from_str(&fixture(endpoint.service, endpoint.name, outcome))
+                    .unwrap_or_else(|error| {
+                        panic!("{}/{}: {}", endpoint.service, endpoint.name, error)
+                    });
+            for key in ["code", "description", "status"] {
+                assert!(
+                    body[key].is_string(),
+                    "{}/{}.{} has no `{}`",
+                    endpoint.service,
+                    endpoint.name,
+                    outcome,
+                    key
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn every_fixture_belongs_to_an_endpoint() {
+    let endpoints = endpoints();
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for service in fs::read_dir(&root).expect("fixtures directory") {
+        let service = service.expect("fixture service directory").path();
+        for file in fs::read_dir(&service).expect("fixture files") {
+            let file = file.expect("fixture file").path();
+            let file_name = file
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default();
+            let known = endpoints.iter().any(|endpoint| {
+                service.ends_with(endpoint.service)
+                    && (file_name == format!("{}.success.json", endpoint.name)
+                        || file_name == format!("{}.error.json", endpoint.name))
+            });
+            assert!(known, "{} is not a fixture of any endpoint", file.display());
+        }
+    }
+}
+
+#[tokio::test]
+async fn success_fixtures_succeed() {
+    for endpoint in endpoints() {
+        let response = call(&endpoint, "success").await;
+        assert!(
+            response.has_succeeded(),
+            "{}/{} did not succeed",
+            endpoint.service,
+            endpoint.name
+        );
+        assert_eq!(response.get_status_code(), 200);
+    }
+}
+
+#[tokio::test]
+async fn error_fixtures_fail_with_their_code_and_description() {
+    for endpoint in endpoints() {
+        let body: Value =
+            serde_json::from_str(&fixture(endpoint.service, endpoint.name, "error")).unwrap();
+        let response = call(&endpoint, "error").await;
+        assert!(
+            response.has_failed(),
+            "{}/{} did not fail",
+            endpoint.service,
+            endpoint.name
+        );
+
+        match ZainpayError::from_response(&response) {
+            ZainpayError::Api {
+                status_code,
+                code,
+                description,
+            } => {
+                assert_eq!(status_code, http_status(&body.to_string()));
+                assert_eq!(code, body["code"].as_str().map(ErrorCode::parse));
+                assert!(!code.unwrap().is_accepted());
+                assert_eq!(description.as_deref(), body["description"].as_str());
+            }
+            other => panic!(
+                "{}/{}: unexpected error {:?}",
+                endpoint.service, endpoint.name, other
+            ),
+        }
+    }
+}
+
+#[tokio::test]
+async fn error_codes_are_neither_accepted_nor_pending() {
+    for endpoint in endpoints() {
+        let response = call(&endpoint, "error").await;
+        let code = response.error_code().expect("error fixtures carry a code");
+        assert!(
+            !code.is_accepted() && !code.is_pending(),
+            "{}/{} error code {} reads as accepted or pending",
+            endpoint.service,
+            endpoint.name,
+            code
+        );
+    }
+
+    let response = response_of("bank", "make_fund_transfer").await;
+    assert_eq!(
+        response.error_code(),
+        Some(ErrorCode::Successful("00".to_string()))
+    );
+
+    let response = response_of("zainbox", "list").await;
+    assert_eq!(
+        response.error_code(),
+        Some(ErrorCode::Successful("00".to_string()))
+    );
+
+    let endpoint = endpoints()
+        .into_iter()
+        .find(|endpoint| endpoint.service == "zainbox" && endpoint.name == "list")
+        .unwrap();
+    let error = ZainpayError::from_response(&call(&endpoint, "error").await);
+    assert_eq!(error.error_code(), Some(&ErrorCode::StatusUnknown));
+    assert!(!error.is_retryable());
+
+    let endpoint = endpoints()
+        .into_iter()
+        .find(|endpoint| endpoint.name == "make_fund_transfer")
+        .unwrap();
+    let error = ZainpayError::from_response(&call(&endpoint, "error").await);
+    assert_eq!(error.error_code(), Some(&ErrorCode::DuplicateReference));
+    assert!(!error.is_retryable());
+}
+
+#[tokio::test]
+async fn zainbox_models_round_trip() {
+    let response = response_of("zainbox", "list").await;
+    let zainboxes: Vec<ZainboxInfo> = assert_round_trip(&response, "zainbox/list");
+    assert_eq!(zainboxes.len(), 2);
+    assert_eq!(zainboxes[0].codeName, ZAINBOX);
+    assert_eq!(
+        zainboxes[0].tags,
+        Some(vec!["retail".to_string(), "lagos".to_string()])
+    );
+    assert_eq!(zainboxes[1].tags, Some(vec!["retail".to_string()]));
+    assert_eq!(zainboxes[1].description, None);
+
+    for name in ["create", "get_zainbox_profile"] {
+        let response = response_of("zainbox", name).await;
+        let zainbox: ZainboxInfo = assert_round_trip(&response, name);
+        assert_eq!(zainbox, zainboxes[0]);
+    }
+}
+
+#[tokio::test]
+async fn transaction_history_models_round_trip() {
+    for (service, name) in [
+        ("zainbox", "get_zainbox_txn_history"),
+        ("zainbox", "get_all_zainboxes_txn_history"),
+        ("virtual_account", "get_virtual_account_txn_history"),
+        ("card", "get_zainbox_card_payment_txn_history"),
+        ("settlement", "get_settlement_payment_history_for_zainbox"),
+    ] {
+        let response = response_of(service, name).await;
+        let transactions: Vec<Transaction> = assert_round_trip(&response, name);
+        assert!(!transactions.is_empty(), "{} is empty", name);
+        assert!(transactions
+            .iter()
+            .all(|transaction| transaction.date().is_some()));
+    }
+
+    let response = response_of("zainbox", "get_zainbox_txn_history").await;
+    let transactions: Vec<Transaction> = response.parse_data().unwrap();
+    assert_eq!(transactions[0].amount, 250000);
+    assert_eq!(transactions[0].balance, Some(1250000));
+    assert!(transactions[0].is_deposit());
+}
+
+#[tokio::test]
+async fn settlement_info_round_trips() {
+    let response = response_of("settlement", "get_settlement_info_for_zainbox").await;
+    let settlement: SettlementInfo = assert_round_trip(&response, "settlement info");
+    let percentages: Vec<f64> = settlement
+        .settlement_account_list
+        .iter()
+        .map(|account| account.percentage)
+        .collect();
+    assert_eq!(percentages, vec![70.0, 30.0]);
+    assert_eq!(settlement.status, Some(true));
+}
+
+#[tokio::test]
+async fn virtual_account_models_round_trip() {
+    let response = response_of("virtual_account", "get_all_virtual_accounts_for_zainbox").await;
+    let accounts: Vec<VirtualAccountInfo> = assert_round_trip(&response, "virtual accounts");
+    assert_eq!(accounts.len(), 2);
+    assert_eq!(accounts[0].account_number.expose(), ACCOUNT);
+    assert_eq!(accounts[0].bank_type.as_deref(), Some("wemaBank"));
+
+    let response = response_of("virtual_account", "get_virtual_account_balance").await;
+    let balance: VirtualAccountBalance = assert_round_trip(&response, "balance");
+    assert_eq!(balance.balance_amount, 1145000);
+
+    let response = response_of(
+        "virtual_account",
+        "get_all_virtual_accounts_balance_for_zainbox",
+    )
+    .await;
+    let balances: Vec<VirtualAccountBalance> = assert_round_trip(&response, "zainbox balances");
+    assert_eq!(balances[0], balance);
+    assert_eq!(balances[1].balance_amount, 0);
+}